@@ -0,0 +1,180 @@
+//! Attribute macros for [`trybox`](https://docs.rs/trybox),
+//! re-exported from there with the `"macros"` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{quote, ToTokens as _};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input, parse_quote,
+    visit_mut::{self, VisitMut},
+    Expr, ExprCall, ExprClosure, ExprPath, Ident, Item, ItemFn, Macro, Path, ReturnType, Token,
+};
+
+/// Rewrite `Box::new(e)` into `trybox::boxed!(e)?` within a function,
+/// to help migrate to fallible allocation.
+///
+/// The function must return a [`Result`] whose error type implements
+/// `From<trybox::ErrorWith<T>>`, e.g [`trybox::Error`](https://docs.rs/trybox/latest/trybox/struct.Error.html).
+///
+/// ```
+/// #[trybox::fallible]
+/// fn pair(a: u8, b: u8) -> Result<(Box<u8>, Box<u8>), trybox::Error> {
+///     Ok((Box::new(a), Box::new(b)))
+/// }
+/// ```
+///
+/// Closures, `async` blocks, and nested items are left alone,
+/// as `?` would apply to them rather than the function.
+///
+/// With `#[fallible(deny)]`, other infallible allocations which can't be
+/// rewritten are compile errors:
+/// `vec!`, `format!`, and `String::from`.
+///
+/// ```compile_fail
+/// #[trybox::fallible(deny)]
+/// fn zeroes() -> Result<Vec<u8>, trybox::Error> {
+///     Ok(vec![0; 1024])
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn fallible(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let Args { deny } = parse_macro_input!(attr as Args);
+    let mut item = parse_macro_input!(item as ItemFn);
+    let mut rewriter = Rewriter {
+        deny,
+        errors: Vec::new(),
+    };
+    rewriter.visit_block_mut(&mut item.block);
+    let errors = rewriter
+        .errors
+        .into_iter()
+        .map(|it| it.into_compile_error());
+    quote!(#(#errors)* #item).into()
+}
+
+/// Define a test which runs repeatedly, failing each allocation in turn,
+/// with [`trybox::testing::oom_test`](https://docs.rs/trybox/latest/trybox/testing/fn.oom_test.html).
+///
+/// This requires `trybox`'s `"testing"` feature,
+/// and its `FailOrFallback` allocator to be the global allocator.
+///
+/// ```
+/// # use trybox::testing::FailOrFallback;
+/// # #[global_allocator]
+/// # static ALLOC: FailOrFallback<std::alloc::System> = FailOrFallback::system();
+/// #[trybox::oom_test]
+/// fn pair() {
+///     if let (Ok(a), Ok(b)) = (trybox::or_drop(1), trybox::or_drop(2)) {
+///         assert_eq!(*a + *b, 3);
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// The test body is run several times, so must return `()`.
+#[proc_macro_attribute]
+pub fn oom_test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        let attr = proc_macro2::TokenStream::from(attr);
+        return syn::Error::new_spanned(attr, "expected no arguments")
+            .into_compile_error()
+            .into();
+    }
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = parse_macro_input!(item as ItemFn);
+    if let ReturnType::Type(_, ty) = &sig.output {
+        return syn::Error::new_spanned(ty, "`oom_test` functions must return `()`")
+            .into_compile_error()
+            .into();
+    }
+    quote! {
+        #[test]
+        #(#attrs)*
+        #vis #sig {
+            ::trybox::testing::oom_test(|| #block)
+        }
+    }
+    .into()
+}
+
+struct Args {
+    deny: bool,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.is_empty() {
+            return Ok(Args { deny: false });
+        }
+        let ident = input.parse::<Ident>()?;
+        if ident != "deny" {
+            return Err(syn::Error::new(ident.span(), "expected `deny`"));
+        }
+        let _ = input.parse::<Option<Token![,]>>()?;
+        Ok(Args { deny: true })
+    }
+}
+
+struct Rewriter {
+    deny: bool,
+    errors: Vec<syn::Error>,
+}
+
+impl VisitMut for Rewriter {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        visit_mut::visit_expr_mut(self, expr);
+        if let Expr::Call(ExprCall { func, args, .. }) = expr {
+            if let Expr::Path(ExprPath { path, .. }) = &**func {
+                if is_path(path, "Box", "new") && args.len() == 1 {
+                    let arg = &args[0];
+                    *expr = parse_quote!(::trybox::boxed!(#arg)?);
+                } else if self.deny && is_path(path, "String", "from") {
+                    self.deny_at(path.to_token_stream(), "String::from");
+                }
+            }
+        }
+    }
+    fn visit_macro_mut(&mut self, mac: &mut Macro) {
+        if !self.deny {
+            return;
+        }
+        if let Some(last) = mac.path.segments.last() {
+            if last.ident == "vec" || last.ident == "format" {
+                let name = format!("{}!", last.ident);
+                self.deny_at(mac.path.to_token_stream(), &name);
+            }
+        }
+    }
+    // `?` in these wouldn't apply to the annotated function.
+    fn visit_expr_closure_mut(&mut self, _: &mut ExprClosure) {}
+    fn visit_expr_async_mut(&mut self, _: &mut syn::ExprAsync) {}
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}
+
+impl Rewriter {
+    fn deny_at(&mut self, tokens: proc_macro2::TokenStream, name: &str) {
+        let span = tokens
+            .into_iter()
+            .next()
+            .map(|it| it.span())
+            .unwrap_or_else(Span::call_site);
+        self.errors.push(syn::Error::new(
+            span,
+            format!("`{name}` allocates infallibly, see the `trybox` crate for alternatives"),
+        ));
+    }
+}
+
+/// Whether `path` ends with `ty::method`, e.g `std::boxed::Box::new`.
+fn is_path(path: &Path, ty: &str, method: &str) -> bool {
+    let mut rev = path.segments.iter().rev();
+    match (rev.next(), rev.next()) {
+        (Some(m), Some(t)) => m.ident == method && t.ident == ty && t.arguments.is_empty(),
+        _ => false,
+    }
+}
@@ -0,0 +1,109 @@
+//! Values with a larger alignment than their type requires.
+//!
+//! ```
+//! let boxed = trybox::new_aligned::<4096, _>([0u8; 64]).unwrap();
+//! assert_eq!(&*boxed as *const _ as usize % 4096, 0);
+//! ```
+
+use core::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+/// A `T`, stored at an address which is a multiple of `ALIGN`.
+///
+/// `ALIGN` must be a power of two no greater than `1 << 29`,
+/// and may be less than the alignment of `T`, in which case it has no effect.
+///
+/// Since the alignment is part of the type, a [`Box`] of
+/// this will be freed with the correct [`Layout`](core::alloc::Layout).
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct Aligned<T, const ALIGN: usize>
+where
+    Alignment<ALIGN>: Supported,
+{
+    _align: [<Alignment<ALIGN> as Supported>::Marker; 0],
+    value: T,
+}
+
+impl<T, const ALIGN: usize> Aligned<T, ALIGN>
+where
+    Alignment<ALIGN>: Supported,
+{
+    /// Wrap `value`, aligning it to `ALIGN`.
+    pub const fn new(value: T) -> Self {
+        Self { _align: [], value }
+    }
+    /// Unwrap the value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T, const ALIGN: usize> Deref for Aligned<T, ALIGN>
+where
+    Alignment<ALIGN>: Supported,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T, const ALIGN: usize> DerefMut for Aligned<T, ALIGN>
+where
+    Alignment<ALIGN>: Supported,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.value
+    }
+}
+
+impl<T: fmt::Debug, const ALIGN: usize> fmt::Debug for Aligned<T, ALIGN>
+where
+    Alignment<ALIGN>: Supported,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// A type-level alignment, see [`Aligned`].
+pub struct Alignment<const ALIGN: usize>;
+
+/// Implemented for every [`Alignment`] that [`Aligned`] supports.
+///
+/// This trait is sealed.
+pub trait Supported: sealed::Sealed {
+    #[doc(hidden)]
+    type Marker: Clone + Copy + Default + PartialEq + Eq + core::hash::Hash;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+macro_rules! supported {
+    ($($marker:ident = $align:literal),* $(,)?) => {
+        $(
+            #[doc(hidden)]
+            #[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+            #[repr(align($align))]
+            pub struct $marker;
+            impl sealed::Sealed for Alignment<$align> {}
+            impl Supported for Alignment<$align> {
+                type Marker = $marker;
+            }
+        )*
+    };
+}
+
+supported! {
+    A0 = 1, A1 = 2, A2 = 4, A3 = 8, A4 = 16, A5 = 32, A6 = 64, A7 = 128,
+    A8 = 256, A9 = 512, A10 = 1024, A11 = 2048, A12 = 4096, A13 = 8192,
+    A14 = 16384, A15 = 32768, A16 = 65536, A17 = 131072, A18 = 262144,
+    A19 = 524288, A20 = 1048576, A21 = 2097152, A22 = 4194304, A23 = 8388608,
+    A24 = 16777216, A25 = 33554432, A26 = 67108864, A27 = 134217728,
+    A28 = 268435456, A29 = 536870912,
+}
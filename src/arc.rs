@@ -0,0 +1,84 @@
+//! Fallibly allocated [`Arc`]s, with the `"nightly"` feature.
+//!
+//! ```
+//! let shared = trybox::arc::pin(1)?;
+//! assert_eq!(*shared, 1);
+//! # Ok::<_, trybox::ErrorWith<i32>>(())
+//! ```
+//!
+//! ```
+//! let mut config = trybox::arc::new(vec![1, 2])?;
+//! let snapshot = config.clone();
+//! trybox::arc::try_make_mut(&mut config)?.push(3);
+//! assert_eq!(*snapshot, [1, 2]);
+//! assert_eq!(*config, [1, 2, 3]);
+//! # Ok::<_, trybox::Error>(())
+//! ```
+//!
+//! The allocation is made by [`Arc`] itself,
+//! so doesn't count towards a [`limit`](crate::limit),
+//! or call the [`pressure`](crate::pressure) callbacks.
+//! Failures are still reported, like [`new`](crate::new).
+
+use alloc::{alloc::Layout, sync::Arc};
+use core::{mem::MaybeUninit, pin::Pin};
+
+use crate::{hook, repr::Repr, Error, ErrorWith};
+
+/// Attempt to move `x` to a new [`Arc`],
+/// returning a wrapped `x` on failure.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn new<T>(x: T) -> Result<Arc<T>, ErrorWith<T>> {
+    match Arc::<T>::try_new_uninit() {
+        Ok(arc) => {
+            hook::success(Layout::new::<T>());
+            let ptr = Arc::into_raw(arc) as *mut MaybeUninit<T>;
+            // SAFETY:
+            // - the `Arc` is unique, so we may write through its pointer.
+            // - the pointer is from `Arc::into_raw` of a `MaybeUninit<T>`,
+            //   which has the same size and alignment as a `T`.
+            // - we've written an initialized `T`.
+            unsafe {
+                (*ptr).write(x);
+                Ok(Arc::from_raw(ptr as *const T))
+            }
+        }
+        Err(_) => {
+            hook::failure(Layout::new::<T>());
+            hook::report(Repr::of::<T>());
+            Err(ErrorWith(x))
+        }
+    }
+}
+
+/// Like [`new`], but pin the [`Arc`], like [`Arc::pin`],
+/// e.g for intrusive data structures with shared ownership.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn pin<T>(x: T) -> Result<Pin<Arc<T>>, ErrorWith<T>> {
+    let arc = new(x)?;
+    // SAFETY: the `T` is never moved out of the `Arc`,
+    //         as it is only reachable through the `Pin`.
+    Ok(unsafe { Pin::new_unchecked(arc) })
+}
+
+/// Like [`Arc::make_mut`], but fallibly allocate the new [`Arc`] if `this` is
+/// shared, instead of aborting.
+///
+/// If there are other [`Arc`]s or [`Weak`](alloc::sync::Weak)s to the value,
+/// it is cloned into a new allocation, and `this` is replaced with it.
+/// On failure, `this` is unchanged.
+///
+/// Note that cloning `T` itself may allocate infallibly.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn try_make_mut<T: Clone>(this: &mut Arc<T>) -> Result<&mut T, Error> {
+    if Arc::get_mut(this).is_none() {
+        *this = match new(T::clone(this)) {
+            Ok(it) => it,
+            Err(e) => return Err(e.without_payload()),
+        };
+    }
+    match Arc::get_mut(this) {
+        Some(it) => Ok(it),
+        None => unreachable!("a new `Arc` is unique"),
+    }
+}
@@ -0,0 +1,236 @@
+//! A typed arena, whose chunks are allocated fallibly.
+//!
+//! ```
+//! use trybox::arena::Arena;
+//!
+//! enum Expr<'a> {
+//!     Num(i64),
+//!     Add(&'a Expr<'a>, &'a Expr<'a>),
+//! }
+//!
+//! fn eval(expr: &Expr) -> i64 {
+//!     match expr {
+//!         Expr::Num(n) => *n,
+//!         Expr::Add(l, r) => eval(l) + eval(r),
+//!     }
+//! }
+//!
+//! let arena = Arena::new();
+//! let one = arena.alloc(Expr::Num(1))?;
+//! let two = arena.alloc(Expr::Num(2))?;
+//! let sum = arena.alloc(Expr::Add(one, two))?;
+//! assert_eq!(eval(sum), 3);
+//! assert_eq!(arena.len(), 3);
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use core::{
+    alloc::Layout,
+    cell::Cell,
+    fmt,
+    marker::PhantomData,
+    mem,
+    ptr::{self, NonNull},
+};
+
+use crate::{raw, Error, ErrorKind, ErrorWith};
+
+/// Chunks are initially about this many bytes.
+const FIRST_CHUNK: usize = 4096;
+
+/// Allocates values of type `T`, which live until the arena is dropped.
+///
+/// Values are stored in chunks, allocated through [`raw`],
+/// which double in size.
+/// If a chunk can't be allocated, [`alloc`](Self::alloc) returns the value
+/// rather than aborting, and the arena is left as it was.
+pub struct Arena<T> {
+    /// The most recent chunk.
+    head: Cell<Option<NonNull<Header>>>,
+    /// The number of values in the most recent chunk.
+    /// Earlier chunks are full.
+    used: Cell<usize>,
+    len: Cell<usize>,
+    _owns: PhantomData<T>,
+}
+
+/// The start of each chunk, followed by its values.
+struct Header {
+    prev: Option<NonNull<Header>>,
+    cap: usize,
+}
+
+// SAFETY: the arena owns its values, and is not `Sync`.
+unsafe impl<T: Send> Send for Arena<T> {}
+
+impl<T> Arena<T> {
+    /// Create an empty arena, which doesn't allocate until the first value.
+    pub fn new() -> Self {
+        Self {
+            head: Cell::new(None),
+            used: Cell::new(0),
+            len: Cell::new(0),
+            _owns: PhantomData,
+        }
+    }
+    /// Attempt to create an empty arena with room for `capacity` values
+    /// in its first chunk.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn with_capacity(capacity: usize) -> Result<Self, Error> {
+        let this = Self::new();
+        if capacity != 0 && mem::size_of::<T>() != 0 {
+            this.push_chunk(capacity)?;
+        }
+        Ok(this)
+    }
+    /// Attempt to move `x` into the arena.
+    #[cfg_attr(feature = "location", track_caller)]
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc(&self, x: T) -> Result<&mut T, ErrorWith<T>> {
+        match self.slot() {
+            Ok(ptr) => {
+                // SAFETY: `ptr` is a vacant slot, which only this value will use,
+                //         and lives until the arena is dropped.
+                unsafe {
+                    ptr.as_ptr().write(x);
+                    Ok(&mut *ptr.as_ptr())
+                }
+            }
+            Err(_) => Err(ErrorWith(x)),
+        }
+    }
+    /// The number of values in the arena.
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+    /// Whether the arena has no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Claim a slot for a value, allocating a new chunk if needed.
+    #[cfg_attr(feature = "location", track_caller)]
+    fn slot(&self) -> Result<NonNull<T>, Error> {
+        if mem::size_of::<T>() == 0 {
+            self.len.set(self.len.get() + 1);
+            return Ok(NonNull::dangling());
+        }
+        let (head, used) = match self.head.get() {
+            // SAFETY: `head` is a live chunk.
+            Some(head) if self.used.get() < unsafe { head.as_ref() }.cap => (head, self.used.get()),
+            prev => {
+                let next = match prev {
+                    // SAFETY: `prev` is a live chunk.
+                    Some(prev) => unsafe { prev.as_ref() }.cap.saturating_mul(2),
+                    None => (FIRST_CHUNK / mem::size_of::<T>()).max(1),
+                };
+                let head = match self.push_chunk_silent(next) {
+                    Some(it) => it,
+                    // Doubling may have been too greedy.
+                    None if next > 1 => self.push_chunk(1)?,
+                    None => return Err(chunk_error::<T>(next)),
+                };
+                (head, 0)
+            }
+        };
+        self.used.set(used + 1);
+        self.len.set(self.len.get() + 1);
+        // SAFETY: `used` is less than the capacity of `head`.
+        Ok(unsafe { NonNull::new_unchecked(values::<T>(head).as_ptr().add(used)) })
+    }
+    /// Allocate a chunk with room for `cap` values, and make it the head.
+    #[cfg_attr(feature = "location", track_caller)]
+    fn push_chunk(&self, cap: usize) -> Result<NonNull<Header>, Error> {
+        match self.push_chunk_silent(cap) {
+            Some(it) => Ok(it),
+            None => Err(chunk_error::<T>(cap)),
+        }
+    }
+    /// Like [`push_chunk`](Self::push_chunk), but failures aren't reported,
+    /// see [`raw::array_silent`].
+    fn push_chunk_silent(&self, cap: usize) -> Option<NonNull<Header>> {
+        let head = raw::imp(layout::<T>(cap)?, false)?.cast::<Header>();
+        let header = Header {
+            prev: self.head.get(),
+            cap,
+        };
+        // SAFETY: `head` was just allocated, with room and alignment for the header.
+        unsafe { head.as_ptr().write(header) };
+        self.head.set(Some(head));
+        self.used.set(0);
+        Some(head)
+    }
+}
+
+/// Report the failure of [`Arena::push_chunk_silent`],
+/// returning the error from [`Arena::push_chunk`].
+#[cold]
+#[inline(never)]
+#[cfg_attr(feature = "location", track_caller)]
+fn chunk_error<T>(cap: usize) -> Error {
+    match layout::<T>(cap) {
+        Some(layout) => crate::hook::report_layout(layout),
+        None => Error::for_type_with_kind::<T>(ErrorKind::LayoutOverflow),
+    }
+}
+
+/// The layout of a chunk with room for `cap` values,
+/// or [`None`] if it would overflow.
+fn layout<T>(cap: usize) -> Option<Layout> {
+    let (layout, _) = Layout::new::<Header>()
+        .extend(Layout::array::<T>(cap).ok()?)
+        .ok()?;
+    Some(layout)
+}
+
+/// The values of the chunk at `head`.
+///
+/// # Safety
+/// `head` must be a chunk of `T`s.
+unsafe fn values<T>(head: NonNull<Header>) -> NonNull<T> {
+    let offset = Layout::new::<Header>()
+        .extend(Layout::new::<T>())
+        .map_or(0, |(_, offset)| offset);
+    // SAFETY: the values follow the header, at the same offset as for one value.
+    unsafe { NonNull::new_unchecked(head.as_ptr().cast::<u8>().add(offset).cast()) }
+}
+
+impl<T> Drop for Arena<T> {
+    fn drop(&mut self) {
+        if mem::size_of::<T>() == 0 {
+            let values =
+                ptr::slice_from_raw_parts_mut(NonNull::<T>::dangling().as_ptr(), self.len());
+            // SAFETY: zero-sized values need no storage, and we have `len` of them.
+            unsafe { ptr::drop_in_place(values) };
+            return;
+        }
+        let mut used = Some(self.used.get());
+        let mut next = self.head.get();
+        while let Some(head) = next {
+            // SAFETY: `head` is a live chunk of `T`s,
+            //         which is full unless it's the most recent.
+            unsafe {
+                let Header { prev, cap } = head.as_ptr().read();
+                ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                    values::<T>(head).as_ptr(),
+                    used.take().unwrap_or(cap),
+                ));
+                if let Some(layout) = layout::<T>(cap) {
+                    raw::dealloc(head.cast(), layout)
+                }
+                next = prev;
+            }
+        }
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Arena<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Arena").field("len", &self.len()).finish()
+    }
+}
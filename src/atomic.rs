@@ -0,0 +1,169 @@
+//! The atomics behind the hooks, the [`stats`](crate::stats),
+//! [`pressure`](crate::pressure) and [`heap`](crate::heap) registries,
+//! and the [`testing`](crate::testing) allocator.
+//!
+//! Targets without compare-and-swap, e.g `thumbv6m-none-eabi`,
+//! emulate them with critical sections, with the `"critical-section"`
+//! feature.
+//! An implementation must be provided, e.g by the HAL,
+//! see the [`critical_section`] docs.
+//!
+//! The stats' byte totals, and the [rate limit](crate::set_oom_rate_limit)'s
+//! timestamps, are 64-bit, using `portable-atomic` on targets without 64-bit
+//! atomics, with the `"portable-atomic"` feature.
+
+#[cfg(target_has_atomic = "ptr")]
+pub(crate) use core::sync::atomic::AtomicPtr;
+#[cfg(all(any(feature = "stats", feature = "std"), target_has_atomic = "64"))]
+pub(crate) use core::sync::atomic::AtomicU64;
+#[cfg(all(
+    any(feature = "stats", feature = "std", feature = "testing"),
+    target_has_atomic = "ptr"
+))]
+pub(crate) use core::sync::atomic::AtomicUsize;
+pub(crate) use core::sync::atomic::Ordering;
+#[cfg(not(target_has_atomic = "ptr"))]
+pub(crate) use emulated::AtomicPtr;
+#[cfg(all(
+    any(feature = "stats", feature = "std", feature = "testing"),
+    not(target_has_atomic = "ptr")
+))]
+pub(crate) use emulated::AtomicUsize;
+#[cfg(all(
+    any(feature = "stats", feature = "std"),
+    feature = "portable-atomic",
+    not(target_has_atomic = "64")
+))]
+pub(crate) use portable_atomic::AtomicU64;
+
+/// A 64-bit counter which wraps at [`usize::MAX`],
+/// for targets without 64-bit atomics or the `"portable-atomic"` feature.
+#[cfg(all(
+    any(feature = "stats", feature = "std"),
+    not(feature = "portable-atomic"),
+    not(target_has_atomic = "64")
+))]
+pub(crate) struct AtomicU64(AtomicUsize);
+
+#[cfg(all(
+    any(feature = "stats", feature = "std"),
+    not(feature = "portable-atomic"),
+    not(target_has_atomic = "64")
+))]
+impl AtomicU64 {
+    pub(crate) const fn new(value: u64) -> Self {
+        Self(AtomicUsize::new(value as usize))
+    }
+    pub(crate) fn load(&self, order: Ordering) -> u64 {
+        self.0.load(order) as u64
+    }
+    #[cfg(feature = "stats")]
+    pub(crate) fn fetch_add(&self, value: u64, order: Ordering) -> u64 {
+        self.0.fetch_add(value as usize, order) as u64
+    }
+    #[cfg(feature = "std")]
+    pub(crate) fn store(&self, value: u64, order: Ordering) {
+        self.0.store(value as usize, order)
+    }
+    #[cfg(feature = "std")]
+    pub(crate) fn compare_exchange(
+        &self,
+        current: u64,
+        new: u64,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<u64, u64> {
+        match self
+            .0
+            .compare_exchange(current as usize, new as usize, success, failure)
+        {
+            Ok(it) => Ok(it as u64),
+            Err(it) => Err(it as u64),
+        }
+    }
+}
+
+#[cfg(not(target_has_atomic = "ptr"))]
+mod emulated {
+    use core::{cell::Cell, sync::atomic::Ordering};
+
+    use critical_section::Mutex;
+
+    #[cfg(any(feature = "stats", feature = "std", feature = "testing"))]
+    pub(crate) type AtomicUsize = Atomic<usize>;
+    pub(crate) type AtomicPtr<T> = Atomic<*mut T>;
+
+    /// Like the `core` atomics, but every operation takes a critical section,
+    /// so the orderings are ignored.
+    pub(crate) struct Atomic<T>(Mutex<Cell<T>>);
+
+    // SAFETY: the value is only accessed within a critical section,
+    //         and is `Copy`, so a pointer is only ever read or written whole.
+    unsafe impl<T: Copy> Sync for Atomic<T> {}
+
+    impl<T> Atomic<T> {
+        pub(crate) const fn new(value: T) -> Self {
+            Self(Mutex::new(Cell::new(value)))
+        }
+    }
+
+    impl<T: Copy> Atomic<T> {
+        fn with<R>(&self, f: impl FnOnce(&Cell<T>) -> R) -> R {
+            critical_section::with(|cs| f(self.0.borrow(cs)))
+        }
+        pub(crate) fn load(&self, _: Ordering) -> T {
+            self.with(Cell::get)
+        }
+        pub(crate) fn store(&self, value: T, _: Ordering) {
+            self.with(|it| it.set(value))
+        }
+        pub(crate) fn swap(&self, value: T, _: Ordering) -> T {
+            self.with(|it| it.replace(value))
+        }
+        #[cfg(feature = "testing")]
+        pub(crate) fn fetch_update(
+            &self,
+            _: Ordering,
+            _: Ordering,
+            f: impl FnOnce(T) -> Option<T>,
+        ) -> Result<T, T> {
+            self.with(|it| match f(it.get()) {
+                Some(new) => Ok(it.replace(new)),
+                None => Err(it.get()),
+            })
+        }
+    }
+
+    impl<T: Copy + PartialEq> Atomic<T> {
+        pub(crate) fn compare_exchange(
+            &self,
+            current: T,
+            new: T,
+            _: Ordering,
+            _: Ordering,
+        ) -> Result<T, T> {
+            self.with(|it| match it.get() {
+                old if old == current => {
+                    it.set(new);
+                    Ok(old)
+                }
+                old => Err(old),
+            })
+        }
+    }
+
+    #[cfg(any(feature = "stats", feature = "std", feature = "testing"))]
+    impl Atomic<usize> {
+        pub(crate) fn fetch_add(&self, value: usize, _: Ordering) -> usize {
+            self.with(|it| it.replace(it.get().wrapping_add(value)))
+        }
+        #[cfg(any(feature = "stats", feature = "testing"))]
+        pub(crate) fn fetch_sub(&self, value: usize, _: Ordering) -> usize {
+            self.with(|it| it.replace(it.get().wrapping_sub(value)))
+        }
+        #[cfg(feature = "stats")]
+        pub(crate) fn fetch_max(&self, value: usize, _: Ordering) -> usize {
+            self.with(|it| it.replace(it.get().max(value)))
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Allocate several boxes at once, or none at all.
+//!
+//! ```
+//! let (a, b, c) = trybox::batch::new_all((1u8, 2u16, [0u32; 4])).unwrap();
+//! # let _: (Box<u8>, Box<u16>, Box<[u32; 4]>) = (a, b, c);
+//! ```
+//!
+//! On failure, the values are returned in the [`BatchError`]:
+//! ```
+//! # use trybox::batch::BatchError;
+//! fn connect(buf: [u8; 1024], peer: String) -> Option<(Box<[u8; 1024]>, Box<String>)> {
+//!     match trybox::batch::new_all((buf, peer)) {
+//!         Ok(boxes) => Some(boxes),
+//!         Err(BatchError { values: (_, peer), error }) => {
+//!             eprintln!("couldn't connect to {peer}: {error}");
+//!             None
+//!         }
+//!     }
+//! }
+//! ```
+
+use alloc::boxed::Box;
+use core::fmt;
+
+use crate::{init, uninit, Error};
+
+/// Attempt to move each member of a tuple to its own heap allocation.
+///
+/// If any allocation fails, those that succeeded are freed,
+/// the rest aren't attempted,
+/// and the tuple is returned in the [`BatchError`].
+///
+/// See [`Batch`] for the supported tuples.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn new_all<T: Batch>(values: T) -> Result<T::Boxed, BatchError<T>> {
+    values.new_all()
+}
+
+/// Tuples which [`new_all`] accepts, of up to twelve members.
+pub trait Batch: Sized {
+    /// The tuple of boxes.
+    type Boxed;
+    /// See [`new_all`].
+    #[cfg_attr(feature = "location", track_caller)]
+    fn new_all(self) -> Result<Self::Boxed, BatchError<Self>>;
+}
+
+/// Represents the failure to allocate one of a [`Batch`].
+#[derive(Debug)]
+pub struct BatchError<T> {
+    /// The original values.
+    pub values: T,
+    /// The first allocation which failed.
+    pub error: Error,
+}
+
+impl<T> fmt::Display for BatchError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<T: fmt::Debug> core::error::Error for BatchError<T> {}
+
+#[cfg(feature = "std")]
+impl<T: fmt::Debug> std::error::Error for BatchError<T> {}
+
+impl<T> From<BatchError<T>> for Error {
+    fn from(value: BatchError<T>) -> Self {
+        value.error
+    }
+}
+
+/// Allocate for a `T`, unless an earlier member failed,
+/// recording the first failure.
+#[cfg_attr(feature = "location", track_caller)]
+fn keep<T>(error: &mut Option<Error>) -> Option<Box<core::mem::MaybeUninit<T>>> {
+    if error.is_some() {
+        return None;
+    }
+    match uninit::<T>() {
+        Ok(heap) => Some(heap),
+        Err(e) => {
+            *error = Some(e);
+            None
+        }
+    }
+}
+
+macro_rules! tuple {
+    ($($ty:ident $value:ident $heap:ident),*) => {
+        impl<$($ty),*> Batch for ($($ty,)*) {
+            type Boxed = ($(Box<$ty>,)*);
+            #[cfg_attr(feature = "location", track_caller)]
+            fn new_all(self) -> Result<Self::Boxed, BatchError<Self>> {
+                let mut error = None;
+                match ($(keep::<$ty>(&mut error),)*) {
+                    ($(Some($heap),)*) => {
+                        let ($($value,)*) = self;
+                        Ok(($(init($heap, $value),)*))
+                    }
+                    _ => Err(BatchError {
+                        values: self,
+                        // at least one allocation failed, so this is always Some
+                        error: error.unwrap_or_else(Error::for_type::<Self>),
+                    }),
+                }
+            }
+        }
+    };
+}
+
+tuple!(A a ha);
+tuple!(A a ha, B b hb);
+tuple!(A a ha, B b hb, C c hc);
+tuple!(A a ha, B b hb, C c hc, D d hd);
+tuple!(A a ha, B b hb, C c hc, D d hd, E e he);
+tuple!(A a ha, B b hb, C c hc, D d hd, E e he, F f hf);
+tuple!(A a ha, B b hb, C c hc, D d hd, E e he, F f hf, G g hg);
+tuple!(A a ha, B b hb, C c hc, D d hd, E e he, F f hf, G g hg, H h hh);
+tuple!(A a ha, B b hb, C c hc, D d hd, E e he, F f hf, G g hg, H h hh, I i hi);
+tuple!(A a ha, B b hb, C c hc, D d hd, E e he, F f hf, G g hg, H h hh, I i hi, J j hj);
+tuple!(A a ha, B b hb, C c hc, D d hd, E e he, F f hf, G g hg, H h hh, I i hi, J j hj, K k hk);
+tuple!(A a ha, B b hb, C c hc, D d hd, E e he, F f hf, G g hg, H h hh, I i hi, J j hj, K k hk, L l hl);
@@ -0,0 +1,86 @@
+//! A fallibly-growing byte buffer, shared by the crate's builders.
+
+use alloc::boxed::Box;
+
+use crate::{slice, Error, ErrorKind, TryIntoBox as _};
+
+/// Like a `Vec<u8>`, but growth is fallible.
+///
+/// The spare capacity is always zeroed, so can be handed out as `&mut [u8]`.
+pub(crate) struct Bytes {
+    buf: Box<[u8]>,
+    len: usize,
+}
+
+impl Bytes {
+    pub fn new() -> Self {
+        Self {
+            // A dangling pointer, which doesn't allocate.
+            buf: Box::new([]),
+            len: 0,
+        }
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+    /// Remove the contents after `len`, re-zeroing them.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.buf[len..self.len].fill(0);
+            self.len = len;
+        }
+    }
+    /// Ensure there is room for at least `additional` more bytes,
+    /// typically doubling the capacity.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn reserve(&mut self, additional: usize) -> Result<(), Error> {
+        let required = match self.len.checked_add(additional) {
+            Some(it) => it,
+            None => return Err(Error::for_type_with_kind::<u8>(ErrorKind::LayoutOverflow)),
+        };
+        if required <= self.buf.len() {
+            return Ok(());
+        }
+        let new_len = required.max(self.buf.len().saturating_mul(2)).max(64);
+        let mut buf = match slice::zeroed_silent(new_len) {
+            Some(it) => it,
+            // Doubling may have been too greedy.
+            None => slice::zeroed(required)?,
+        };
+        buf[..self.len].copy_from_slice(self.as_slice());
+        self.buf = buf;
+        Ok(())
+    }
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.reserve(bytes.len())?;
+        self.buf[self.len..][..bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+    /// The zeroed capacity after the contents.
+    #[cfg(feature = "std")]
+    pub fn spare_mut(&mut self) -> &mut [u8] {
+        &mut self.buf[self.len..]
+    }
+    /// Add `n` bytes of the [spare capacity](Self::spare_mut) to the contents.
+    #[cfg(feature = "std")]
+    pub fn commit(&mut self, n: usize) {
+        assert!(n <= self.buf.len() - self.len);
+        self.len += n;
+    }
+    /// Get the contents, allocating again unless the buffer is full.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn into_boxed_slice(self) -> Result<Box<[u8]>, Error> {
+        match self.len == self.buf.len() {
+            true => Ok(self.buf),
+            false => self.as_slice().try_into_box(),
+        }
+    }
+}
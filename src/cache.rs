@@ -0,0 +1,333 @@
+//! A cache of boxed values, which sheds entries under memory pressure.
+//!
+//! ```
+//! use std::sync::Arc;
+//! use trybox::cache::BoxCache;
+//!
+//! let cache = Arc::new(BoxCache::new(1 << 20));
+//! // Evict entries when other allocations through this crate fail.
+//! assert!(cache.shed_under_pressure());
+//! cache.insert("greeting", [0u8; 4096]).unwrap();
+//! assert_eq!(cache.get("greeting", |it| it.len()), Some(4096));
+//! assert_eq!(cache.bytes(), 4096);
+//! ```
+
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
+use core::{
+    borrow::Borrow,
+    cell::UnsafeCell,
+    fmt,
+    hash::Hash,
+    mem,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard, PoisonError, TryLockError},
+};
+
+use crate::{
+    pressure::{self, Level},
+    ErrorWith,
+};
+
+/// The maximum number of caches which may [`shed_under_pressure`](BoxCache::shed_under_pressure)
+/// at once.
+pub const CAPACITY: usize = 16;
+
+/// A map of boxed values, bounded by their total weight in bytes,
+/// evicting the least recently used entries to stay within it.
+///
+/// Values are boxed with this crate, and the map grows fallibly,
+/// so inserts fail rather than abort.
+///
+/// Each entry weighs the size of `V` by default,
+/// see [`insert_weighted`](Self::insert_weighted) for values which own
+/// further memory.
+pub struct BoxCache<K, V> {
+    max_bytes: usize,
+    inner: Mutex<Inner<K, V>>,
+}
+
+struct Inner<K, V> {
+    map: HashMap<K, Entry<V>>,
+    bytes: usize,
+    /// Incremented on each access, for recency.
+    tick: u64,
+}
+
+struct Entry<V> {
+    value: Box<V>,
+    weight: usize,
+    used: u64,
+}
+
+impl<K, V> BoxCache<K, V> {
+    /// Create an empty cache holding at most `max_bytes` of values,
+    /// which doesn't allocate until the first insert.
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            inner: Mutex::new(Inner {
+                map: HashMap::new(),
+                bytes: 0,
+                tick: 0,
+            }),
+        }
+    }
+    fn lock(&self) -> MutexGuard<'_, Inner<K, V>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// The total weight of the entries.
+    pub fn bytes(&self) -> usize {
+        self.lock().bytes
+    }
+    /// The weight above which entries are evicted.
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+    /// The number of entries.
+    pub fn len(&self) -> usize {
+        self.lock().map.len()
+    }
+    /// Whether there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Remove every entry.
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        inner.map.clear();
+        inner.bytes = 0;
+    }
+    /// Evict the least recently used entries until at least `bytes` have been
+    /// freed, or the cache is empty, returning the weight freed.
+    ///
+    /// At least one entry is evicted, if there are any.
+    pub fn shed(&self, bytes: usize) -> usize {
+        self.lock().shed(bytes)
+    }
+}
+
+impl<K: Eq + Hash, V> BoxCache<K, V> {
+    /// Attempt to insert `value` under `key`, returning the previous value,
+    /// if any, or both on failure.
+    ///
+    /// Entries are then evicted until the cache is within its
+    /// [`max_bytes`](Self::max_bytes), which may include this one.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn insert(&self, key: K, value: V) -> Result<Option<Box<V>>, ErrorWith<(K, V)>> {
+        self.insert_weighted(key, value, mem::size_of::<V>())
+    }
+    /// Like [`insert`](Self::insert), but with an explicit `weight` in bytes,
+    /// e.g including memory owned by the value.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn insert_weighted(
+        &self,
+        key: K,
+        value: V,
+        weight: usize,
+    ) -> Result<Option<Box<V>>, ErrorWith<(K, V)>> {
+        // Box the value before taking the lock,
+        // in case allocating calls back into `shed`.
+        let value = match crate::new(value) {
+            Ok(it) => it,
+            Err(ErrorWith(value)) => return Err(ErrorWith((key, value))),
+        };
+        let mut inner = self.lock();
+        if inner.map.try_reserve(1).is_err() {
+            crate::hook::report(crate::repr::Repr::of::<(K, V)>());
+            return Err(ErrorWith((key, *value)));
+        }
+        let used = inner.touch();
+        let old = inner.map.insert(
+            key,
+            Entry {
+                value,
+                weight,
+                used,
+            },
+        );
+        inner.bytes = inner.bytes.saturating_add(weight);
+        let old = old.map(|old| {
+            inner.bytes -= old.weight;
+            old.value
+        });
+        while inner.bytes > self.max_bytes && inner.evict().is_some() {}
+        Ok(old)
+    }
+    /// Call `f` with the value under `key`, if any,
+    /// marking it as recently used.
+    pub fn get<Q, R>(&self, key: &Q, f: impl FnOnce(&V) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let mut inner = self.lock();
+        let used = inner.touch();
+        let entry = inner.map.get_mut(key)?;
+        entry.used = used;
+        Some(f(&entry.value))
+    }
+    /// Remove the value under `key`, if any.
+    pub fn remove<Q>(&self, key: &Q) -> Option<Box<V>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let mut inner = self.lock();
+        let entry = inner.map.remove(key)?;
+        inner.bytes -= entry.weight;
+        Some(entry.value)
+    }
+}
+
+impl<K: Send + 'static, V: Send + 'static> BoxCache<K, V> {
+    /// [`shed`](Self::shed) entries from this cache when an allocation
+    /// through this crate fails, before it is retried,
+    /// using the [`pressure`] callbacks.
+    ///
+    /// This lasts as long as the cache.
+    /// Returns `false` if [`CAPACITY`] caches are already shedding,
+    /// or the [`pressure`] callbacks are full.
+    ///
+    /// If the cache is in use on the thread whose allocation failed,
+    /// it is skipped.
+    pub fn shed_under_pressure(self: &Arc<Self>) -> bool {
+        if !REGISTERED.swap(true, Ordering::AcqRel) {
+            match pressure::register(relieve) {
+                Some(it) => it.forget(),
+                None => {
+                    REGISTERED.store(false, Ordering::Release);
+                    return false;
+                }
+            }
+        }
+        let weak: Weak<dyn Shed> = Arc::downgrade(self) as _;
+        REGISTRY.with(|slots| {
+            match slots
+                .iter_mut()
+                .find(|it| it.as_ref().map_or(true, |it| it.strong_count() == 0))
+            {
+                Some(slot) => {
+                    *slot = Some(weak);
+                    true
+                }
+                None => false,
+            }
+        })
+    }
+}
+
+impl<K, V> Inner<K, V> {
+    fn touch(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+    /// Remove the least recently used entry, returning its weight.
+    fn evict(&mut self) -> Option<usize> {
+        let oldest = self.map.values().map(|it| it.used).min()?;
+        let mut weight = 0;
+        self.map.retain(|_, it| match it.used == oldest {
+            true => {
+                weight = it.weight;
+                false
+            }
+            false => true,
+        });
+        self.bytes -= weight;
+        Some(weight)
+    }
+    fn shed(&mut self, bytes: usize) -> usize {
+        let mut freed = 0;
+        while let Some(weight) = self.evict() {
+            freed += weight;
+            if freed >= bytes {
+                break;
+            }
+        }
+        freed
+    }
+}
+
+impl<K, V> fmt::Debug for BoxCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.lock();
+        f.debug_struct("BoxCache")
+            .field("len", &inner.map.len())
+            .field("bytes", &inner.bytes)
+            .field("max_bytes", &self.max_bytes)
+            .finish()
+    }
+}
+
+/// A cache which may shed entries from a [`pressure`] callback.
+trait Shed: Send + Sync {
+    /// Like [`BoxCache::shed`], but skip the cache if it's locked,
+    /// e.g by an allocation on this thread.
+    fn try_shed(&self, bytes: usize) -> usize;
+}
+
+impl<K: Send, V: Send> Shed for BoxCache<K, V> {
+    fn try_shed(&self, bytes: usize) -> usize {
+        match self.inner.try_lock() {
+            Ok(mut it) => it.shed(bytes),
+            Err(TryLockError::Poisoned(it)) => it.into_inner().shed(bytes),
+            Err(TryLockError::WouldBlock) => 0,
+        }
+    }
+}
+
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+static REGISTRY: Registry = Registry {
+    lock: AtomicBool::new(false),
+    slots: UnsafeCell::new([NONE; CAPACITY]),
+};
+
+const NONE: Option<Weak<dyn Shed>> = None;
+
+/// A spin lock around the caches, since [`Mutex::new`] isn't `const` in our
+/// MSRV.
+struct Registry {
+    lock: AtomicBool,
+    slots: UnsafeCell<[Option<Weak<dyn Shed>>; CAPACITY]>,
+}
+
+// SAFETY: the slots are only accessed with the lock held.
+unsafe impl Sync for Registry {}
+
+impl Registry {
+    fn with<R>(&self, f: impl FnOnce(&mut [Option<Weak<dyn Shed>>; CAPACITY]) -> R) -> R {
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop()
+        }
+        struct Unlock<'a>(&'a AtomicBool);
+        impl Drop for Unlock<'_> {
+            fn drop(&mut self) {
+                self.0.store(false, Ordering::Release)
+            }
+        }
+        let _unlock = Unlock(&self.lock);
+        // SAFETY: we hold the lock.
+        f(unsafe { &mut *self.slots.get() })
+    }
+}
+
+fn relieve(level: Level) {
+    REGISTRY.with(|slots| {
+        let mut freed = 0;
+        for cache in slots.iter().flatten().filter_map(Weak::upgrade) {
+            freed += cache.try_shed(level.bytes.saturating_sub(freed));
+            if level.bytes != 0 && freed >= level.bytes {
+                break;
+            }
+        }
+    })
+}
@@ -0,0 +1,67 @@
+//! Co-operation with the [`cap`](https://docs.rs/cap) crate's limited allocator,
+//! for the `"cap"` feature,
+//! e.g to simulate a small-memory container in tests.
+//!
+//! ```
+//! use std::alloc::System;
+//! use cap::Cap;
+//!
+//! #[global_allocator]
+//! static ALLOC: Cap<System> = Cap::new(System, usize::MAX);
+//!
+//! # fn main() {
+//! # #[cfg(feature = "backtrace")]
+//! # trybox::Backtrace::init();
+//! trybox::cap::with_headroom(&ALLOC, 1024, || {
+//!     let e = trybox::or_drop([0u8; 4096]).unwrap_err();
+//!     assert_eq!(e.size(), 4096);
+//!     assert!(trybox::cap::exceeded(&ALLOC, &e));
+//! });
+//! # }
+//! ```
+
+use ::cap::Cap;
+
+use crate::Error;
+
+/// Run `f` with `cap` limited to `bytes` more than is currently allocated,
+/// restoring the previous limit afterwards, including on panic.
+///
+/// Allocations which would exceed the limit fail,
+/// which this crate reports as an [`Error`] for the type or layout requested.
+///
+/// The limit is process-wide, so allocations on other threads count towards
+/// it too.
+pub fn with_headroom<H, R>(cap: &Cap<H>, bytes: usize, f: impl FnOnce() -> R) -> R {
+    let _restore = Restore {
+        cap,
+        limit: cap.limit(),
+    };
+    // This can only fail if the new limit is below the allocated bytes.
+    let _ = cap.set_limit(cap.allocated().saturating_add(bytes));
+    f()
+}
+
+/// Whether `e` is explained by `cap`'s limit,
+/// i.e the failed allocation is larger than the bytes remaining.
+///
+/// This should be called soon after the failure,
+/// as the remaining bytes change as memory is allocated and freed.
+pub fn exceeded<H>(cap: &Cap<H>, e: &Error) -> bool {
+    e.size() > cap.remaining()
+}
+
+struct Restore<'a, H> {
+    cap: &'a Cap<H>,
+    limit: usize,
+}
+
+impl<H> Drop for Restore<'_, H> {
+    fn drop(&mut self) {
+        // Memory allocated within the scope may have taken us over,
+        // in which case tighten the limit as far as possible.
+        if self.cap.set_limit(self.limit).is_err() {
+            let _ = self.cap.set_limit(self.cap.allocated());
+        }
+    }
+}
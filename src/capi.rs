@@ -0,0 +1,133 @@
+//! C ABI exports, for the `"capi"` feature.
+//!
+//! These let C and C++ code share this crate's allocation front-end,
+//! e.g the [`limit`](crate::limit)s, [`pressure`](crate::pressure) callbacks
+//! and [OOM hook](crate::set_oom_hook), when linked into the same binary.
+//!
+//! ```c
+//! int trybox_alloc(size_t size, size_t align, void **out);
+//! void trybox_free(void *ptr, size_t size, size_t align);
+//! size_t trybox_last_error_message(char *buf, size_t len);
+//! ```
+
+use core::{alloc::Layout, cell::Cell, fmt, ptr::NonNull};
+use std::os::raw::{c_char, c_int, c_void};
+
+use crate::{raw, Error, ErrorKind};
+
+/// Returned from [`trybox_alloc`] on success.
+pub const TRYBOX_OK: c_int = 0;
+/// The allocator refused the request, see [`ErrorKind::Exhausted`].
+pub const TRYBOX_EXHAUSTED: c_int = 1;
+/// The size overflowed, see [`ErrorKind::LayoutOverflow`].
+pub const TRYBOX_LAYOUT_OVERFLOW: c_int = 2;
+/// The alignment wasn't a power of two, see [`ErrorKind::Alignment`].
+pub const TRYBOX_ALIGNMENT: c_int = 3;
+
+std::thread_local! {
+    static LAST_ERROR: Cell<Option<Error>> = const { Cell::new(None) };
+}
+
+/// Allocate `size` bytes aligned to `align`, storing the pointer in `out`.
+///
+/// Returns [`TRYBOX_OK`] on success,
+/// or another status on failure, in which case `out` is unchanged,
+/// and the error is available from [`trybox_last_error_message`].
+///
+/// A zero `size` returns a dangling, suitably aligned pointer.
+///
+/// # Safety
+/// - `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn trybox_alloc(size: usize, align: usize, out: *mut *mut c_void) -> c_int {
+    let res = match Layout::from_size_align(size, align) {
+        Ok(layout) => raw::alloc(layout),
+        Err(_) => Err(Error::for_kind(match align.is_power_of_two() {
+            true => ErrorKind::LayoutOverflow,
+            false => ErrorKind::Alignment,
+        })),
+    };
+    match res {
+        Ok(ptr) => {
+            // SAFETY: caller upholds the contract.
+            unsafe { out.write(ptr.as_ptr().cast()) };
+            TRYBOX_OK
+        }
+        Err(e) => {
+            LAST_ERROR.with(|it| it.set(Some(e)));
+            match e.kind() {
+                ErrorKind::Exhausted => TRYBOX_EXHAUSTED,
+                ErrorKind::LayoutOverflow => TRYBOX_LAYOUT_OVERFLOW,
+                ErrorKind::Alignment => TRYBOX_ALIGNMENT,
+            }
+        }
+    }
+}
+
+/// Free memory returned from [`trybox_alloc`].
+///
+/// Null pointers are ignored.
+///
+/// # Safety
+/// - `ptr` must have been returned from [`trybox_alloc`] with this `size`
+///   and `align`.
+/// - `ptr` must not have already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn trybox_free(ptr: *mut c_void, size: usize, align: usize) {
+    if let Some(ptr) = NonNull::new(ptr) {
+        // SAFETY: caller upholds the contract.
+        unsafe { raw::dealloc(ptr.cast(), Layout::from_size_align_unchecked(size, align)) }
+    }
+}
+
+/// Write the message for the last failure of [`trybox_alloc`] on this thread
+/// to `buf`, truncated to fit `len` bytes including a trailing NUL.
+///
+/// Returns the length of the whole message, excluding the NUL,
+/// like `snprintf`, or zero if there has been no failure.
+/// This doesn't allocate.
+///
+/// # Safety
+/// - `buf` must be valid for writes of `len` bytes, or `len` must be zero.
+#[no_mangle]
+pub unsafe extern "C" fn trybox_last_error_message(buf: *mut c_char, len: usize) -> usize {
+    let e = match LAST_ERROR.with(Cell::get) {
+        Some(it) => it,
+        None => return 0,
+    };
+    let buf = match len {
+        0 => &mut [][..],
+        // SAFETY: caller upholds the contract.
+        _ => unsafe { core::slice::from_raw_parts_mut(buf.cast::<u8>(), len) },
+    };
+    let mut w = Truncate {
+        buf,
+        written: 0,
+        len: 0,
+    };
+    let _ = fmt::Write::write_fmt(&mut w, format_args!("{e}"));
+    let Truncate { buf, written, len } = w;
+    if let Some(nul) = buf.get_mut(written) {
+        *nul = 0;
+    }
+    len
+}
+
+/// Writes as much as fits in `buf`, leaving room for a NUL,
+/// and counts the rest.
+struct Truncate<'a> {
+    buf: &'a mut [u8],
+    written: usize,
+    len: usize,
+}
+
+impl fmt::Write for Truncate<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = self.buf.len().saturating_sub(1) - self.written;
+        let n = s.len().min(room);
+        self.buf[self.written..][..n].copy_from_slice(&s.as_bytes()[..n]);
+        self.written += n;
+        self.len += s.len();
+        Ok(())
+    }
+}
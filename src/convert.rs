@@ -0,0 +1,102 @@
+//! Fallible conversion to a [`Box`], for generic APIs.
+//!
+//! ```
+//! use trybox::TryIntoBox;
+//!
+//! fn store(name: impl TryIntoBox<str>) -> Result<Box<str>, trybox::Error> {
+//!     name.try_into_box()
+//! }
+//! assert_eq!(&*store("hello")?, "hello");
+//! assert_eq!(&*store(String::from("hello"))?, "hello");
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::ptr;
+
+use crate::{or_drop, or_drop_with, slice, Error};
+
+/// Values which can be fallibly moved or copied to a `Box<T>`,
+/// like a fallible `Into<Box<T>>`.
+pub trait TryIntoBox<T: ?Sized> {
+    /// Attempt the conversion.
+    fn try_into_box(self) -> Result<Box<T>, Error>;
+}
+
+impl<T> TryIntoBox<T> for T {
+    #[cfg_attr(feature = "location", track_caller)]
+    fn try_into_box(self) -> Result<Box<T>, Error> {
+        or_drop(self)
+    }
+}
+
+/// Clones `T` only if allocation succeeds.
+impl<T: Clone> TryIntoBox<T> for &T {
+    #[cfg_attr(feature = "location", track_caller)]
+    fn try_into_box(self) -> Result<Box<T>, Error> {
+        or_drop_with(|| self.clone())
+    }
+}
+
+/// Never allocates.
+impl<T: ?Sized> TryIntoBox<T> for Box<T> {
+    fn try_into_box(self) -> Result<Box<T>, Error> {
+        Ok(self)
+    }
+}
+
+impl<T, const N: usize> TryIntoBox<[T]> for [T; N] {
+    #[cfg_attr(feature = "location", track_caller)]
+    fn try_into_box(self) -> Result<Box<[T]>, Error> {
+        Ok(or_drop(self)?)
+    }
+}
+
+impl<T: Clone> TryIntoBox<[T]> for &[T] {
+    #[cfg_attr(feature = "location", track_caller)]
+    fn try_into_box(self) -> Result<Box<[T]>, Error> {
+        slice::from_fn(self.len(), |ix| self[ix].clone())
+    }
+}
+
+impl TryIntoBox<str> for &str {
+    #[cfg_attr(feature = "location", track_caller)]
+    fn try_into_box(self) -> Result<Box<str>, Error> {
+        let bytes = self.as_bytes().try_into_box()?;
+        // SAFETY: the bytes were copied from a `str`.
+        Ok(unsafe { alloc::str::from_boxed_utf8_unchecked(bytes) })
+    }
+}
+
+/// Doesn't allocate if the [`Vec`] has no spare capacity,
+/// otherwise the elements are moved to a fresh allocation,
+/// rather than reallocating the [`Vec`] infallibly.
+impl<T> TryIntoBox<[T]> for Vec<T> {
+    #[cfg_attr(feature = "location", track_caller)]
+    fn try_into_box(mut self) -> Result<Box<[T]>, Error> {
+        if self.len() == self.capacity() {
+            return Ok(self.into_boxed_slice());
+        }
+        let len = self.len();
+        let mut boxed = slice::uninit::<T>(len)?;
+        // SAFETY:
+        // - the allocations are distinct, and both have room for `len` `T`s.
+        // - the elements are moved, so must not be dropped by the `Vec`.
+        unsafe {
+            ptr::copy_nonoverlapping(self.as_ptr(), boxed.as_mut_ptr().cast::<T>(), len);
+            self.set_len(0);
+        }
+        // SAFETY: every element has been initialized.
+        Ok(unsafe { Box::from_raw(Box::into_raw(boxed) as *mut [T]) })
+    }
+}
+
+/// See the implementation for [`Vec`].
+impl TryIntoBox<str> for String {
+    #[cfg_attr(feature = "location", track_caller)]
+    fn try_into_box(self) -> Result<Box<str>, Error> {
+        let bytes = self.into_bytes().try_into_box()?;
+        // SAFETY: the bytes were moved from a `String`.
+        Ok(unsafe { alloc::str::from_boxed_utf8_unchecked(bytes) })
+    }
+}
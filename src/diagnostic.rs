@@ -0,0 +1,53 @@
+//! [`miette::Diagnostic`] implementations, behind the `"miette"` feature.
+
+use alloc::boxed::Box;
+use core::fmt;
+
+use miette::Diagnostic;
+
+use crate::{Error, ErrorKind, ErrorWith, Info};
+
+impl Diagnostic for Error {
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        help(self.info())
+    }
+}
+
+impl<T: fmt::Debug> Diagnostic for ErrorWith<T> {
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        help(self.info())
+    }
+}
+
+fn help<'a>(info: Info) -> Option<Box<dyn fmt::Display + 'a>> {
+    // Boxing a zero-sized type doesn't allocate.
+    match info.kind {
+        ErrorKind::Exhausted => Some(Box::new(Exhausted)),
+        ErrorKind::LayoutOverflow => Some(Box::new(LayoutOverflow)),
+        ErrorKind::Alignment => Some(Box::new(Alignment)),
+    }
+}
+
+struct Exhausted;
+
+impl fmt::Display for Exhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("try requesting less memory at once, e.g by processing smaller batches")
+    }
+}
+
+struct LayoutOverflow;
+
+impl fmt::Display for LayoutOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the requested length is too large to ever be allocated")
+    }
+}
+
+struct Alignment;
+
+impl fmt::Display for Alignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("use an alignment that is a power of two, and supported by the allocator")
+    }
+}
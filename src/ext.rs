@@ -0,0 +1,146 @@
+//! Extension traits, also available from the [`prelude`](crate::prelude).
+//!
+//! ```
+//! use trybox::prelude::*;
+//!
+//! let heaped = trybox::new(1).or_recover(|stacked| Box::new(stacked));
+//! assert_eq!(*heaped, 1);
+//! let heaped = [0u8; 64].try_boxed_or_drop()?;
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use alloc::boxed::Box;
+use core::{
+    fmt,
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut},
+    ptr,
+};
+
+use crate::{Error, ErrorWith};
+
+/// Fallible boxing as a method, for any type.
+pub trait TryBoxed: Sized {
+    /// See [`new`](crate::new).
+    fn try_boxed(self) -> Result<Box<Self>, ErrorWith<Self>>;
+    /// See [`or_drop`](crate::or_drop).
+    fn try_boxed_or_drop(self) -> Result<Box<Self>, Error>;
+}
+
+impl<T> TryBoxed for T {
+    #[inline(always)]
+    #[cfg_attr(feature = "location", track_caller)]
+    fn try_boxed(self) -> Result<Box<Self>, ErrorWith<Self>> {
+        crate::new(self)
+    }
+    #[inline(always)]
+    #[cfg_attr(feature = "location", track_caller)]
+    fn try_boxed_or_drop(self) -> Result<Box<Self>, Error> {
+        crate::or_drop(self)
+    }
+}
+
+/// Extension methods for `Result<Box<T>, ErrorWith<T>>`.
+///
+/// This trait is sealed.
+pub trait ResultExt<T>: sealed::Sealed {
+    /// Recover the payload on failure, e.g by retrying
+    /// after freeing memory.
+    fn or_recover(self, f: impl FnOnce(T) -> Box<T>) -> Box<T>;
+    /// Fall back to moving the payload into `slot` on failure.
+    ///
+    /// ```
+    /// # use core::mem::MaybeUninit;
+    /// use trybox::ResultExt as _;
+    ///
+    /// let mut slot = MaybeUninit::uninit();
+    /// let mut it = trybox::new([0u8; 64]).or_place_in(&mut slot);
+    /// it[0] = 1;
+    /// ```
+    fn or_place_in(self, slot: &mut MaybeUninit<T>) -> Placed<'_, T>;
+    /// Call [`Error::handle`](crate::Error::handle) on failure,
+    /// typically aborting the process, like [`or_handle`](crate::or_handle).
+    fn or_handle(self) -> Box<T>;
+}
+
+impl<T> ResultExt<T> for Result<Box<T>, ErrorWith<T>> {
+    #[inline(always)]
+    fn or_recover(self, f: impl FnOnce(T) -> Box<T>) -> Box<T> {
+        match self {
+            Ok(it) => it,
+            Err(ErrorWith(it)) => f(it),
+        }
+    }
+    #[inline(always)]
+    fn or_place_in(self, slot: &mut MaybeUninit<T>) -> Placed<'_, T> {
+        match self {
+            Ok(it) => Placed(Repr::Heap(it)),
+            Err(ErrorWith(it)) => Placed(Repr::Stack(slot.write(it))),
+        }
+    }
+    #[inline(always)]
+    fn or_handle(self) -> Box<T> {
+        match self {
+            Ok(it) => it,
+            Err(e) => e.without_payload().handle(),
+        }
+    }
+}
+
+/// A `T` either on the heap, or in a caller-provided slot,
+/// returned from [`ResultExt::or_place_in`].
+///
+/// The `T` is dropped with this.
+pub struct Placed<'a, T>(Repr<'a, T>);
+
+enum Repr<'a, T> {
+    Heap(Box<T>),
+    Stack(&'a mut T),
+}
+
+impl<T> Placed<'_, T> {
+    /// Whether allocation succeeded, and the `T` is on the heap.
+    pub fn is_heap(&self) -> bool {
+        matches!(self.0, Repr::Heap(_))
+    }
+}
+
+impl<T> Deref for Placed<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        match &self.0 {
+            Repr::Heap(it) => it,
+            Repr::Stack(it) => it,
+        }
+    }
+}
+
+impl<T> DerefMut for Placed<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match &mut self.0 {
+            Repr::Heap(it) => it,
+            Repr::Stack(it) => it,
+        }
+    }
+}
+
+impl<T> Drop for Placed<'_, T> {
+    fn drop(&mut self) {
+        if let Repr::Stack(it) = &mut self.0 {
+            // SAFETY: we initialized the slot, and it won't be used again,
+            //         as the caller's `MaybeUninit` never drops its contents.
+            unsafe { ptr::drop_in_place(*it) }
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Placed<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+    impl<T> Sealed for Result<alloc::boxed::Box<T>, crate::ErrorWith<T>> {}
+}
@@ -0,0 +1,173 @@
+//! Fallible formatting, see [`try_format!`](crate::try_format!) and [`Sink`].
+
+use alloc::boxed::Box;
+use core::fmt::{self, Write as _};
+
+use crate::{bytes::Bytes, slice, Error, ErrorKind};
+
+/// Like [`format!`](alloc::format!), but fallibly creates a `Box<str>`,
+/// returning a `Result<Box<str>, Error>`.
+///
+/// ```
+/// let s = trybox::try_format!("{} + {} = {}", 1, 2, 1 + 2)?;
+/// assert_eq!(&*s, "1 + 2 = 3");
+/// # Ok::<_, trybox::Error>(())
+/// ```
+///
+/// See [`format::to_boxed_str`](crate::format::to_boxed_str) for more.
+#[macro_export]
+macro_rules! try_format {
+    ($($arg:tt)*) => {
+        $crate::format::to_boxed_str(::core::format_args!($($arg)*))
+    };
+}
+
+/// Format `args` to an exactly-sized `Box<str>`.
+///
+/// The arguments are formatted twice:
+/// once to measure the length, and again into the allocation.
+///
+/// # Panics
+/// Like [`format!`](alloc::format!), if a formatting trait implementation
+/// returns an error, or if the arguments format to different lengths.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn to_boxed_str(args: fmt::Arguments<'_>) -> Result<Box<str>, Error> {
+    if let Some(s) = args.as_str() {
+        return crate::TryIntoBox::try_into_box(s);
+    }
+    let mut len = Measure(Some(0));
+    len.write_fmt(args)
+        .expect("a formatting trait implementation returned an error");
+    let mut buf = match len.0 {
+        Some(len) => slice::zeroed(len)?,
+        None => return Err(Error::for_type_with_kind::<u8>(ErrorKind::LayoutOverflow)),
+    };
+    let mut w = Fill {
+        buf: &mut buf,
+        len: 0,
+        overflowed: false,
+    };
+    let res = w.write_fmt(args);
+    assert!(
+        !w.overflowed && w.len == w.buf.len(),
+        "arguments formatted to different lengths"
+    );
+    res.expect("a formatting trait implementation returned an error");
+    // SAFETY: the buffer has been entirely filled with `str`s.
+    Ok(unsafe { alloc::str::from_boxed_utf8_unchecked(buf) })
+}
+
+/// Counts the bytes written, or [`None`] on overflow.
+struct Measure(Option<usize>);
+
+impl fmt::Write for Measure {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 = self.0.and_then(|it| it.checked_add(s.len()));
+        Ok(())
+    }
+}
+
+struct Fill<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    overflowed: bool,
+}
+
+impl fmt::Write for Fill<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let dst = match self.buf[self.len..].get_mut(..s.len()) {
+            Some(it) => it,
+            None => {
+                self.overflowed = true;
+                return Err(fmt::Error);
+            }
+        };
+        dst.copy_from_slice(s.as_bytes());
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+/// A [`fmt::Write`]r over a fallibly-growing buffer,
+/// like a [`String`] that can't abort.
+///
+/// ```
+/// use core::fmt::Write as _;
+///
+/// let mut sink = trybox::format::Sink::new();
+/// for i in 0..3 {
+///     let _ = write!(sink, "{i},");
+/// }
+/// assert_eq!(&*sink.finish()?, "0,1,2,");
+/// # Ok::<_, trybox::Error>(())
+/// ```
+///
+/// On allocation failure, [`write_str`](fmt::Write::write_str) returns
+/// [`fmt::Error`], and the [`Error`] is kept, to be returned from
+/// [`finish`](Sink::finish).
+/// Further writes are ignored.
+pub struct Sink {
+    bytes: Bytes,
+    error: Option<Error>,
+}
+
+impl Sink {
+    /// Create an empty sink, without allocating.
+    pub fn new() -> Self {
+        Self {
+            bytes: Bytes::new(),
+            error: None,
+        }
+    }
+    /// The text written so far.
+    pub fn as_str(&self) -> &str {
+        // SAFETY: only whole `str`s are written.
+        unsafe { core::str::from_utf8_unchecked(self.bytes.as_slice()) }
+    }
+    /// The first allocation failure, if any.
+    pub fn error(&self) -> Option<Error> {
+        self.error
+    }
+    /// Get the written text, or the first allocation failure.
+    ///
+    /// This may allocate once more, to fit the text exactly.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn finish(self) -> Result<Box<str>, Error> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let bytes = self.bytes.into_boxed_slice()?;
+        // SAFETY: only whole `str`s are written.
+        Ok(unsafe { alloc::str::from_boxed_utf8_unchecked(bytes) })
+    }
+}
+
+impl Default for Sink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Write for Sink {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.error.is_some() {
+            return Err(fmt::Error);
+        }
+        match self.bytes.extend_from_slice(s.as_bytes()) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                self.error = Some(e);
+                Err(fmt::Error)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sink")
+            .field("text", &self.as_str())
+            .field("error", &self.error)
+            .finish()
+    }
+}
@@ -0,0 +1,133 @@
+//! Heap values followed by a guard page, for catching buffer overruns.
+//!
+//! Each value gets its own memory mapping, so this is intended for debugging,
+//! rather than general use.
+//!
+//! ```
+//! let mut buf = trybox::guarded::new([0u8; 100]).unwrap();
+//! buf[99] = 1;
+//! // Writing one past the end would segfault:
+//! // unsafe { buf.as_mut_ptr().add(100).write(1) };
+//! ```
+
+use core::{
+    fmt,
+    mem::{align_of, size_of},
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+
+use crate::{
+    repr::Repr,
+    sys::{map, page_size, report_map, unmap},
+    ErrorWith,
+};
+
+/// Attempt to move `x` to a fresh memory mapping,
+/// placing it as close as its alignment allows to a trailing `PROT_NONE` page,
+/// returning a wrapped `x` on failure.
+///
+/// Types aligned to more than a page are not supported, and always fail.
+/// Failures are reported as usual, e.g to the
+/// [OOM hook](crate::set_oom_hook), with the layout of the mapping.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn new<T>(x: T) -> Result<Guarded<T>, ErrorWith<T>> {
+    let page = page_size();
+    if align_of::<T>() > page {
+        crate::hook::report(Repr::of::<T>());
+        return Err(ErrorWith(x));
+    }
+    let data = match size_of::<T>().checked_add(page - 1) {
+        Some(it) => it & !(page - 1),
+        None => return Err(ErrorWith(x)),
+    };
+    let len = match data.checked_add(page) {
+        Some(it) => it,
+        None => return Err(ErrorWith(x)),
+    };
+    let base = match map(len) {
+        Some(it) => it,
+        None => {
+            report_map::<T>(len);
+            return Err(ErrorWith(x));
+        }
+    };
+    // SAFETY: the final page is within the mapping.
+    let guard = unsafe { base.as_ptr().add(data) };
+    // SAFETY: the final page is within the mapping.
+    if unsafe { libc::mprotect(guard.cast(), page, libc::PROT_NONE) } != 0 {
+        // SAFETY: we've just created this mapping.
+        unsafe { unmap(base, len) };
+        report_map::<T>(len);
+        return Err(ErrorWith(x));
+    }
+    let offset = (data - size_of::<T>()) & !(align_of::<T>() - 1);
+    // SAFETY:
+    // - the offset leaves room for the T before the guard page.
+    // - `base` is page aligned, and the offset is a multiple of T's alignment,
+    //   which is at most a page.
+    let ptr = unsafe { base.as_ptr().add(offset).cast::<T>() };
+    // SAFETY: as above, and `base` is non-null.
+    unsafe {
+        ptr.write(x);
+        Ok(Guarded {
+            ptr: NonNull::new_unchecked(ptr),
+            base,
+            len,
+        })
+    }
+}
+
+/// An owned `T` followed by a guard page, returned from [`new`].
+pub struct Guarded<T> {
+    ptr: NonNull<T>,
+    base: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: we uniquely own the value, like `Box<T>`.
+unsafe impl<T: Send> Send for Guarded<T> {}
+// SAFETY: we uniquely own the value, like `Box<T>`.
+unsafe impl<T: Sync> Sync for Guarded<T> {}
+
+impl<T> Guarded<T> {
+    /// Move the value out, unmapping its memory.
+    pub fn into_inner(self) -> T {
+        let this = core::mem::ManuallyDrop::new(self);
+        // SAFETY: the value is initialized, and we won't drop it again.
+        let x = unsafe { this.ptr.as_ptr().read() };
+        // SAFETY: we created this mapping, and won't use it again.
+        unsafe { unmap(this.base, this.len) };
+        x
+    }
+}
+
+impl<T> Deref for Guarded<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the value is initialized, and we own it.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for Guarded<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the value is initialized, and we own it uniquely.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for Guarded<T> {
+    fn drop(&mut self) {
+        // SAFETY: the value is initialized, and we won't use it again.
+        unsafe { ptr::drop_in_place(self.ptr.as_ptr()) };
+        // SAFETY: we created this mapping, and won't use it again.
+        unsafe { unmap(self.base, self.len) };
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Guarded<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
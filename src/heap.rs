@@ -0,0 +1,201 @@
+//! Boxes which fall back to secondary heaps,
+//! e.g external PSRAM when internal SRAM is exhausted.
+//!
+//! ```
+//! use std::alloc::System;
+//! use trybox::heap::{self, Chain, Heap};
+//!
+//! // Stands in for e.g an allocator for external PSRAM.
+//! static PSRAM: System = System;
+//! static CHAIN: Chain = Chain(&[&PSRAM]);
+//!
+//! heap::set_chain(&CHAIN);
+//! let internal = heap::new([0u8; 64]).unwrap();
+//! assert_eq!(internal.heap(), Heap::Global);
+//! // When the global allocator fails, the next heap in the chain is tried.
+//! let external = trybox::limit::scoped(0, || heap::new([0u8; 64])).unwrap();
+//! assert_eq!(external.heap(), Heap::Secondary(0));
+//! # heap::take_chain();
+//! ```
+//!
+//! A [`Box`] is always freed to the global allocator,
+//! so values which may live in a secondary heap are returned as a
+//! [`HeapBox`] instead, which records where it was allocated.
+
+use alloc::boxed::Box;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    fmt, mem,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+
+use crate::{
+    atomic::{AtomicPtr, Ordering},
+    raw,
+    repr::Repr,
+    Error, ErrorWith,
+};
+
+/// The heaps to try, in order, after the global allocator,
+/// see [`set_chain`].
+pub struct Chain(pub &'static [&'static (dyn GlobalAlloc + Sync)]);
+
+impl fmt::Debug for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chain").field("len", &self.0.len()).finish()
+    }
+}
+
+static CHAIN: AtomicPtr<Chain> = AtomicPtr::new(ptr::null_mut());
+
+/// Try the heaps in `chain` when the global allocator fails, for [`new`],
+/// replacing any previous chain.
+///
+/// Existing [`HeapBox`]es are still freed to the heap they came from.
+pub fn set_chain(chain: &'static Chain) {
+    CHAIN.store(chain as *const Chain as *mut Chain, Ordering::Release);
+}
+
+/// Remove the chain installed with [`set_chain`], returning it.
+pub fn take_chain() -> Option<&'static Chain> {
+    // SAFETY: only `&'static Chain`s are stored.
+    unsafe { CHAIN.swap(ptr::null_mut(), Ordering::AcqRel).as_ref() }
+}
+
+fn chain() -> &'static [&'static (dyn GlobalAlloc + Sync)] {
+    // SAFETY: only `&'static Chain`s are stored.
+    match unsafe { CHAIN.load(Ordering::Acquire).as_ref() } {
+        Some(chain) => chain.0,
+        None => &[],
+    }
+}
+
+/// Where the memory for a [`HeapBox`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Heap {
+    /// The global allocator, which is tried first.
+    Global,
+    /// The heap at this index in the [`Chain`].
+    Secondary(usize),
+}
+
+/// Attempt to box `x`, trying the global allocator,
+/// then each heap in the [`Chain`], if any.
+///
+/// A failure is only reported, e.g to the [OOM hook](crate::set_oom_hook),
+/// if every heap fails.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn new<T>(x: T) -> Result<HeapBox<T>, ErrorWith<T>> {
+    let (ptr, secondary) = match alloc::<T>() {
+        Ok(it) => it,
+        Err(_) => return Err(ErrorWith(x)),
+    };
+    // SAFETY: `ptr` was just allocated for a `T`.
+    unsafe { ptr.as_ptr().write(x) };
+    Ok(HeapBox { ptr, secondary })
+}
+
+type Secondary = Option<(usize, &'static (dyn GlobalAlloc + Sync))>;
+
+#[cfg_attr(feature = "location", track_caller)]
+fn alloc<T>() -> Result<(NonNull<T>, Secondary), Error> {
+    if let Some(ptr) = raw::imp(Layout::new::<T>(), false) {
+        return Ok((ptr.cast(), None));
+    }
+    for (index, heap) in chain().iter().enumerate() {
+        // SAFETY: the layout isn't zero-sized,
+        //         or the global allocator would have succeeded.
+        if let Some(ptr) = NonNull::new(unsafe { heap.alloc(Layout::new::<T>()) }) {
+            return Ok((ptr.cast(), Some((index, *heap))));
+        }
+    }
+    Err(crate::hook::report(Repr::of::<T>()))
+}
+
+/// Like a [`Box`], but may live in a secondary heap, returned from [`new`].
+pub struct HeapBox<T> {
+    ptr: NonNull<T>,
+    /// Kept in case the chain changes before we're dropped.
+    secondary: Secondary,
+}
+
+// SAFETY: we uniquely own the value, like `Box<T>`.
+unsafe impl<T: Send> Send for HeapBox<T> {}
+// SAFETY: we uniquely own the value, like `Box<T>`.
+unsafe impl<T: Sync> Sync for HeapBox<T> {}
+
+impl<T> HeapBox<T> {
+    /// Which heap the value lives in.
+    pub fn heap(&self) -> Heap {
+        match self.secondary {
+            Some((index, _)) => Heap::Secondary(index),
+            None => Heap::Global,
+        }
+    }
+    /// Convert to a [`Box`], if the value lives in the global allocator.
+    pub fn into_box(self) -> Result<Box<T>, Self> {
+        match self.secondary {
+            Some(_) => Err(self),
+            None => {
+                let ptr = self.ptr;
+                mem::forget(self);
+                // SAFETY: the global allocator is the one `Box` uses.
+                Ok(unsafe { Box::from_raw(ptr.as_ptr()) })
+            }
+        }
+    }
+    /// Move the value out, freeing the allocation.
+    pub fn into_inner(self) -> T {
+        // SAFETY: the value is initialized, and is freed without being dropped.
+        let x = unsafe { self.ptr.as_ptr().read() };
+        // SAFETY: as above.
+        unsafe { self.free() };
+        mem::forget(self);
+        x
+    }
+    /// Free the allocation, without dropping the value.
+    ///
+    /// # Safety
+    /// Must only be called once.
+    unsafe fn free(&self) {
+        let layout = Layout::new::<T>();
+        match self.secondary {
+            // SAFETY: the value was allocated by this heap, with this layout.
+            Some((_, heap)) => unsafe { heap.dealloc(self.ptr.as_ptr().cast(), layout) },
+            // SAFETY: the value was allocated by `raw::alloc_one`.
+            None => unsafe { raw::dealloc(self.ptr.cast(), layout) },
+        }
+    }
+}
+
+impl<T> Drop for HeapBox<T> {
+    fn drop(&mut self) {
+        // SAFETY: the value is initialized, and not used again.
+        unsafe {
+            ptr::drop_in_place(self.ptr.as_ptr());
+            self.free();
+        }
+    }
+}
+
+impl<T> Deref for HeapBox<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the value is initialized, and we own it.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for HeapBox<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the value is initialized, and we own it.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for HeapBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
@@ -0,0 +1,300 @@
+//! A global callback for allocation failures.
+//!
+//! ```
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//! static FAILURES: AtomicUsize = AtomicUsize::new(0);
+//!
+//! trybox::set_oom_hook(|_e| {
+//!     FAILURES.fetch_add(1, Ordering::Relaxed);
+//! });
+//! trybox::limit::scoped(0, || trybox::or_drop(1u8)).unwrap_err();
+//! assert_eq!(FAILURES.load(Ordering::Relaxed), 1);
+//! # trybox::take_oom_hook();
+//! ```
+
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+use core::ptr;
+
+use core::{alloc::Layout, fmt};
+
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+use crate::atomic::{AtomicPtr, Ordering};
+use crate::{repr::Repr, Error, Info};
+
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+static HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Install a callback for whenever an allocation through this crate fails,
+/// replacing any previous one.
+///
+/// This includes [`new`](crate::new), [`or_drop`](crate::or_drop),
+/// and the [`slice`](crate::slice) and [`raw`](crate::raw) functions,
+/// but not [`Error`]s created by hand with e.g [`Error::for_type`].
+///
+/// The hook may run on any thread.
+/// Allocation failures within the hook itself don't call it again
+/// on the same thread, with the `"std"` feature.
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+pub fn set_oom_hook(hook: fn(&Error)) {
+    HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Remove the callback installed with [`set_oom_hook`], returning it.
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+pub fn take_oom_hook() -> Option<fn(&Error)> {
+    get(HOOK.swap(ptr::null_mut(), Ordering::AcqRel))
+}
+
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+fn get(ptr: *mut ()) -> Option<fn(&Error)> {
+    match ptr.is_null() {
+        true => None,
+        // SAFETY: only `fn(&Error)`s are stored.
+        false => Some(unsafe { core::mem::transmute::<*mut (), fn(&Error)>(ptr) }),
+    }
+}
+
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+static MESSAGE: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// A function which writes the message for a failed allocation,
+/// see [`set_message_fn`].
+pub type MessageFn = fn(&Info, &mut fmt::Formatter<'_>) -> fmt::Result;
+
+/// Replace the [`Display`](fmt::Display) message of every [`Error`] and
+/// [`ErrorWith`](crate::ErrorWith), e.g to localize it.
+///
+/// ```
+/// use std::fmt;
+///
+/// fn spanish(info: &trybox::Info, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///     write!(f, "memoria insuficiente: {} bytes", info.layout.size())
+/// }
+///
+/// trybox::set_message_fn(spanish);
+/// let e = trybox::Error::for_type::<[u8; 64]>();
+/// assert_eq!(e.to_string(), "memoria insuficiente: 64 bytes");
+/// # trybox::take_message_fn();
+/// ```
+///
+/// The function replaces the whole message,
+/// including the system information with the `"meminfo"` feature,
+/// but the width, fill and alignment are still applied to its output.
+/// It should not allocate, so that errors can be formatted when memory is
+/// exhausted.
+///
+/// [`Error::with_units`], and the `"ufmt"` and `"defmt"` implementations,
+/// aren't affected.
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+pub fn set_message_fn(f: MessageFn) {
+    MESSAGE.store(f as *mut (), Ordering::Release);
+}
+
+/// Remove the function installed with [`set_message_fn`], returning it.
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+pub fn take_message_fn() -> Option<MessageFn> {
+    message(MESSAGE.swap(ptr::null_mut(), Ordering::AcqRel))
+}
+
+/// The function installed with [`set_message_fn`], if any.
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+pub(crate) fn message_fn() -> Option<MessageFn> {
+    message(MESSAGE.load(Ordering::Acquire))
+}
+
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+fn message(ptr: *mut ()) -> Option<MessageFn> {
+    match ptr.is_null() {
+        true => None,
+        // SAFETY: only `MessageFn`s are stored.
+        false => Some(unsafe { core::mem::transmute::<*mut (), MessageFn>(ptr) }),
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    any(target_has_atomic = "ptr", feature = "critical-section")
+))]
+std::thread_local! {
+    static IN_HOOK: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+}
+
+/// Report a failure with the error built by `f`:
+/// counting it with the `"metrics"` feature,
+/// then, unless it's rate limited with the `"std"` feature,
+/// logging with the `"log"` feature,
+/// emitting an event with the `"tracing"` feature,
+/// and calling the hook, if any.
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+pub(crate) fn notify(f: impl FnOnce() -> Error) {
+    #[cfg(feature = "metrics")]
+    let f = {
+        let e = f();
+        metrics::counter!("trybox.alloc_failures").increment(1);
+        metrics::counter!("trybox.failed_bytes").increment(e.size() as u64);
+        move || e
+    };
+    #[cfg(all(
+        feature = "std",
+        any(target_has_atomic = "ptr", feature = "critical-section")
+    ))]
+    if crate::rate::limited() {
+        let e = f();
+        match crate::rate::admit(&e) {
+            crate::rate::Admit::Suppress => return,
+            crate::rate::Admit::Report(0, _) => {}
+            crate::rate::Admit::Report(_suppressed, _over) => {
+                #[cfg(feature = "log")]
+                log::warn!(
+                    target: "trybox",
+                    "suppressed {_suppressed} further failures{} in the last {} s",
+                    ForType(&e),
+                    _over.as_secs()
+                );
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    target: "trybox",
+                    suppressed = _suppressed,
+                    r#type = e.type_name(),
+                    "suppressed {_suppressed} further failures{} in the last {} s",
+                    ForType(&e),
+                    _over.as_secs()
+                );
+            }
+        }
+        return emit(move || e);
+    }
+    emit(f)
+}
+
+/// The part of [`notify`] which is rate limited.
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+fn emit(f: impl FnOnce() -> Error) {
+    #[cfg(feature = "log")]
+    let f = {
+        let e = f();
+        log::error!(target: "trybox", "{e:#}");
+        move || e
+    };
+    // Events are recorded within the current span, for context.
+    #[cfg(feature = "tracing")]
+    let f = {
+        let e = f();
+        tracing::error!(
+            target: "trybox",
+            size = e.size(),
+            align = e.align(),
+            r#type = e.type_name(),
+            "{e:#}"
+        );
+        move || e
+    };
+    #[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+    if let Some(hook) = get(HOOK.load(Ordering::Acquire)) {
+        notify_cold(hook, f())
+    }
+    #[cfg(not(any(target_has_atomic = "ptr", feature = "critical-section")))]
+    let _ = f;
+}
+
+/// Prints ` for type {name}` if there is a name,
+/// otherwise ` of {size} bytes`.
+#[cfg(all(
+    feature = "std",
+    any(target_has_atomic = "ptr", feature = "critical-section"),
+    any(feature = "log", feature = "tracing")
+))]
+struct ForType<'a>(&'a Error);
+
+#[cfg(all(
+    feature = "std",
+    any(target_has_atomic = "ptr", feature = "critical-section"),
+    any(feature = "log", feature = "tracing")
+))]
+impl fmt::Display for ForType<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.type_name() {
+            Some(name) => f.write_fmt(format_args!(" for type {name}")),
+            None => f.write_fmt(format_args!(" of {} bytes", self.0.size())),
+        }
+    }
+}
+
+/// [`notify`] a failure to allocate for `repr`, returning the error.
+///
+/// This is outlined, so the failure path isn't duplicated for every type.
+#[cold]
+#[inline(never)]
+#[cfg_attr(feature = "location", track_caller)]
+pub(crate) fn report(repr: Repr) -> Error {
+    // Outside the closure, which can't track the caller.
+    let e = Error::new(repr);
+    notify(|| e);
+    e
+}
+
+/// [`notify`] a failure to allocate `layout`, returning the error.
+#[cold]
+#[inline(never)]
+#[cfg_attr(feature = "location", track_caller)]
+pub(crate) fn report_layout(layout: Layout) -> Error {
+    let e = Error::for_layout(layout);
+    notify(|| e);
+    e
+}
+
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+#[cold]
+#[inline(never)]
+fn notify_cold(hook: fn(&Error), e: Error) {
+    #[cfg(feature = "std")]
+    {
+        if IN_HOOK.with(|it| it.replace(true)) {
+            return;
+        }
+        struct Reset;
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                IN_HOOK.with(|it| it.set(false))
+            }
+        }
+        let _reset = Reset;
+        hook(&e)
+    }
+    #[cfg(not(feature = "std"))]
+    hook(&e)
+}
+
+/// Record a successful allocation of `layout`,
+/// for the `"stats"` and `"metrics-success"` features.
+#[inline(always)]
+pub(crate) fn success(layout: Layout) {
+    #[cfg(all(
+        feature = "stats",
+        any(target_has_atomic = "ptr", feature = "critical-section")
+    ))]
+    crate::stats::success(layout);
+    #[cfg(feature = "metrics-success")]
+    {
+        metrics::counter!("trybox.allocs").increment(1);
+        metrics::counter!("trybox.allocated_bytes").increment(layout.size() as u64);
+    }
+    let _ = layout;
+}
+
+/// Record a failed allocation of `layout`, for the `"stats"` feature.
+///
+/// Unlike [`notify`], this includes failures which aren't reported,
+/// e.g from [`new_silent`](crate::new_silent).
+#[inline(always)]
+pub(crate) fn failure(layout: Layout) {
+    #[cfg(all(
+        feature = "stats",
+        any(target_has_atomic = "ptr", feature = "critical-section")
+    ))]
+    crate::stats::failure(layout);
+    let _ = layout;
+}
@@ -0,0 +1,164 @@
+//! A string interner, whose storage grows fallibly.
+//!
+//! ```
+//! use trybox::intern::Interner;
+//!
+//! let mut idents = Interner::new();
+//! let x = idents.try_intern("x")?;
+//! let y = idents.try_intern("y")?;
+//! // Each string is stored once.
+//! assert_eq!(idents.try_intern("x")?, x);
+//! assert_ne!(x, y);
+//! assert_eq!(idents.resolve(y), Some("y"));
+//! assert_eq!(idents.get("z"), None);
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use alloc::boxed::Box;
+use core::{fmt, num::NonZeroU32};
+
+use crate::{slice, vec::TryVec, Error, ErrorKind, TryIntoBox as _};
+
+/// Identifies a string in an [`Interner`].
+///
+/// Symbols are a non-zero `u32`, so `Option<Symbol>` is four bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(NonZeroU32);
+
+impl Symbol {
+    /// The order in which the string was first interned, from zero.
+    pub fn index(self) -> usize {
+        self.0.get() as usize - 1
+    }
+}
+
+/// Deduplicates strings, each stored once as a `Box<str>`,
+/// returning a [`Symbol`] for each.
+///
+/// Strings are never removed, and symbols from one interner are
+/// meaningless in another.
+///
+/// Lookup is by [FNV-1a](crate::wire::type_hash), which is fast for short
+/// strings, but isn't resistant to deliberately colliding input.
+pub struct Interner {
+    strings: TryVec<Box<str>>,
+    /// An open-addressed table of symbols, whose length is a power of two.
+    /// At most half of the slots are occupied.
+    table: Box<[Option<Symbol>]>,
+}
+
+impl Interner {
+    /// Create an empty interner, which doesn't allocate until the first string.
+    pub fn new() -> Self {
+        Self {
+            strings: TryVec::new(),
+            // A dangling pointer, which doesn't allocate.
+            table: Box::new([]),
+        }
+    }
+    /// Attempt to intern `s`, returning the existing symbol if it's already
+    /// interned.
+    ///
+    /// On failure, the interner is unchanged.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_intern(&mut self, s: &str) -> Result<Symbol, Error> {
+        if let Some(it) = self.get(s) {
+            return Ok(it);
+        }
+        if self.len() >= u32::MAX as usize {
+            return Err(Error::for_type_with_kind::<Symbol>(
+                ErrorKind::LayoutOverflow,
+            ));
+        }
+        if self.len().saturating_add(1).saturating_mul(2) > self.table.len() {
+            self.grow()?;
+        }
+        // Reserve first, so the string isn't copied just to be dropped.
+        self.strings.try_reserve(1)?;
+        let boxed = s.try_into_box()?;
+        let symbol = symbol(self.len());
+        self.strings.try_push(boxed)?;
+        let slot = vacant(&self.table, s);
+        self.table[slot] = Some(symbol);
+        Ok(symbol)
+    }
+    /// The symbol for `s`, if it has been interned.
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        let mask = self.table.len().checked_sub(1)?;
+        let mut ix = hash(s) & mask;
+        loop {
+            match self.table[ix] {
+                Some(symbol) if *self.strings[symbol.index()] == *s => return Some(symbol),
+                Some(_) => ix = (ix + 1) & mask,
+                None => return None,
+            }
+        }
+    }
+    /// The string for `symbol`,
+    /// or [`None`] if there is none, e.g it's from a larger interner.
+    pub fn resolve(&self, symbol: Symbol) -> Option<&str> {
+        self.strings.get(symbol.index()).map(|it| &**it)
+    }
+    /// The number of distinct strings.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+    /// Whether there are no strings.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+    /// The strings and their symbols, in the order they were interned.
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &str)> + '_ {
+        self.strings
+            .iter()
+            .enumerate()
+            .map(|(ix, it)| (symbol(ix), &**it))
+    }
+    /// Double the table, rehashing every string.
+    #[cfg_attr(feature = "location", track_caller)]
+    fn grow(&mut self) -> Result<(), Error> {
+        let mut table = slice::from_fn(self.table.len().saturating_mul(2).max(8), |_| None)?;
+        for (ix, s) in self.strings.iter().enumerate() {
+            let slot = vacant(&table, s);
+            table[slot] = Some(symbol(ix));
+        }
+        self.table = table;
+        Ok(())
+    }
+}
+
+/// The symbol for the string at `ix`, which has been checked to fit.
+fn symbol(ix: usize) -> Symbol {
+    match NonZeroU32::new(ix as u32 + 1) {
+        Some(it) => Symbol(it),
+        None => unreachable!("too many strings"),
+    }
+}
+
+/// The slot where `s`, which isn't in `table`, would go.
+///
+/// `table` must have a vacant slot.
+fn vacant(table: &[Option<Symbol>], s: &str) -> usize {
+    let mask = table.len() - 1;
+    let mut ix = hash(s) & mask;
+    while table[ix].is_some() {
+        ix = (ix + 1) & mask;
+    }
+    ix
+}
+
+fn hash(s: &str) -> usize {
+    crate::wire::type_hash(s) as usize
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Interner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,229 @@
+//! Reading into fallibly allocated buffers.
+//!
+//! Allocation failures are reported as an [`io::Error`] of kind
+//! [`OutOfMemory`](io::ErrorKind::OutOfMemory),
+//! created with [`Error::into_io_error_static`](crate::Error::into_io_error_static),
+//! so reporting them doesn't allocate.
+//!
+//! ```
+//! let mut reader: &[u8] = b"hello, world";
+//! let hello = trybox::io::read_exact_boxed(&mut reader, 5)?;
+//! assert_eq!(&*hello, b"hello");
+//! let rest = trybox::io::read_to_boxed_slice(&mut reader)?;
+//! assert_eq!(&*rest, b", world");
+//! # Ok::<_, std::io::Error>(())
+//! ```
+//!
+//! For incremental reads, e.g of framed network messages, see [`Buffer`].
+
+use alloc::boxed::Box;
+use core::{fmt, mem::MaybeUninit, ptr, slice as raw_slice};
+use std::io::{self, Read};
+
+use crate::{bytes::Bytes, slice, Error, ErrorKind};
+
+/// Read until EOF, into a fallibly-growing buffer.
+///
+/// Like [`Read::read_to_end`], [`Interrupted`](io::ErrorKind::Interrupted)
+/// errors are retried.
+pub fn read_to_boxed_slice(mut reader: impl Read) -> io::Result<Box<[u8]>> {
+    let mut bytes = Bytes::new();
+    loop {
+        if bytes.spare_mut().is_empty() {
+            bytes.reserve(32).map_err(Error::into_io_error_static)?;
+        }
+        match reader.read(bytes.spare_mut()) {
+            Ok(0) => break,
+            Ok(n) => bytes.commit(n),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    bytes
+        .into_boxed_slice()
+        .map_err(Error::into_io_error_static)
+}
+
+/// Read exactly `len` bytes, allocating them up front.
+///
+/// See [`Read::read_exact`] for more.
+pub fn read_exact_boxed(mut reader: impl Read, len: usize) -> io::Result<Box<[u8]>> {
+    let mut buf = slice::zeroed(len).map_err(Error::into_io_error_static)?;
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// A read buffer, which separates the bytes which have been read (filled)
+/// from the spare capacity after them (unfilled).
+///
+/// Like [`BorrowedBuf`](https://doc.rust-lang.org/std/io/struct.BorrowedBuf.html),
+/// the spare capacity is only zeroed once,
+/// so repeated reads don't re-initialize it.
+///
+/// ```
+/// let mut reader: &[u8] = b"\x05hello, world";
+/// let mut buf = trybox::io::Buffer::with_capacity(4)?;
+/// while buf.filled().len() < 6 {
+///     // Grows fallibly when there's no spare capacity.
+///     buf.read_from(&mut reader)?;
+/// }
+/// let len = buf.filled()[0] as usize;
+/// assert_eq!(&buf.filled()[1..][..len], b"hello");
+/// buf.consume(1 + len);
+/// assert!(buf.filled().starts_with(b","));
+/// # Ok::<_, std::io::Error>(())
+/// ```
+pub struct Buffer {
+    buf: Box<[MaybeUninit<u8>]>,
+    filled: usize,
+    /// The bytes up to here are initialized,
+    /// and never less than `filled`.
+    init: usize,
+}
+
+impl Buffer {
+    /// Create an empty buffer, without allocating.
+    pub fn new() -> Self {
+        Self {
+            // A dangling pointer, which doesn't allocate.
+            buf: Box::new([]),
+            filled: 0,
+            init: 0,
+        }
+    }
+    /// Attempt to create an empty buffer with room for exactly `capacity`
+    /// bytes.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn with_capacity(capacity: usize) -> Result<Self, Error> {
+        Ok(Self {
+            buf: slice::uninit(capacity)?,
+            filled: 0,
+            init: 0,
+        })
+    }
+    /// The bytes which have been read.
+    pub fn filled(&self) -> &[u8] {
+        // SAFETY: the filled bytes are initialized.
+        unsafe { raw_slice::from_raw_parts(self.buf.as_ptr().cast(), self.filled) }
+    }
+    /// The bytes which have been read.
+    pub fn filled_mut(&mut self) -> &mut [u8] {
+        // SAFETY: the filled bytes are initialized.
+        unsafe { raw_slice::from_raw_parts_mut(self.buf.as_mut_ptr().cast(), self.filled) }
+    }
+    /// The spare capacity, to read into before calling [`advance`](Self::advance).
+    ///
+    /// This zeroes any of it which hasn't been initialized yet.
+    pub fn unfilled_mut(&mut self) -> &mut [u8] {
+        let uninit = &mut self.buf[self.init..];
+        // SAFETY: writing zeroes to memory we own.
+        unsafe { ptr::write_bytes(uninit.as_mut_ptr(), 0, uninit.len()) };
+        self.init = self.buf.len();
+        // SAFETY: the whole buffer is now initialized.
+        unsafe {
+            raw_slice::from_raw_parts_mut(
+                self.buf.as_mut_ptr().add(self.filled).cast(),
+                self.buf.len() - self.filled,
+            )
+        }
+    }
+    /// Mark the first `n` bytes of the [unfilled](Self::unfilled_mut) part as
+    /// filled.
+    ///
+    /// # Panics
+    /// If `n` is more than the unfilled bytes which have been initialized.
+    pub fn advance(&mut self, n: usize) {
+        assert!(
+            n <= self.init - self.filled,
+            "advanced past the initialized bytes"
+        );
+        self.filled += n;
+    }
+    /// Remove the first `n` filled bytes, moving the rest to the start.
+    ///
+    /// # Panics
+    /// If `n` is more than the filled bytes.
+    pub fn consume(&mut self, n: usize) {
+        assert!(n <= self.filled, "consumed more than was filled");
+        self.filled_mut().copy_within(n.., 0);
+        self.filled -= n;
+    }
+    /// Remove the filled bytes, keeping the capacity.
+    pub fn clear(&mut self) {
+        self.filled = 0;
+    }
+    /// The number of bytes which can be held without growing.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+    /// Attempt to ensure there are at least `additional` unfilled bytes,
+    /// typically doubling the capacity.
+    ///
+    /// The filled bytes are moved to a new allocation.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), Error> {
+        let required = match self.filled.checked_add(additional) {
+            Some(it) => it,
+            None => return Err(Error::for_type_with_kind::<u8>(ErrorKind::LayoutOverflow)),
+        };
+        if required <= self.capacity() {
+            return Ok(());
+        }
+        let mut buf = match slice::uninit_silent(required.max(self.capacity().saturating_mul(2))) {
+            Some(it) => it,
+            // Doubling may have been too greedy.
+            None => slice::uninit(required)?,
+        };
+        buf[..self.filled].copy_from_slice(&self.buf[..self.filled]);
+        self.buf = buf;
+        self.init = self.filled;
+        Ok(())
+    }
+    /// Read once from `reader` into the unfilled bytes,
+    /// first growing the buffer if there are none,
+    /// and returning the number of bytes read.
+    ///
+    /// Like [`Read::read_exact`], [`Interrupted`](io::ErrorKind::Interrupted)
+    /// errors are retried.
+    /// Allocation failures are reported as in the [module documentation](self).
+    pub fn read_from(&mut self, reader: &mut impl Read) -> io::Result<usize> {
+        if self.filled == self.capacity() {
+            self.try_reserve(32).map_err(Error::into_io_error_static)?;
+        }
+        loop {
+            match reader.read(self.unfilled_mut()) {
+                Ok(n) => {
+                    self.advance(n);
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    /// Attempt to convert the filled bytes to an exactly-sized `Box<[u8]>`,
+    /// which allocates unless the buffer is full.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_into_boxed_slice(self) -> Result<Box<[u8]>, Error> {
+        match self.filled == self.capacity() {
+            // SAFETY: every byte is filled, so initialized.
+            true => Ok(unsafe { Box::from_raw(Box::into_raw(self.buf) as *mut [u8]) }),
+            false => crate::TryIntoBox::try_into_box(self.filled()),
+        }
+    }
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buffer")
+            .field("filled", &self.filled())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
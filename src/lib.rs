@@ -22,13 +22,22 @@
 //! }
 //! ```
 //!
-//! Care has been taken to optimize the size of [`Error`] down to a single usize:
+//! Care has been taken to optimize the size of [`Error`] down to a single usize
+//! (unless the `"location"`, `"backtrace"`, `"meminfo"` or `"timestamp"`
+//! features are enabled):
 //! ```
 //! # use std::mem::size_of;
+//! # #[cfg(not(any(
+//! #     feature = "location",
+//! #     feature = "backtrace",
+//! #     feature = "meminfo",
+//! #     feature = "timestamp"
+//! # )))]
 //! assert_eq!(size_of::<trybox::Error>(), size_of::<usize>());
 //! ```
 //!
-//! And to provide ergonomic error messages:
+//! And to provide ergonomic error messages
+//! (the sizes are exact without the default `"humanize"` feature):
 //! ```text
 #![doc = include_str!("../tests/i32-error-message.expected")]
 //! ```
@@ -56,20 +65,127 @@
 //!     doesn't implement common error traits, and isn't strictly minimal.
 
 #![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(
+    feature = "nightly",
+    feature(allocator_api, error_generic_member_access)
+)]
 
 extern crate alloc;
 
 use alloc::{
-    alloc::{alloc, handle_alloc_error, Layout},
+    alloc::{handle_alloc_error, Layout},
     boxed::Box,
 };
-use core::{any, fmt, mem::MaybeUninit, ptr::NonNull};
+use core::{fmt, mem::MaybeUninit, pin::Pin, ptr::NonNull};
+
+pub mod aligned;
+#[cfg(feature = "nightly")]
+pub mod arc;
+pub mod arena;
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+mod atomic;
+pub mod batch;
+mod bytes;
+#[cfg(all(feature = "std", target_has_atomic = "ptr"))]
+pub mod cache;
+#[cfg(feature = "cap")]
+pub mod cap;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod convert;
+#[cfg(feature = "miette")]
+mod diagnostic;
+pub mod ext;
+pub mod format;
+#[cfg(all(feature = "mmap", unix))]
+pub mod guarded;
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+pub mod heap;
+mod hook;
+pub mod intern;
+#[cfg(feature = "std")]
+pub mod io;
+#[cfg(feature = "std")]
+pub mod limit;
+#[cfg(all(feature = "mmap", unix))]
+pub mod mapped;
+#[cfg(all(feature = "meminfo", target_os = "linux"))]
+mod meminfo;
+#[cfg(feature = "panic-free")]
+mod nopanic;
+#[cfg(all(feature = "numa", target_os = "linux"))]
+pub mod numa;
+pub mod prelude;
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+pub mod pressure;
+pub mod probe;
+#[cfg(all(
+    feature = "std",
+    any(target_has_atomic = "ptr", feature = "critical-section")
+))]
+mod rate;
+pub mod raw;
+mod repr;
+pub mod retry;
+pub mod ring;
+#[cfg(feature = "secure")]
+pub mod secure;
+pub mod segmented;
+#[cfg(feature = "serde")]
+mod ser;
+pub mod slab;
+pub mod slice;
+#[cfg(all(
+    feature = "stats",
+    any(target_has_atomic = "ptr", feature = "critical-section")
+))]
+pub mod stats;
+#[cfg(feature = "std")]
+mod stderr;
+pub mod string;
+#[cfg(all(feature = "unix", unix))]
+mod sys;
+#[cfg(all(
+    feature = "testing",
+    any(target_has_atomic = "ptr", feature = "critical-section")
+))]
+pub mod testing;
+#[cfg(feature = "backtrace")]
+mod trace;
+#[cfg(feature = "ufmt")]
+mod udisplay;
+pub mod vec;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod wasm;
+pub mod wire;
+
+pub use aligned::Aligned;
+pub use convert::TryIntoBox;
+pub use ext::ResultExt;
+pub use hook::MessageFn;
+#[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+pub use hook::{set_message_fn, set_oom_hook, take_message_fn, take_oom_hook};
+#[cfg(all(feature = "meminfo", target_os = "linux"))]
+pub use meminfo::SystemInfo;
+#[cfg(all(
+    feature = "std",
+    any(target_has_atomic = "ptr", feature = "critical-section")
+))]
+pub use rate::{set_oom_hook_with_rate_limit, set_oom_rate_limit};
+use repr::Repr;
+#[cfg(feature = "backtrace")]
+pub use trace::Backtrace;
+#[cfg(feature = "macros")]
+pub use trybox_macros::fallible;
+#[cfg(all(feature = "macros", feature = "testing", feature = "std"))]
+pub use trybox_macros::oom_test;
 
 /// Attempt to move `x` to a heap allocation,
 /// returning a wrapped `x` on failure.
 ///
 /// See [crate documentation](mod@self) for more.
 #[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
 pub fn new<T>(x: T) -> Result<Box<T>, ErrorWith<T>> {
     match imp(x) {
         Ok(it) => Ok(it),
@@ -83,125 +199,839 @@ pub fn new<T>(x: T) -> Result<Box<T>, ErrorWith<T>> {
 ///
 /// See [crate documentation](mod@self) for more.
 #[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
 pub fn or_drop<T>(x: T) -> Result<Box<T>, Error> {
-    match new(x) {
+    // Rather than `new`, to return the error passed to the hook.
+    match uninit::<T>() {
+        Ok(heap) => Ok(init(heap, x)),
+        Err(e) => Err(e),
+    }
+}
+
+/// The canonical spelling of a fallible [`Box::new`]:
+/// `boxed!(x)` is [`new(x)`](new), and `boxed!(drop x)` is
+/// [`or_drop(x)`](or_drop).
+///
+/// ```
+/// let kept = trybox::boxed!(1u8);
+/// assert_eq!(*kept?, 1);
+/// let dropped = trybox::boxed!(drop [0u8; 64]);
+/// assert_eq!(dropped?.len(), 64);
+/// # Ok::<_, trybox::Error>(())
+/// ```
+///
+/// This is what the `#[fallible]` attribute, with the `"macros"` feature,
+/// rewrites `Box::new(x)` to.
+#[macro_export]
+macro_rules! boxed {
+    (drop $x:expr) => {
+        $crate::or_drop($x)
+    };
+    ($x:expr) => {
+        $crate::new($x)
+    };
+}
+
+/// Like [`new`], but failures aren't reported to the
+/// [OOM hook](set_oom_hook), or logged with the `"log"` feature.
+#[inline(always)]
+pub fn new_silent<T>(x: T) -> Result<Box<T>, ErrorWith<T>> {
+    match uninit_silent::<T>() {
+        Some(heap) => Ok(init(heap, x)),
+        None => Err(ErrorWith(x)),
+    }
+}
+
+/// Like [`or_drop`], but failures aren't reported, see [`new_silent`].
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+pub fn or_drop_silent<T>(x: T) -> Result<Box<T>, Error> {
+    match new_silent(x) {
         Ok(it) => Ok(it),
         Err(e) => Err(e.without_payload()),
     }
 }
 
+/// Like [`new`], but also count the allocation under `tag`,
+/// with the `"stats"` feature.
+///
+/// See [`stats::tagged`] for the counts.
+/// Only the first [`stats::TAGS`] distinct tags are counted.
+#[cfg(all(
+    feature = "stats",
+    any(target_has_atomic = "ptr", feature = "critical-section")
+))]
 #[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+pub fn new_tagged<T>(tag: &'static str, x: T) -> Result<Box<T>, ErrorWith<T>> {
+    let res = new(x);
+    match res {
+        Ok(_) => stats::tagged_success(tag, Layout::new::<T>()),
+        Err(_) => stats::tagged_failure(tag, Layout::new::<T>()),
+    }
+    res
+}
+
+/// Like [`or_drop`], but also count the allocation under `tag`,
+/// see [`new_tagged`].
+#[cfg(all(
+    feature = "stats",
+    any(target_has_atomic = "ptr", feature = "critical-section")
+))]
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+pub fn or_drop_tagged<T>(tag: &'static str, x: T) -> Result<Box<T>, Error> {
+    let res = or_drop(x);
+    match res {
+        Ok(_) => stats::tagged_success(tag, Layout::new::<T>()),
+        Err(_) => stats::tagged_failure(tag, Layout::new::<T>()),
+    }
+    res
+}
+
+/// Attempt to allocate space for a `T`,
+/// only calling `f` to construct it if allocation succeeds.
+///
+/// ```
+/// let heaped = trybox::or_drop_with(|| [1u8; 64])?;
+/// assert_eq!(heaped[0], 1);
+/// # Ok::<_, trybox::Error>(())
+/// ```
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+pub fn or_drop_with<T>(f: impl FnOnce() -> T) -> Result<Box<T>, Error> {
+    match uninit::<T>() {
+        Ok(heap) => Ok(init(heap, f())),
+        Err(e) => Err(e),
+    }
+}
+
+/// Attempt to deep-clone a box,
+/// allocating before cloning the contents into place.
+///
+/// This works for sized `T: Clone`, slices of `T: Clone`, and [`str`],
+/// see [`TryIntoBox`].
+///
+/// ```
+/// let buffer = trybox::try_boxed_slice![0u8; 1 << 20]?;
+/// let copy = trybox::clone_box(&buffer)?;
+/// assert_eq!(buffer, copy);
+/// # Ok::<_, trybox::Error>(())
+/// ```
+#[allow(clippy::borrowed_box)]
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+pub fn clone_box<T: ?Sized>(x: &Box<T>) -> Result<Box<T>, Error>
+where
+    for<'a> &'a T: TryIntoBox<T>,
+{
+    TryIntoBox::try_into_box(&**x)
+}
+
+/// Like [`slice::from_fn`], but pin the slice,
+/// e.g for DMA descriptor rings which must not move.
+///
+/// ```
+/// let ring = trybox::pin_slice(16, |ix| [ix as u32; 4])?;
+/// assert_eq!(ring[15], [15; 4]);
+/// # Ok::<_, trybox::Error>(())
+/// ```
+#[cfg_attr(feature = "location", track_caller)]
+pub fn pin_slice<T>(len: usize, f: impl FnMut(usize) -> T) -> Result<Pin<Box<[T]>>, Error> {
+    slice::from_fn(len, f).map(Pin::from)
+}
+
+/// Attempt to copy `src` into a pinned slice, see [`pin_slice`].
+#[cfg_attr(feature = "location", track_caller)]
+pub fn pin_from_copy<T: Copy>(src: &[T]) -> Result<Pin<Box<[T]>>, Error> {
+    let mut boxed = slice::uninit::<T>(src.len())?;
+    // SAFETY: the allocations are distinct, and both have room for `len` `T`s.
+    unsafe { core::ptr::copy_nonoverlapping(src.as_ptr(), boxed.as_mut_ptr().cast(), src.len()) };
+    // SAFETY: every element has been initialized.
+    let boxed = unsafe { Box::from_raw(Box::into_raw(boxed) as *mut [T]) };
+    Ok(Pin::from(boxed))
+}
+
+/// Move `x` to a heap allocation, calling [`Error::handle`] on failure.
+///
+/// Like [`Box::new`], this typically aborts the process on failure,
+/// but the allocation is routed through this crate,
+/// e.g counting towards the thread's [`limit`] with the `"std"` feature.
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+pub fn or_handle<T>(x: T) -> Box<T> {
+    match or_drop(x) {
+        Ok(it) => it,
+        Err(e) => e.handle(),
+    }
+}
+
+/// Attempt to move `x` to a heap allocation aligned to at least `ALIGN` bytes,
+/// returning a wrapped `x` on failure.
+///
+/// The [`Error`] reflects the larger alignment.
+///
+/// See [`Aligned`] for more.
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+pub fn new_aligned<const ALIGN: usize, T>(
+    x: T,
+) -> Result<Box<Aligned<T, ALIGN>>, ErrorWith<Aligned<T, ALIGN>>>
+where
+    aligned::Alignment<ALIGN>: aligned::Supported,
+{
+    new(Aligned::new(x))
+}
+
+/// Box a zero-sized `x`, which never allocates, so can't fail.
+///
+/// ```
+/// #[derive(Debug, PartialEq)]
+/// struct Marker;
+///
+/// let boxed = trybox::new_zst(Marker);
+/// assert_eq!(*boxed, Marker);
+/// ```
+///
+/// Using this with a type which isn't zero-sized fails to compile:
+///
+/// ```compile_fail
+/// let _ = trybox::new_zst(1u8);
+/// ```
+///
+/// This can't be a `const fn`, since a [`Box`] can't be created in const
+/// contexts on stable or nightly Rust.
+#[inline(always)]
+pub fn new_zst<T>(x: T) -> Box<T> {
+    #[allow(clippy::let_unit_value)]
+    let () = Zst::<T>::ASSERT;
+    let ptr = NonNull::<T>::dangling().as_ptr();
+    // SAFETY:
+    // - a dangling, aligned pointer is valid for zero-sized writes.
+    // - Box::from_raw with such a pointer is explicitly called
+    //   out as safe in the Box docs.
+    unsafe {
+        ptr.write(x);
+        Box::from_raw(ptr)
+    }
+}
+
+/// Whether `T` is zero-sized, as a constant,
+/// so the allocation path is chosen at compile time.
+struct Zst<T>(core::marker::PhantomData<T>);
+
+impl<T> Zst<T> {
+    const IS: bool = core::mem::size_of::<T>() == 0;
+    const ASSERT: () = assert!(Self::IS, "the type must be zero-sized");
+}
+
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
 fn imp<T>(x: T) -> Result<Box<T>, T> {
-    let layout = Layout::for_value(&x);
-    match layout.size() == 0 {
-        true => {
-            let ptr = NonNull::<T>::dangling().as_ptr();
-            // SAFETY: This is recommended by the Box documentation
-            Ok(unsafe { Box::from_raw(ptr) })
-        }
-        false => {
-            // SAFETY: We've checked layout to be non-empty, above.
-            let ptr = unsafe { alloc(layout) }.cast::<T>();
-            match ptr.is_null() {
-                true => Err(x),
-                false => {
-                    // SAFETY:
-                    // - we've called GlobalAlloc::alloc above.
-                    // - Box::from_raw with such a pointer is explicitly called
-                    //   out as safe in the Box docs.
-                    let mut heap = unsafe { Box::<MaybeUninit<T>>::from_raw(ptr.cast()) };
-                    heap.write(x);
-                    // SAFETY: we've written an initialized T to the memory.
-                    Ok(unsafe { Box::from_raw(Box::into_raw(heap).cast()) })
-                }
-            }
-        }
+    match uninit::<T>() {
+        Ok(heap) => Ok(init(heap, x)),
+        Err(_) => Err(x),
+    }
+}
+
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+pub(crate) fn uninit<T>() -> Result<Box<MaybeUninit<T>>, Error> {
+    match uninit_silent::<T>() {
+        Some(heap) => Ok(heap),
+        None => Err(hook::report(Repr::of::<T>())),
+    }
+}
+
+/// Like [`uninit`], but doesn't report failures.
+#[inline(always)]
+fn uninit_silent<T>() -> Option<Box<MaybeUninit<T>>> {
+    let ptr = match Zst::<T>::IS {
+        true => NonNull::<T>::dangling().cast(),
+        false => raw::nonzero(Layout::new::<T>(), false)?,
+    };
+    // SAFETY:
+    // - the pointer is either dangling for a ZST, or from
+    //   GlobalAlloc::alloc with T's layout.
+    // - Box::from_raw with such a pointer is explicitly called
+    //   out as safe in the Box docs.
+    Some(unsafe { Box::from_raw(ptr.as_ptr().cast()) })
+}
+
+#[inline(always)]
+pub(crate) fn init<T>(heap: Box<MaybeUninit<T>>, x: T) -> Box<T> {
+    // Write through the raw pointer,
+    // rather than `MaybeUninit::write` on the box,
+    // which may leave an extra copy of a large `T` on the stack.
+    let ptr = Box::into_raw(heap).cast::<T>();
+    // SAFETY:
+    // - `ptr` is from a box, so is valid and aligned for writes.
+    // - we've written an initialized T to the memory.
+    unsafe {
+        ptr.write(x);
+        Box::from_raw(ptr)
     }
 }
 
 /// Represents an allocation failure from [`or_drop`].
 ///
 /// Designed to be small and propogatable.
+///
+/// Neither the [`Display`](fmt::Display) nor [`Debug`](fmt::Debug)
+/// implementations allocate, see [`Error::write_to`].
+///
+/// Errors compare equal if their [`Info`] is equal,
+/// regardless of where they were created (see the `"location"` feature).
+#[derive(Clone, Copy)]
 pub struct Error {
-    // This could be replaced by `&'static Info` once type_name is a const fn
-    info: fn() -> Info,
+    repr: Repr,
+    #[cfg(feature = "location")]
+    location: &'static core::panic::Location<'static>,
+    #[cfg(feature = "backtrace")]
+    backtrace: usize,
+    #[cfg(all(feature = "meminfo", target_os = "linux"))]
+    system_info: Option<SystemInfo>,
+    #[cfg(feature = "timestamp")]
+    timestamp: std::time::SystemTime,
+}
+
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        self.info() == other.info()
+    }
+}
+
+impl Eq for Error {}
+
+impl core::hash::Hash for Error {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.info().hash(state)
+    }
 }
 
 impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Info { layout, name } = self.info();
+        let Info { layout, name, kind } = self.info();
         let mut d = f.debug_struct("Error");
-        d.field("layout", &layout).field("name", &name);
+        d.field("kind", &kind).field("layout", &layout);
+        if let Some(name) = name {
+            d.field("name", &name);
+        }
+        #[cfg(feature = "location")]
+        d.field("location", &self.location);
+        #[cfg(feature = "backtrace")]
+        d.field("backtrace", &self.backtrace());
+        #[cfg(all(feature = "meminfo", target_os = "linux"))]
+        d.field("system_info", &self.system_info);
+        #[cfg(feature = "timestamp")]
+        d.field("timestamp", &self.timestamp);
         d.finish()
     }
 }
 
 impl fmt::Display for Error {
+    /// The alternate flag (`{:#}`) prints the exact size, see [`Units::Exact`],
+    /// and the system information with the `"meminfo"` feature, if any.
+    ///
+    /// The width, fill and alignment are respected, e.g `{:<60}`.
+    /// The message may be replaced with [`set_message_fn`].
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write_info(self.info(), f)
+        pad(f, |f| {
+            #[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+            if let Some(message) = hook::message_fn() {
+                return message(&self.info(), f);
+            }
+            write_info(self.info(), Units::from_flags(f), f)?;
+            #[cfg(all(feature = "meminfo", target_os = "linux"))]
+            if let (true, Some(info)) = (f.alternate(), self.system_info) {
+                if let Some(available) = info.available {
+                    f.write_fmt(format_args!(", while {available} bytes were available"))?;
+                }
+                if let Some(resident) = info.resident {
+                    f.write_fmt(format_args!(
+                        ", with {resident} bytes resident for this process"
+                    ))?;
+                }
+            }
+            Ok(())
+        })
     }
 }
 
-fn write_info(info: Info, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    let Info { layout, name } = info;
+/// Sizes and alignments are emitted as integers, for the host to render.
+///
+/// Type names aren't known until monomorphization,
+/// so can't be interned, and are sent as strings.
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        let info = self.info();
+        match (info.kind, info.name) {
+            (ErrorKind::Exhausted, Some(name)) => defmt::write!(
+                f,
+                "memory allocation of {=usize} bytes with alignment {=usize} (for type {=str}) failed",
+                info.layout.size(),
+                info.layout.align(),
+                name
+            ),
+            (ErrorKind::Exhausted, None) => defmt::write!(
+                f,
+                "memory allocation of {=usize} bytes with alignment {=usize} failed",
+                info.layout.size(),
+                info.layout.align()
+            ),
+            (ErrorKind::LayoutOverflow, Some(name)) => defmt::write!(
+                f,
+                "memory allocation of an array of {=str} failed: the size overflowed",
+                name
+            ),
+            (ErrorKind::LayoutOverflow, None) => {
+                defmt::write!(f, "memory allocation failed: the size overflowed")
+            }
+            (ErrorKind::Alignment, Some(name)) => defmt::write!(
+                f,
+                "memory allocation for type {=str} failed: the alignment is unsupported",
+                name
+            ),
+            (ErrorKind::Alignment, None) => {
+                defmt::write!(f, "memory allocation failed: the alignment is unsupported")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ErrorKind {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match self {
+            ErrorKind::Exhausted => defmt::write!(f, "Exhausted"),
+            ErrorKind::LayoutOverflow => defmt::write!(f, "LayoutOverflow"),
+            ErrorKind::Alignment => defmt::write!(f, "Alignment"),
+        }
+    }
+}
+
+/// How to render the size of a failed allocation,
+/// see [`Error::with_units`].
+///
+/// Without the `"humanize"` feature, all units are rendered as
+/// [`Units::Exact`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Units {
+    /// Powers of 1024, e.g `memory allocation of 2.44 kibibytes ...`.
+    ///
+    /// This is the default.
+    Binary,
+    /// Powers of 1000, e.g `memory allocation of 2.50 kilobytes ...`.
+    Decimal,
+    /// The exact number of bytes, and the alignment,
+    /// e.g `memory allocation of 2500 bytes with alignment 1 ...`.
+    ///
+    /// This is used for the alternate form (`{:#}`).
+    Exact,
+}
 
-    let mut size = layout.size() as f64;
-    let mut prefix = "";
-    let boundary = 1024.0;
-    for next in [
-        "kibi", "mebi", "gibi", "tebi", "pebi", "exbi", "zebi", "yobi",
-    ] {
-        if size <= boundary {
-            break;
+impl Default for Units {
+    fn default() -> Self {
+        Self::Binary
+    }
+}
+
+impl Units {
+    fn from_flags(f: &fmt::Formatter<'_>) -> Self {
+        match f.alternate() {
+            true => Self::Exact,
+            false => Self::Binary,
+        }
+    }
+    /// The unit boundary and SI/IEC prefixes, unless sizes are exact.
+    #[inline]
+    fn prefixes(self) -> Option<(u64, [&'static str; 8])> {
+        match self {
+            #[cfg(feature = "humanize")]
+            Units::Binary => Some((
+                1024,
+                [
+                    "kibi", "mebi", "gibi", "tebi", "pebi", "exbi", "zebi", "yobi",
+                ],
+            )),
+            #[cfg(feature = "humanize")]
+            Units::Decimal => Some((
+                1000,
+                [
+                    "kilo", "mega", "giga", "tera", "peta", "exa", "zetta", "yotta",
+                ],
+            )),
+            _ => None,
         }
-        size /= boundary;
-        prefix = next;
     }
-    let precision = match fract(size) == 0.0 {
-        true => 0,
-        false => 2,
+}
+
+/// An [`Error`] or [`ErrorWith`] rendered with particular [`Units`],
+/// returned from [`Error::with_units`] and [`ErrorWith::with_units`].
+#[derive(Debug, Clone, Copy)]
+pub struct WithUnits {
+    info: Info,
+    units: Units,
+}
+
+impl fmt::Display for WithUnits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        pad(f, |f| write_info(self.info, self.units, f))
+    }
+}
+
+/// Write with `write`, padded to `f`'s width with its fill and alignment,
+/// like a [`str`], without allocating.
+///
+/// The message is formatted twice: once to measure it, and again to `f`.
+fn pad(
+    f: &mut fmt::Formatter<'_>,
+    write: impl Fn(&mut fmt::Formatter<'_>) -> fmt::Result,
+) -> fmt::Result {
+    let width = match f.width() {
+        Some(it) => it,
+        None => return write(f),
     };
-    f.write_fmt(format_args!(
-        "memory allocation of {size:.precision$} {prefix}bytes (for type {name}) failed",
-    ))
+    let write = Unpadded(write);
+    let alternate = f.alternate();
+    let mut count = Count(0);
+    write.emit(&mut count, alternate)?;
+    let padding = width.saturating_sub(count.0);
+    let (before, after) = match f.align() {
+        Some(fmt::Alignment::Right) => (padding, 0),
+        Some(fmt::Alignment::Center) => (padding / 2, padding - padding / 2),
+        Some(fmt::Alignment::Left) | None => (0, padding),
+    };
+    let fill = f.fill();
+    for _ in 0..before {
+        fmt::Write::write_char(f, fill)?;
+    }
+    write.emit(f, alternate)?;
+    for _ in 0..after {
+        fmt::Write::write_char(f, fill)?;
+    }
+    Ok(())
+}
+
+/// Calls the function with a fresh [`fmt::Formatter`], without a width.
+struct Unpadded<F>(F);
+
+impl<F: Fn(&mut fmt::Formatter<'_>) -> fmt::Result> Unpadded<F> {
+    fn emit(&self, w: &mut dyn fmt::Write, alternate: bool) -> fmt::Result {
+        match alternate {
+            true => w.write_fmt(format_args!("{self:#}")),
+            false => w.write_fmt(format_args!("{self}")),
+        }
+    }
+}
+
+impl<F: Fn(&mut fmt::Formatter<'_>) -> fmt::Result> fmt::Display for Unpadded<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.0)(f)
+    }
+}
+
+/// Counts the [`char`]s written, for [`pad`].
+struct Count(usize);
+
+impl fmt::Write for Count {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 = self.0.saturating_add(s.chars().count());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "panic-free")]
+fn write_info(info: Info, units: Units, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    nopanic::write_info(info, units, f)
+}
+
+#[cfg(not(feature = "panic-free"))]
+fn write_info(info: Info, units: Units, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(reason) = info.kind.reason() {
+        return match (info.kind, info.name) {
+            (ErrorKind::LayoutOverflow, Some(name)) => f.write_fmt(format_args!(
+                "memory allocation of an array of {name} failed: {reason}"
+            )),
+            (_, Some(name)) => f.write_fmt(format_args!(
+                "memory allocation for type {name} failed: {reason}"
+            )),
+            (_, None) => f.write_fmt(format_args!("memory allocation failed: {reason}")),
+        };
+    }
+    match units.prefixes() {
+        #[cfg(feature = "humanize")]
+        Some((boundary, prefixes)) => write_humanized(info, boundary, prefixes, f),
+        _ => write_exact(info, f),
+    }
 }
 
-/// `no_std` version of [`f64::fract`]
-const fn fract(x: f64) -> f64 {
-    x - trunc(x)
+#[cfg(not(feature = "panic-free"))]
+fn write_exact(info: Info, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let Info { layout, name, .. } = info;
+    let (size, align) = (layout.size(), layout.align());
+    match name {
+        Some(name) => f.write_fmt(format_args!(
+            "memory allocation of {size} bytes with alignment {align} (for type {name}) failed",
+        )),
+        None => f.write_fmt(format_args!(
+            "memory allocation of {size} bytes with alignment {align} failed",
+        )),
+    }
 }
 
-/// `no_std` version of [`f64::trunc`]
-// https://github.com/rust-lang/libm/blob/754daced79e320c6bc6d2a666a99a60a742c42c4/src/math/trunc.rs#L3-L33
-const fn trunc(x: f64) -> f64 {
-    // let x1p120 = f64::from_bits(0x4770000000000000); // 0x1p120f === 2 ^ 120
+#[cfg(all(feature = "humanize", not(feature = "panic-free")))]
+fn write_humanized(
+    info: Info,
+    boundary: u64,
+    prefixes: [&'static str; 8],
+    f: &mut fmt::Formatter<'_>,
+) -> fmt::Result {
+    let Info { layout, name, .. } = info;
+    let (amount, prefix) = Amount::scale(layout.size(), boundary, prefixes);
+    let aligned = AlignedTo(overaligned(layout));
+    match name {
+        Some(name) => f.write_fmt(format_args!(
+            "memory allocation of {amount} {prefix}bytes{aligned} (for type {name}) failed",
+        )),
+        None => f.write_fmt(format_args!(
+            "memory allocation of {amount} {prefix}bytes{aligned} failed",
+        )),
+    }
+}
 
-    let mut i: u64 = x.to_bits();
-    let e: i64 = (i >> 52 & 0x7ff) as i64 - 0x3ff + 12;
-    let m: u64 = -1i64 as u64 >> e;
+/// The alignment that the system allocator typically guarantees,
+/// e.g 16 bytes on 64-bit platforms.
+#[cfg(feature = "humanize")]
+const DEFAULT_ALIGN: usize = 2 * core::mem::size_of::<usize>();
 
-    if e >= 52 + 12 {
-        return x;
+/// The alignment of `layout`, if it's larger than the [`DEFAULT_ALIGN`],
+/// so worth mentioning in the humanized message.
+#[cfg(feature = "humanize")]
+#[inline]
+fn overaligned(layout: Layout) -> Option<usize> {
+    match layout.align() > DEFAULT_ALIGN {
+        true => Some(layout.align()),
+        false => None,
     }
+}
+
+/// Prints ` aligned to {align}`, if there is an alignment.
+#[cfg(all(feature = "humanize", not(feature = "panic-free")))]
+struct AlignedTo(Option<usize>);
 
-    if (i & m) == 0 {
-        return x;
+#[cfg(all(feature = "humanize", not(feature = "panic-free")))]
+impl fmt::Display for AlignedTo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(align) => f.write_fmt(format_args!(" aligned to {align}")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `NonZeroU64::MIN` isn't in our MSRV.
+#[cfg(feature = "humanize")]
+const ONE: core::num::NonZeroU64 = match core::num::NonZeroU64::new(1) {
+    Some(it) => it,
+    None => unreachable!(),
+};
+
+/// A fixed-point number, printed with two decimal places if it isn't whole.
+#[cfg(feature = "humanize")]
+struct Amount {
+    whole: u64,
+    hundredths: Option<u64>,
+}
+
+#[cfg(feature = "humanize")]
+impl Amount {
+    /// Express `size` in the largest unit it exceeds.
+    #[inline]
+    fn scale(size: usize, boundary: u64, prefixes: [&'static str; 8]) -> (Self, &'static str) {
+        // Integer arithmetic only, to avoid pulling in float formatting on
+        // soft-float targets.
+        let size = size as u64;
+        let mut unit = ONE;
+        let mut prefix = "";
+        for next in prefixes {
+            match unit
+                .get()
+                .checked_mul(boundary)
+                .and_then(core::num::NonZeroU64::new)
+            {
+                Some(larger) if size > larger.get() => {
+                    unit = larger;
+                    prefix = next;
+                }
+                _ => break,
+            }
+        }
+        (Self::new(size, unit), prefix)
+    }
+    /// `size / unit`, rounding to two decimal places, ties to even.
+    ///
+    /// `unit` is non-zero so that the divisions can't panic.
+    #[inline]
+    fn new(size: u64, unit: core::num::NonZeroU64) -> Self {
+        let whole = size / unit;
+        let mut rem = size % unit;
+        if rem == 0 {
+            return Self {
+                whole,
+                hundredths: None,
+            };
+        }
+        // Long division, which can't overflow as `unit` is at most 1024^6.
+        let mut hundredths = 0;
+        for _ in 0..2 {
+            rem *= 10;
+            hundredths = hundredths * 10 + rem / unit;
+            rem %= unit.get();
+        }
+        let round_up = match (rem * 2).cmp(&unit.get()) {
+            core::cmp::Ordering::Less => false,
+            core::cmp::Ordering::Equal => hundredths % 2 == 1,
+            core::cmp::Ordering::Greater => true,
+        };
+        if round_up {
+            hundredths += 1;
+        }
+        Self {
+            whole: whole + hundredths / 100,
+            hundredths: Some(hundredths % 100),
+        }
+    }
+}
+
+#[cfg(all(feature = "humanize", not(feature = "panic-free")))]
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.hundredths {
+            Some(hundredths) => f.write_fmt(format_args!("{}.{hundredths:02}", self.whole)),
+            None => f.write_fmt(format_args!("{}", self.whole)),
+        }
     }
-    // force_eval!(x + x1p120); // don't care about signalling
-    i &= !m;
-    f64::from_bits(i)
 }
 
 #[cfg(not(feature = "std"))]
-impl core::error::Error for Error {}
+impl core::error::Error for Error {
+    #[cfg(feature = "nightly")]
+    fn provide<'a>(&'a self, request: &mut core::error::Request<'a>) {
+        provide(self.info(), request)
+    }
+}
 
 #[cfg(feature = "std")]
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    /// Provides the [`Info`], [`Layout`], [`ErrorKind`], and type name
+    /// (as a `&str`) with the `"nightly"` feature.
+    #[cfg(feature = "nightly")]
+    fn provide<'a>(&'a self, request: &mut std::error::Request<'a>) {
+        provide(self.info(), request);
+        #[cfg(feature = "backtrace")]
+        if let Some(backtrace) = self.backtrace() {
+            request.provide_value(backtrace);
+        }
+    }
+}
+
+#[cfg(feature = "nightly")]
+fn provide<'a>(info: Info, request: &mut core::error::Request<'a>) {
+    request
+        .provide_value(info)
+        .provide_value(info.layout)
+        .provide_value(info.kind);
+    if let Some(name) = info.name {
+        request.provide_ref::<str>(name);
+    }
+}
 
 impl Error {
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline(always)]
+    fn new(repr: Repr) -> Self {
+        Self {
+            repr,
+            #[cfg(feature = "location")]
+            location: core::panic::Location::caller(),
+            #[cfg(feature = "backtrace")]
+            backtrace: trace::capture(),
+            #[cfg(all(feature = "meminfo", target_os = "linux"))]
+            system_info: match repr.meta() {
+                Ok(meta) if meta.kind != ErrorKind::Exhausted => None,
+                _ => Some(meminfo::capture()),
+            },
+            #[cfg(feature = "timestamp")]
+            timestamp: std::time::SystemTime::now(),
+        }
+    }
+    /// Create an error representing the failure to allocate a `T`,
+    /// e.g for custom allocation routines.
+    ///
+    /// ```
+    /// let e = trybox::Error::for_type::<u64>();
+    /// # #[cfg(not(feature = "strip-type-names"))]
+    /// assert_eq!(e.type_name(), Some("u64"));
+    /// ```
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn for_type<T>() -> Self {
+        Self::new(Repr::of::<T>())
+    }
+    /// Create an error of the given [`ErrorKind`] for a `T`.
+    ///
+    /// For [`ErrorKind::LayoutOverflow`], `T` should be the element type of
+    /// the array that was too large.
+    ///
+    /// ```
+    /// # use trybox::{Error, ErrorKind};
+    /// let e = Error::for_type_with_kind::<u64>(ErrorKind::LayoutOverflow);
+    /// assert_eq!(e.kind(), ErrorKind::LayoutOverflow);
+    /// ```
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn for_type_with_kind<T>(kind: ErrorKind) -> Self {
+        Self::new(Repr::of_kind::<T>(kind))
+    }
+    /// Create an error of the given [`ErrorKind`],
+    /// without a type or a meaningful layout.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn for_kind(kind: ErrorKind) -> Self {
+        Self::new(Repr::untyped(kind))
+    }
+    /// Create an error representing the failure to allocate `layout`,
+    /// for allocations which don't correspond to a single type,
+    /// e.g from [`raw::alloc`].
+    ///
+    /// The size is rounded up to a multiple of the alignment,
+    /// and very large sizes (more than a quarter of the address space) are
+    /// saturated, so that the error is a single word.
+    ///
+    /// ```
+    /// # use core::alloc::Layout;
+    /// let layout = Layout::from_size_align(4096, 64).unwrap();
+    /// assert_eq!(trybox::Error::for_layout(layout).layout(), layout);
+    /// ```
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn for_layout(layout: Layout) -> Self {
+        Self::new(Repr::from_layout(layout))
+    }
+    /// Get all the information about the failed allocation,
+    /// e.g for custom error messages or metrics.
     #[inline(always)]
-    fn info(&self) -> Info {
-        (self.info)()
+    pub fn info(&self) -> Info {
+        match self.repr.meta() {
+            Ok(meta) => Info {
+                layout: meta.layout,
+                name: meta.name.map(|it| it()),
+                kind: meta.kind,
+            },
+            Err(layout) => Info {
+                layout,
+                name: None,
+                kind: ErrorKind::Exhausted,
+            },
+        }
     }
     /// Call [`handle_alloc_error`], typically aborting the process.
     ///
@@ -210,11 +1040,172 @@ impl Error {
     pub fn handle(self) -> ! {
         handle_alloc_error(self.layout())
     }
+    /// Render this error to `w`, e.g a fixed-size buffer.
+    ///
+    /// Formatting an [`Error`] never allocates, so this is safe to use when
+    /// memory is exhausted, unlike e.g [`to_string`](alloc::string::ToString),
+    /// which allocates a [`String`].
+    ///
+    /// This is equivalent to `write!(w, "{}", self)`.
+    ///
+    /// With the `"panic-free"` feature, the message is instead written
+    /// straight to `w` without the formatting machinery,
+    /// ignoring any [`set_message_fn`],
+    /// so this can't panic unless `w` does.
+    pub fn write_to<W: fmt::Write + ?Sized>(&self, w: &mut W) -> fmt::Result {
+        #[cfg(feature = "panic-free")]
+        return nopanic::write_info(self.info(), Units::Binary, w);
+        #[cfg(not(feature = "panic-free"))]
+        w.write_fmt(format_args!("{self}"))
+    }
+    /// Write this error and a newline straight to stderr,
+    /// using a buffer on the stack.
+    ///
+    /// Unlike [`eprintln!`](std::eprintln), this doesn't take the stderr
+    /// lock (on unix), so is suitable for last-ditch diagnostics when memory
+    /// is exhausted.
+    #[cfg(feature = "std")]
+    pub fn eprint(&self) -> std::io::Result<()> {
+        stderr::print(format_args!("{self}"))
+    }
+    /// Create an [`OutOfMemory`](std::io::ErrorKind::OutOfMemory) error,
+    /// without allocating.
+    ///
+    /// Unlike the [`From`] implementation, this never tries to preserve
+    /// `self` as the [source](std::error::Error::source).
+    /// Stable Rust can't attach a custom message to an [`std::io::Error`]
+    /// without boxing it, so the message is that of the
+    /// [`ErrorKind`](std::io::ErrorKind).
+    ///
+    /// ```
+    /// let e = trybox::Error::for_type::<u8>().into_io_error_static();
+    /// assert_eq!(e.kind(), std::io::ErrorKind::OutOfMemory);
+    /// assert!(e.get_ref().is_none());
+    /// ```
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    pub fn into_io_error_static(self) -> std::io::Error {
+        std::io::Error::from(std::io::ErrorKind::OutOfMemory)
+    }
+    /// Encode this error as a small fixed-size record,
+    /// see [`wire`] for more.
+    pub fn encode(&self) -> [u8; wire::LEN] {
+        wire::Record::from(*self).encode()
+    }
+    /// Get the reason that the allocation failed.
+    #[inline(always)]
+    pub fn kind(&self) -> ErrorKind {
+        self.info().kind
+    }
     /// Get the [`Layout`] that corresponds to the failed allocation.
+    ///
+    /// For [`ErrorKind::LayoutOverflow`], this is the layout of a single
+    /// element, and for [`ErrorKind::Alignment`] it is meaningless.
     #[inline(always)]
     pub fn layout(&self) -> Layout {
         self.info().layout
     }
+    /// The size of the failed allocation, see [`Error::layout`].
+    #[inline(always)]
+    pub fn size(&self) -> usize {
+        self.layout().size()
+    }
+    /// The alignment of the failed allocation, see [`Error::layout`].
+    #[inline(always)]
+    pub fn align(&self) -> usize {
+        self.layout().align()
+    }
+    /// A numeric code for the type that failed to be allocated,
+    /// for tagging events without strings.
+    ///
+    /// This is the 64-bit FNV-1a hash of the [`type_name`](Error::type_name),
+    /// as computed by [`wire::type_hash`], or `0` if there is no type name.
+    ///
+    /// ```
+    /// let e = trybox::Error::for_type::<u64>();
+    /// # #[cfg(not(feature = "strip-type-names"))]
+    /// assert_eq!(e.code(), trybox::wire::type_hash("u64"));
+    /// ```
+    pub fn code(&self) -> u64 {
+        self.type_name().map(wire::type_hash).unwrap_or(0)
+    }
+    /// Render this error with the given [`Units`].
+    ///
+    /// ```
+    /// # use trybox::{Error, ErrorWith, Units};
+    /// let e = Error::from(ErrorWith([0u8; 2500]));
+    /// # #[cfg(not(feature = "strip-type-names"))]
+    /// assert_eq!(
+    ///     e.with_units(Units::Exact).to_string(),
+    ///     "memory allocation of 2500 bytes with alignment 1 (for type [u8; 2500]) failed"
+    /// );
+    /// ```
+    pub fn with_units(&self, units: Units) -> WithUnits {
+        WithUnits {
+            info: self.info(),
+            units,
+        }
+    }
+    /// Get the location of the call which failed to allocate,
+    /// when the `"location"` feature is enabled.
+    ///
+    /// This points to the outermost caller in this crate's public API, or
+    /// where an [`ErrorWith`] was converted to an [`Error`], e.g with `?`.
+    ///
+    /// Note that this makes [`Error`] two words.
+    #[cfg(feature = "location")]
+    pub fn location(&self) -> &'static core::panic::Location<'static> {
+        self.location
+    }
+    /// Get the stack at the time this error was created, when the
+    /// `"backtrace"` feature is enabled, and `RUST_BACKTRACE` is set.
+    ///
+    /// Backtraces are stored in a small static buffer, without allocating,
+    /// so this returns [`None`] if it has since been overwritten by more
+    /// recent errors.
+    ///
+    /// Checking `RUST_BACKTRACE` may allocate, so call [`Backtrace::init`]
+    /// early in your program.
+    ///
+    /// Note that this makes [`Error`] at least two words.
+    #[cfg(feature = "backtrace")]
+    pub fn backtrace(&self) -> Option<Backtrace> {
+        Backtrace::get(self.backtrace)
+    }
+    /// Get the state of the system's memory when this error was created,
+    /// when the `"meminfo"` feature is enabled, on Linux.
+    ///
+    /// This is read from `/proc` without allocating,
+    /// and is [`None`] for errors other than [`ErrorKind::Exhausted`].
+    ///
+    /// Note that this makes [`Error`] several words.
+    #[cfg(all(feature = "meminfo", target_os = "linux"))]
+    pub fn system_info(&self) -> Option<SystemInfo> {
+        self.system_info
+    }
+    /// Get the time this error was created,
+    /// when the `"timestamp"` feature is enabled.
+    ///
+    /// This is when the allocation failed, even if the error is reported later,
+    /// except for [`ErrorWith`]s, which record the time they were converted to
+    /// an [`Error`].
+    ///
+    /// Note that this makes [`Error`] at least three words.
+    #[cfg(feature = "timestamp")]
+    pub fn timestamp(&self) -> std::time::SystemTime {
+        self.timestamp
+    }
+    /// Get the name of the type that failed to be allocated,
+    /// as returned by [`core::any::type_name`].
+    ///
+    /// This is [`None`] for allocations which don't correspond to a single
+    /// type, e.g from [`raw::alloc`],
+    /// and always [`None`] with the `"strip-type-names"` feature,
+    /// which keeps type names out of the binary.
+    #[inline(always)]
+    pub fn type_name(&self) -> Option<&'static str> {
+        self.info().name
+    }
 }
 
 #[cfg(feature = "std")]
@@ -237,6 +1228,18 @@ impl From<Error> for std::io::Error {
     }
 }
 
+/// With the `"nightly"` feature.
+///
+/// There is no conversion in the other direction, as an
+/// [`AllocError`](core::alloc::AllocError) carries no [`Layout`]:
+/// use [`Error::for_layout`] with the layout you requested.
+#[cfg(feature = "nightly")]
+impl From<Error> for core::alloc::AllocError {
+    fn from(_: Error) -> Self {
+        core::alloc::AllocError
+    }
+}
+
 #[cfg(feature = "std")]
 impl From<Error> for std::io::ErrorKind {
     fn from(_: Error) -> Self {
@@ -244,53 +1247,214 @@ impl From<Error> for std::io::ErrorKind {
     }
 }
 
-/// [`Layout`] is two words, but this function pointer is just one.
-trait Indirect: Sized {
-    fn info() -> Info {
-        Info {
-            layout: Layout::new::<Self>(),
-            name: any::type_name::<Self>(),
+/// The reason an allocation failed, returned from [`Error::kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The allocator refused the request,
+    /// typically because memory is exhausted.
+    Exhausted,
+    /// The size of the allocation overflowed,
+    /// e.g because an array was too long.
+    LayoutOverflow,
+    /// The alignment was invalid or unsupported.
+    Alignment,
+}
+
+impl ErrorKind {
+    /// Why the allocation failed, unless it was simply refused.
+    #[inline]
+    fn reason(self) -> Option<&'static str> {
+        match self {
+            ErrorKind::Exhausted => None,
+            ErrorKind::LayoutOverflow => Some("the size overflowed"),
+            ErrorKind::Alignment => Some("the alignment is unsupported"),
         }
     }
 }
-impl<T: Sized> Indirect for T {}
 
-#[derive(Debug, Clone, Copy)]
-struct Info {
-    layout: Layout,
-    name: &'static str,
+/// Information about a failed allocation,
+/// returned from [`Error::info`] and [`ErrorWith::info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Info {
+    /// See [`Error::layout`].
+    pub layout: Layout,
+    /// See [`Error::type_name`].
+    pub name: Option<&'static str>,
+    /// See [`Error::kind`].
+    pub kind: ErrorKind,
 }
 
 /// Represents the failure to allocate a particular object on the heap,
 /// returned from [`new`].
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ErrorWith<T>(pub T);
 
 impl<T> ErrorWith<T> {
-    fn info(&self) -> Info {
+    /// Get the object that failed to be allocated.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+    /// Borrow the object that failed to be allocated.
+    #[inline(always)]
+    pub fn payload(&self) -> &T {
+        &self.0
+    }
+    /// Mutably borrow the object that failed to be allocated.
+    #[inline(always)]
+    pub fn payload_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+    /// Attempt the allocation again, e.g after freeing some memory.
+    ///
+    /// This is equivalent to calling [`new`] with the payload.
+    #[inline(always)]
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn retry(self) -> Result<Box<T>, ErrorWith<T>> {
+        new(self.0)
+    }
+    /// Transform the object that failed to be allocated.
+    ///
+    /// ```
+    /// # use trybox::ErrorWith;
+    /// let e = ErrorWith([1u8, 2, 3]).map(|it| it.len());
+    /// assert_eq!(e.into_inner(), 3);
+    /// ```
+    #[inline(always)]
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> ErrorWith<U> {
+        ErrorWith(f(self.0))
+    }
+    /// See [`Error::info`].
+    pub fn info(&self) -> Info {
         Info {
             layout: Layout::for_value(&self.0),
-            name: any::type_name::<T>(),
+            name: repr::type_name::<T>(),
+            kind: ErrorKind::Exhausted,
+        }
+    }
+    /// See [`Error::with_units`].
+    pub fn with_units(&self, units: Units) -> WithUnits {
+        WithUnits {
+            info: self.info(),
+            units,
+        }
+    }
+    /// Render this error followed by the [`Debug`](fmt::Debug)
+    /// representation of the payload.
+    ///
+    /// The alternate flag (`{:#}`) prints the exact size,
+    /// and pretty-prints the payload.
+    /// The message is padded, and may be replaced, like [`ErrorWith`]'s.
+    ///
+    /// ```
+    /// # use trybox::ErrorWith;
+    /// let e = ErrorWith([1u8, 2]);
+    /// # #[cfg(all(feature = "humanize", not(feature = "strip-type-names")))]
+    /// assert_eq!(
+    ///     e.with_payload().to_string(),
+    ///     "memory allocation of 2 bytes (for type [u8; 2]) failed: [1, 2]"
+    /// );
+    /// ```
+    pub fn with_payload(&self) -> WithPayload<'_, T>
+    where
+        T: fmt::Debug,
+    {
+        WithPayload(self)
+    }
+    /// Create an [`OutOfMemory`](std::io::ErrorKind::OutOfMemory) error,
+    /// _trying_ to preserve `self`, including the payload,
+    /// as the [source](std::error::Error::source).
+    ///
+    /// If that allocation fails, this falls back to the [`From`]
+    /// implementation, which drops the payload.
+    ///
+    /// Note that [`std::io::Error::new`] makes a further, infallible,
+    /// allocation, see [`Error::into_io_error_static`] to avoid it.
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn into_io_error(self) -> std::io::Error
+    where
+        T: fmt::Debug + Send + Sync + 'static,
+    {
+        match new(self) {
+            Ok(source) => std::io::Error::new(
+                std::io::ErrorKind::OutOfMemory,
+                source as Box<dyn std::error::Error + Send + Sync>,
+            ),
+            Err(ErrorWith(this)) => this.into(),
         }
     }
+    #[cfg_attr(feature = "location", track_caller)]
     pub fn without_payload(self) -> Error {
-        Error { info: T::info }
+        Error::for_type::<T>()
     }
 }
 
 impl<T> fmt::Display for ErrorWith<T> {
+    /// The alternate flag (`{:#}`) prints the exact size, see [`Units::Exact`].
+    ///
+    /// The width, fill and alignment are respected, like [`Error`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        pad(f, |f| {
+            #[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+            if let Some(message) = hook::message_fn() {
+                return message(&self.info(), f);
+            }
+            write_info(self.info(), Units::from_flags(f), f)
+        })
+    }
+}
+
+impl<T> core::ops::Deref for ErrorWith<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> core::ops::DerefMut for ErrorWith<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// An [`ErrorWith`] rendered with its payload,
+/// returned from [`ErrorWith::with_payload`].
+#[derive(Debug, Clone, Copy)]
+pub struct WithPayload<'a, T>(&'a ErrorWith<T>);
+
+impl<T: fmt::Debug> fmt::Display for WithPayload<'_, T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write_info(self.info(), f)
+        // Respecting the message function and padding, like `ErrorWith`.
+        fmt::Display::fmt(self.0, f)?;
+        match f.alternate() {
+            true => f.write_fmt(format_args!(": {:#?}", self.0 .0)),
+            false => f.write_fmt(format_args!(": {:?}", self.0 .0)),
+        }
     }
 }
 
 #[cfg(not(feature = "std"))]
-impl<T: fmt::Debug> core::error::Error for ErrorWith<T> {}
+impl<T: fmt::Debug> core::error::Error for ErrorWith<T> {
+    #[cfg(feature = "nightly")]
+    fn provide<'a>(&'a self, request: &mut core::error::Request<'a>) {
+        provide(self.info(), request)
+    }
+}
 
 #[cfg(feature = "std")]
-impl<T: fmt::Debug> std::error::Error for ErrorWith<T> {}
+impl<T: fmt::Debug> std::error::Error for ErrorWith<T> {
+    /// See [`Error`]'s implementation.
+    #[cfg(feature = "nightly")]
+    fn provide<'a>(&'a self, request: &mut std::error::Request<'a>) {
+        provide(self.info(), request)
+    }
+}
 
 impl<T> From<ErrorWith<T>> for Error {
+    #[cfg_attr(feature = "location", track_caller)]
     fn from(value: ErrorWith<T>) -> Self {
         value.without_payload()
     }
@@ -300,6 +1464,9 @@ impl<T> From<ErrorWith<T>> for Error {
 impl<T> From<ErrorWith<T>> for std::io::Error {
     /// Create an [`OutOfMemory`](std::io::ErrorKind::OutOfMemory) error,
     /// possibly with an [`Error`] as the [source](std::error::Error::source).
+    ///
+    /// See [`ErrorWith::into_io_error`] to preserve the payload.
+    #[cfg_attr(feature = "location", track_caller)]
     fn from(value: ErrorWith<T>) -> Self {
         Error::from(value).into()
     }
@@ -315,7 +1482,205 @@ impl<T> From<ErrorWith<T>> for std::io::ErrorKind {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::{format, string::ToString};
 
+    #[cfg(not(any(
+        feature = "location",
+        feature = "backtrace",
+        feature = "meminfo",
+        feature = "timestamp"
+    )))]
     static_assertions::assert_eq_size!(Error, *const u8);
-    static_assertions::assert_impl_all!(Error: Send, Sync);
+    static_assertions::assert_impl_all!(Error: Send, Sync, Copy, Eq, core::hash::Hash);
+
+    #[test]
+    #[cfg(all(feature = "serde", not(feature = "strip-type-names")))]
+    fn serialize() {
+        assert_eq!(
+            serde_json::to_value(Error::for_type::<[u16; 3]>()).unwrap(),
+            serde_json::json!({
+                "kind": "Exhausted",
+                "size": 6,
+                "align": 2,
+                "type_name": "[u16; 3]",
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(ErrorWith(1u8)).unwrap()["payload"],
+            serde_json::json!(1)
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "ufmt", feature = "humanize"))]
+    fn udisplay() {
+        fn render(x: impl ufmt::uDisplay) -> alloc::string::String {
+            let mut s = alloc::string::String::new();
+            ufmt::uwrite!(s, "{}", x).unwrap();
+            s
+        }
+        for e in [
+            Error::for_type::<[u8; 2500]>(),
+            Error::for_type::<[u8; 2048]>(),
+            Error::for_layout(Layout::from_size_align(4096, 64).unwrap()),
+            Error::for_type_with_kind::<u8>(ErrorKind::LayoutOverflow),
+            Error::for_kind(ErrorKind::Alignment),
+        ] {
+            assert_eq!(render(e), e.to_string());
+            for units in [Units::Binary, Units::Decimal, Units::Exact] {
+                assert_eq!(render(e.with_units(units)), e.with_units(units).to_string());
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "strip-type-names"))]
+    fn type_name() {
+        assert_eq!(Error::for_type::<i32>().type_name(), Some("i32"));
+        assert_eq!(Error::for_layout(Layout::new::<i32>()).type_name(), None);
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "humanize",
+        not(feature = "strip-type-names"),
+        not(all(feature = "meminfo", target_os = "linux"))
+    ))]
+    fn units() {
+        let e = Error::for_type::<[u8; 2500]>();
+        assert_eq!(
+            format!("{e:#}"),
+            "memory allocation of 2500 bytes with alignment 1 (for type [u8; 2500]) failed"
+        );
+        assert_eq!(
+            e.with_units(Units::Decimal).to_string(),
+            "memory allocation of 2.50 kilobytes (for type [u8; 2500]) failed"
+        );
+        assert_eq!(e.with_units(Units::Binary).to_string(), e.to_string());
+    }
+
+    #[test]
+    #[cfg(feature = "humanize")]
+    fn overaligned() {
+        let e = Error::for_layout(Layout::from_size_align(4096, 4096).unwrap());
+        assert_eq!(
+            e.to_string(),
+            "memory allocation of 4 kibibytes aligned to 4096 failed"
+        );
+        let e = Error::for_layout(Layout::from_size_align(4096, 8).unwrap());
+        assert_eq!(e.to_string(), "memory allocation of 4 kibibytes failed");
+    }
+
+    #[test]
+    fn padding() {
+        let e = Error::for_kind(ErrorKind::Alignment);
+        let s = e.to_string();
+        assert_eq!(format!("{e:<60}|"), format!("{s:<60}|"));
+        assert_eq!(format!("{e:*^70}"), format!("{s:*^70}"));
+        assert_eq!(format!("{e:>60}"), format!("{s:>60}"));
+        assert_eq!(format!("{e:10}"), s);
+        let with = ErrorWith(1u8);
+        assert_eq!(format!("{with:#>80}"), format!("{:#>80}", with.to_string()));
+    }
+
+    #[test]
+    #[cfg(all(feature = "std", feature = "humanize"))]
+    fn amount_matches_float() {
+        for size in (0..20_000).chain([1 << 20, (1 << 20) + 5243, 1 << 40, 3 << 50]) {
+            let e = Error::for_layout(Layout::from_size_align(size, 1).unwrap());
+            let mut expected = size as f64;
+            let mut prefix = "";
+            for next in ["kibi", "mebi", "gibi", "tebi", "pebi", "exbi"] {
+                if expected <= 1024.0 {
+                    break;
+                }
+                expected /= 1024.0;
+                prefix = next;
+            }
+            let expected = match expected.fract() == 0.0 {
+                true => format!("memory allocation of {expected} {prefix}bytes failed"),
+                false => format!("memory allocation of {expected:.2} {prefix}bytes failed"),
+            };
+            assert_eq!(e.to_string(), expected);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "strip-type-names"))]
+    fn kind() {
+        let e = Error::for_type_with_kind::<u32>(ErrorKind::LayoutOverflow);
+        assert_eq!(
+            e.to_string(),
+            "memory allocation of an array of u32 failed: the size overflowed"
+        );
+        assert_ne!(e, Error::for_type::<u32>());
+        assert_eq!(
+            Error::for_kind(ErrorKind::Alignment).to_string(),
+            "memory allocation failed: the alignment is unsupported"
+        );
+        assert_eq!(Error::for_kind(ErrorKind::Alignment).type_name(), None);
+        assert_eq!(Error::for_type::<u32>().kind(), ErrorKind::Exhausted);
+    }
+
+    #[test]
+    #[cfg(not(feature = "strip-type-names"))]
+    fn eq() {
+        assert_eq!(Error::for_type::<i32>(), Error::for_type::<i32>());
+        assert_ne!(Error::for_type::<i32>(), Error::for_type::<u32>());
+        assert_ne!(
+            Error::for_type::<i32>(),
+            Error::for_layout(Layout::new::<i32>())
+        );
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "strip-type-names",
+        not(all(feature = "meminfo", target_os = "linux"))
+    ))]
+    fn strip_type_names() {
+        let e = Error::for_type::<[u8; 2500]>();
+        assert_eq!(e.type_name(), None);
+        assert_eq!(e.layout(), Layout::new::<[u8; 2500]>());
+        assert_eq!(
+            format!("{e:#}"),
+            "memory allocation of 2500 bytes with alignment 1 failed"
+        );
+        assert_eq!(ErrorWith(1u8).info().name, None);
+    }
+
+    #[test]
+    #[cfg(all(feature = "nightly", not(feature = "strip-type-names")))]
+    fn provide() {
+        use core::error::{request_ref, request_value, Error as _};
+        let e = Error::for_type::<u32>();
+        let dyn_e: &dyn core::error::Error = &e;
+        assert_eq!(request_value::<Layout>(dyn_e), Some(Layout::new::<u32>()));
+        assert_eq!(
+            request_value::<ErrorKind>(dyn_e),
+            Some(ErrorKind::Exhausted)
+        );
+        assert_eq!(request_ref::<str>(dyn_e), Some("u32"));
+        assert!(e.source().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "miette")]
+    fn diagnostic() {
+        use miette::Diagnostic as _;
+        let help = Error::for_type::<u8>().help().unwrap().to_string();
+        assert!(help.contains("smaller batches"), "{help}");
+    }
+
+    #[test]
+    fn layout_roundtrip() {
+        for (size, align) in [(0, 1), (1, 1), (3, 1), (8, 8), (4096, 4096), (2500, 4)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            assert_eq!(Error::for_layout(layout).layout(), layout);
+        }
+        let padded = Layout::from_size_align(3, 4).unwrap();
+        assert_eq!(Error::for_layout(padded).layout(), padded.pad_to_align());
+        let huge = Layout::from_size_align(isize::MAX as usize, 1).unwrap();
+        assert!(Error::for_layout(huge).layout().size() <= huge.size());
+    }
 }
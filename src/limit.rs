@@ -0,0 +1,79 @@
+//! Per-thread caps on the memory allocated through this crate.
+//!
+//! ```
+//! let res = trybox::limit::scoped(16, || {
+//!     let small = trybox::or_drop([0u8; 8]);
+//!     let large = trybox::or_drop([0u8; 32]);
+//!     (small.is_ok(), large.is_ok())
+//! });
+//! assert_eq!(res, (true, false));
+//! ```
+
+use std::cell::Cell;
+
+std::thread_local! {
+    static REMAINING: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Run `f`, failing all allocations made by this crate on this thread
+/// once a total of `bytes` has been allocated.
+///
+/// The limit is a budget: freeing memory does not replenish it.
+/// Zero-sized allocations are not counted.
+///
+/// Scopes may be nested, in which case the inner scope is bounded by what
+/// remains of the outer one, and bytes spent in the inner scope count towards
+/// the outer one.
+pub fn scoped<F: FnOnce() -> R, R>(bytes: usize, f: F) -> R {
+    let budget = match REMAINING.with(Cell::get) {
+        Some(outer) => outer.min(bytes),
+        None => bytes,
+    };
+    let _restore = Restore {
+        outer: REMAINING.with(|it| it.replace(Some(budget))),
+        budget,
+    };
+    f()
+}
+
+/// The number of bytes that may still be allocated on this thread,
+/// or [`None`] if there is no limit in place.
+pub fn remaining() -> Option<usize> {
+    REMAINING.with(Cell::get)
+}
+
+/// Restores the outer scope on exit, including on panic.
+struct Restore {
+    outer: Option<usize>,
+    budget: usize,
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        REMAINING.with(|it| {
+            let spent = self.budget - it.get().unwrap_or(self.budget);
+            it.set(self.outer.map(|outer| outer - spent));
+        })
+    }
+}
+
+/// Deduct `size` from the budget, returning `false` if it would be exceeded.
+#[inline(always)]
+pub(crate) fn take(size: usize) -> bool {
+    REMAINING.with(|it| match it.get() {
+        Some(remaining) => match remaining.checked_sub(size) {
+            Some(left) => {
+                it.set(Some(left));
+                true
+            }
+            None => false,
+        },
+        None => true,
+    })
+}
+
+/// Return `size` to the budget after a failed allocation.
+#[inline(always)]
+pub(crate) fn refund(size: usize) {
+    REMAINING.with(|it| it.set(it.get().map(|remaining| remaining + size)))
+}
@@ -0,0 +1,159 @@
+//! Large buffers which fall back to memory mappings.
+//!
+//! ```
+//! let mut buf = trybox::mapped::new_slice(1 << 20)?;
+//! assert!(buf.iter().all(|it| *it == 0));
+//! buf[0] = 1;
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use core::{
+    alloc::Layout,
+    fmt,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    slice,
+};
+
+use crate::{
+    raw,
+    sys::{map, map_file, populate, unmap},
+    Error, ErrorKind,
+};
+
+/// Attempt to allocate a zeroed buffer of `len` bytes.
+///
+/// The following are tried in order:
+/// - the global allocator.
+/// - an anonymous memory mapping.
+/// - a mapping of an anonymous temporary file,
+///   which may spill to disk rather than swap.
+///
+/// If all fail, the [`Error`] from the global allocator is returned,
+/// and is the only failure reported, e.g to the
+/// [OOM hook](crate::set_oom_hook).
+#[cfg_attr(feature = "location", track_caller)]
+pub fn new_slice(len: usize) -> Result<MappedSlice, Error> {
+    let layout = match Layout::array::<u8>(len) {
+        Ok(it) => it,
+        Err(_) => return Err(Error::for_type_with_kind::<u8>(ErrorKind::LayoutOverflow)),
+    };
+    if let Some(ptr) = raw::imp(layout, true) {
+        return Ok(MappedSlice {
+            ptr,
+            len,
+            backing: Backing::Heap,
+        });
+    }
+    if let Some(ptr) = map(len) {
+        return Ok(MappedSlice {
+            ptr,
+            len,
+            backing: Backing::Anonymous,
+        });
+    }
+    if let Some(ptr) = map_file(len) {
+        return Ok(MappedSlice {
+            ptr,
+            len,
+            backing: Backing::File,
+        });
+    }
+    Err(crate::hook::report_layout(layout))
+}
+
+/// Where the memory for a [`MappedSlice`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backing {
+    /// The global allocator.
+    Heap,
+    /// An anonymous memory mapping.
+    Anonymous,
+    /// A mapping of an anonymous temporary file.
+    File,
+}
+
+/// A buffer of bytes, returned from [`new_slice`].
+pub struct MappedSlice {
+    ptr: NonNull<u8>,
+    len: usize,
+    backing: Backing,
+}
+
+// SAFETY: we uniquely own the allocation, like `Box<[u8]>`.
+unsafe impl Send for MappedSlice {}
+// SAFETY: we uniquely own the allocation, like `Box<[u8]>`.
+unsafe impl Sync for MappedSlice {}
+
+impl MappedSlice {
+    /// Where this buffer's memory came from:
+    /// [`Backing::Heap`] from the global allocator,
+    /// [`Backing::Anonymous`] from the anonymous mapping,
+    /// or [`Backing::File`] from the temporary file, see [`new_slice`].
+    pub fn backing(&self) -> Backing {
+        self.backing
+    }
+    /// Fault in every page of this buffer now,
+    /// so latency-critical code doesn't take page faults later.
+    ///
+    /// Returns an [`Error`] if the pages can't be backed by memory or disk,
+    /// on Linux 5.14 and later,
+    /// rather than raising `SIGBUS` on a later access to a [`Backing::File`].
+    /// Otherwise, each page is touched in turn.
+    ///
+    /// ```
+    /// let mut buf = trybox::mapped::new_slice(1 << 20)?;
+    /// buf.populate()?;
+    /// # Ok::<_, trybox::Error>(())
+    /// ```
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn populate(&mut self) -> Result<(), Error> {
+        // SAFETY: we uniquely own the allocation.
+        match unsafe { populate(self.ptr, self.len) } {
+            true => Ok(()),
+            false => {
+                // SAFETY: `new_slice` checked this layout.
+                let layout = unsafe { Layout::from_size_align_unchecked(self.len, 1) };
+                Err(crate::hook::report_layout(layout))
+            }
+        }
+    }
+}
+
+impl Deref for MappedSlice {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: we allocated and zeroed this many bytes.
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for MappedSlice {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: we allocated and zeroed this many bytes.
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for MappedSlice {
+    fn drop(&mut self) {
+        match self.backing {
+            // SAFETY: we allocated with this layout, and have not freed it.
+            Backing::Heap => unsafe {
+                raw::dealloc(self.ptr, Layout::from_size_align_unchecked(self.len, 1))
+            },
+            // SAFETY: we created this mapping, and won't use it again.
+            Backing::Anonymous | Backing::File => unsafe { unmap(self.ptr, self.len) },
+        }
+    }
+}
+
+impl fmt::Debug for MappedSlice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MappedSlice")
+            .field("ptr", &self.ptr)
+            .field("len", &self.len)
+            .field("backing", &self.backing)
+            .finish()
+    }
+}
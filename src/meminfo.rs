@@ -0,0 +1,56 @@
+//! Allocation-free snapshots of the system's memory, for the `"meminfo"` feature.
+
+use std::{fs::File, io::Read as _};
+
+/// The state of the system's memory when an [`Error`](crate::Error)
+/// was created, returned from [`Error::system_info`](crate::Error::system_info).
+///
+/// Each field is [`None`] if it couldn't be read from `/proc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct SystemInfo {
+    /// An estimate of the bytes available for new allocations,
+    /// from `MemAvailable` in `/proc/meminfo`.
+    pub available: Option<u64>,
+    /// The bytes of this process in physical memory,
+    /// from `VmRSS` in `/proc/self/status`.
+    pub resident: Option<u64>,
+}
+
+#[inline(never)]
+#[cold]
+pub(crate) fn capture() -> SystemInfo {
+    SystemInfo {
+        // This is the third line, so comes early.
+        available: read::<256>("/proc/meminfo", b"MemAvailable:"),
+        // This is typically a couple of dozen lines in.
+        resident: read::<2048>("/proc/self/status", b"VmRSS:"),
+    }
+}
+
+/// Find the `key:   1234 kB` line in the start of the file at `path`,
+/// returning the size in bytes.
+///
+/// Opening a short path uses a stack buffer, so doesn't allocate.
+fn read<const N: usize>(path: &str, key: &[u8]) -> Option<u64> {
+    let mut buf = [0; N];
+    let mut file = File::open(path).ok()?;
+    let mut len = 0;
+    while len < N {
+        match file.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(_) => return None,
+        }
+    }
+    // Skip the last line, which may have been cut off.
+    let complete = buf[..len].iter().rposition(|it| *it == b'\n')?;
+    let line = buf[..complete]
+        .split(|it| *it == b'\n')
+        .find_map(|line| line.strip_prefix(key))?;
+    let start = line.iter().position(|it| !it.is_ascii_whitespace())?;
+    let digits = line[start..].split(|it| *it == b' ').next()?;
+    let kib = core::str::from_utf8(digits).ok()?.parse::<u64>().ok()?;
+    kib.checked_mul(1024)
+}
@@ -0,0 +1,78 @@
+//! Rendering which can't panic, behind the `"panic-free"` feature.
+//!
+//! Messages match the [`Display`](core::fmt::Display) implementations,
+//! but are written piecewise with [`fmt::Write::write_str`],
+//! with integer-only formatting,
+//! rather than through [`format_args!`] and the formatting machinery,
+//! whose panics can't be ruled out.
+
+use core::fmt::{self, Write};
+
+use crate::{ErrorKind, Info, Units};
+
+pub(crate) fn write_info<W: Write + ?Sized>(info: Info, units: Units, w: &mut W) -> fmt::Result {
+    let Info { layout, name, kind } = info;
+    if let Some(reason) = kind.reason() {
+        match (kind, name) {
+            (ErrorKind::LayoutOverflow, Some(name)) => {
+                w.write_str("memory allocation of an array of ")?;
+                w.write_str(name)?;
+            }
+            (_, Some(name)) => {
+                w.write_str("memory allocation for type ")?;
+                w.write_str(name)?;
+            }
+            (_, None) => w.write_str("memory allocation")?,
+        }
+        w.write_str(" failed: ")?;
+        return w.write_str(reason);
+    }
+    w.write_str("memory allocation of ")?;
+    match units.prefixes() {
+        #[cfg(feature = "humanize")]
+        Some((boundary, prefixes)) => {
+            let (amount, prefix) = crate::Amount::scale(layout.size(), boundary, prefixes);
+            write_u64(w, amount.whole)?;
+            if let Some(hundredths) = amount.hundredths {
+                w.write_str(if hundredths < 10 { ".0" } else { "." })?;
+                write_u64(w, hundredths)?;
+            }
+            w.write_str(" ")?;
+            w.write_str(prefix)?;
+            w.write_str("bytes")?;
+            if let Some(align) = crate::overaligned(layout) {
+                w.write_str(" aligned to ")?;
+                write_u64(w, align as u64)?;
+            }
+        }
+        _ => {
+            write_u64(w, layout.size() as u64)?;
+            w.write_str(" bytes with alignment ")?;
+            write_u64(w, layout.align() as u64)?;
+        }
+    }
+    if let Some(name) = name {
+        w.write_str(" (for type ")?;
+        w.write_str(name)?;
+        w.write_str(")")?;
+    }
+    w.write_str(" failed")
+}
+
+/// Write `n` in decimal.
+pub(crate) fn write_u64<W: Write + ?Sized>(w: &mut W, mut n: u64) -> fmt::Result {
+    // Room for `u64::MAX`.
+    let mut buf = [0u8; 20];
+    let mut len = 0;
+    for digit in buf.iter_mut().rev() {
+        *digit = b'0' + (n % 10) as u8;
+        n /= 10;
+        len += 1;
+        if n == 0 {
+            break;
+        }
+    }
+    let digits = buf.get(buf.len() - len..).unwrap_or_default();
+    // SAFETY: the digits are ASCII.
+    w.write_str(unsafe { core::str::from_utf8_unchecked(digits) })
+}
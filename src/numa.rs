@@ -0,0 +1,200 @@
+//! Allocation on a particular NUMA node.
+//!
+//! ```
+//! match trybox::numa::new_on_node([0u64; 512], 0) {
+//!     Ok(boxed) => assert_eq!(boxed[0], 0),
+//!     Err(trybox::numa::Error::Alloc(_)) => {}
+//!     Err(trybox::numa::Error::Node { source, .. }) => {
+//!         println!("can't allocate on node 0: {source}")
+//!     }
+//! }
+//! ```
+
+use core::{
+    fmt,
+    mem::{align_of, size_of},
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+
+use crate::{
+    repr::Repr,
+    sys::{map, page_size, report_map, unmap},
+    ErrorWith,
+};
+
+/// The largest node number supported.
+pub const MAX_NODE: usize = NODEMASK_WORDS * BITS - 1;
+
+const NODEMASK_WORDS: usize = 16;
+// `c_ulong` is `usize` on every linux target.
+const BITS: usize = usize::BITS as usize;
+// The kernel reads every argument as an `unsigned long`, so a `usize`.
+const MPOL_BIND: usize = 2;
+const MPOL_MF_STRICT: usize = 1 << 0;
+const MPOL_MF_MOVE: usize = 1 << 1;
+
+/// Attempt to move `x` to memory bound to NUMA `node`,
+/// returning a wrapped `x` on failure.
+///
+/// Each value gets its own (page-granular) memory mapping,
+/// so this is best suited to large values.
+/// Types aligned to more than a page are not supported, and always fail.
+/// Allocation failures are reported as usual, e.g to the
+/// [OOM hook](crate::set_oom_hook), with the layout of the mapping.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn new_on_node<T>(x: T, node: usize) -> Result<NumaBox<T>, Error<T>> {
+    let page = page_size();
+    if align_of::<T>() > page {
+        crate::hook::report(Repr::of::<T>());
+        return Err(Error::Alloc(ErrorWith(x)));
+    }
+    if size_of::<T>() == 0 {
+        // We can't check the node, but nor do we need to.
+        core::mem::forget(x);
+        return Ok(NumaBox {
+            ptr: NonNull::dangling(),
+            len: 0,
+        });
+    }
+    let len = match size_of::<T>().checked_add(page - 1) {
+        Some(it) => it & !(page - 1),
+        None => return Err(Error::Alloc(ErrorWith(x))),
+    };
+    let base = match map(len) {
+        Some(it) => it,
+        None => {
+            report_map::<T>(len);
+            return Err(Error::Alloc(ErrorWith(x)));
+        }
+    };
+    if let Err(source) = bind(base, len, node) {
+        // SAFETY: we've just created this mapping.
+        unsafe { unmap(base, len) };
+        return Err(Error::Node { payload: x, source });
+    }
+    let ptr = base.cast::<T>();
+    // SAFETY: the mapping is large and aligned enough for a T.
+    unsafe { ptr.as_ptr().write(x) };
+    Ok(NumaBox { ptr, len })
+}
+
+fn bind(base: NonNull<u8>, len: usize, node: usize) -> std::io::Result<()> {
+    if node > MAX_NODE {
+        return Err(std::io::Error::from_raw_os_error(libc::EINVAL));
+    }
+    let mut mask = [0usize; NODEMASK_WORDS];
+    mask[node / BITS] |= 1 << (node % BITS);
+    // The kernel only reads `maxnode - 1` bits, the whole mask.
+    // SAFETY: the range is our mapping, and the mask is large enough for
+    //         `maxnode`.
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_mbind,
+            base.as_ptr(),
+            len,
+            MPOL_BIND,
+            mask.as_ptr(),
+            MAX_NODE + 2,
+            MPOL_MF_STRICT | MPOL_MF_MOVE,
+        )
+    };
+    match ret {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// An owned `T` bound to a NUMA node, returned from [`new_on_node`].
+pub struct NumaBox<T> {
+    ptr: NonNull<T>,
+    len: usize,
+}
+
+// SAFETY: we uniquely own the value, like `Box<T>`.
+unsafe impl<T: Send> Send for NumaBox<T> {}
+// SAFETY: we uniquely own the value, like `Box<T>`.
+unsafe impl<T: Sync> Sync for NumaBox<T> {}
+
+impl<T> NumaBox<T> {
+    /// Move the value out, unmapping its memory.
+    pub fn into_inner(self) -> T {
+        let this = core::mem::ManuallyDrop::new(self);
+        // SAFETY: the value is initialized, and we won't drop it again.
+        let x = unsafe { this.ptr.as_ptr().read() };
+        this.unmap();
+        x
+    }
+    fn unmap(&self) {
+        if self.len != 0 {
+            // SAFETY: we created this mapping, and won't use it again.
+            unsafe { unmap(self.ptr.cast(), self.len) };
+        }
+    }
+}
+
+impl<T> Deref for NumaBox<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the value is initialized, and we own it.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for NumaBox<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the value is initialized, and we own it uniquely.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for NumaBox<T> {
+    fn drop(&mut self) {
+        // SAFETY: the value is initialized, and we won't use it again.
+        unsafe { ptr::drop_in_place(self.ptr.as_ptr()) };
+        self.unmap();
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for NumaBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+/// Failure to create a [`NumaBox`].
+#[derive(Debug)]
+pub enum Error<T> {
+    /// The memory could not be allocated.
+    Alloc(ErrorWith<T>),
+    /// The memory could not be bound to the requested node,
+    /// e.g because it has no memory, or doesn't exist.
+    Node { payload: T, source: std::io::Error },
+}
+
+impl<T> Error<T> {
+    /// Recover the value.
+    pub fn into_inner(self) -> T {
+        match self {
+            Error::Alloc(ErrorWith(payload)) | Error::Node { payload, .. } => payload,
+        }
+    }
+}
+
+impl<T> fmt::Display for Error<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Alloc(e) => e.fmt(f),
+            Error::Node { .. } => f.write_str("failed to bind memory to NUMA node"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for Error<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Alloc(_) => None,
+            Error::Node { source, .. } => Some(source),
+        }
+    }
+}
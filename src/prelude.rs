@@ -0,0 +1,10 @@
+//! Extension traits, for glob importing.
+//!
+//! ```
+//! use trybox::prelude::*;
+//!
+//! let heaped = 1.try_boxed()?;
+//! # Ok::<_, trybox::ErrorWith<i32>>(())
+//! ```
+
+pub use crate::ext::{ResultExt, TryBoxed};
@@ -0,0 +1,239 @@
+//! Callbacks to shed memory when an allocation fails.
+//!
+//! When an allocation through this crate fails, each registered callback is
+//! called once, and the allocation is retried before an [`Error`](crate::Error)
+//! is returned.
+//! Caches and pools may use this to release memory cooperatively.
+//!
+//! Allocations refused by a [`limit`](crate::limit) don't call the callbacks,
+//! since releasing memory doesn't replenish the limit.
+//!
+//! With the `"windows"` feature on Windows, the callbacks may also be called
+//! when the system reports low memory, see `watch_low_memory`.
+//!
+//! ```
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//! static PURGES: AtomicUsize = AtomicUsize::new(0);
+//!
+//! let registration = trybox::pressure::register(|level| {
+//!     // e.g clear a cache of at least `level.bytes`.
+//!     PURGES.fetch_add(1, Ordering::Relaxed);
+//! })
+//! .expect("registry is full");
+//! registration.unregister();
+//! ```
+
+use core::{alloc::Layout, fmt, ptr};
+
+use crate::atomic::{AtomicPtr, Ordering};
+
+/// The maximum number of callbacks which may be registered at once.
+pub const CAPACITY: usize = 16;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+static CALLBACKS: [AtomicPtr<()>; CAPACITY] = [EMPTY; CAPACITY];
+
+/// Describes the allocation which failed, passed to [`register`]ed callbacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Level {
+    /// The size of the failed allocation,
+    /// as a hint for how much memory should be released.
+    ///
+    /// This is zero for notifications which aren't for a particular
+    /// allocation.
+    pub bytes: usize,
+}
+
+/// Call `callback` whenever an allocation through this crate fails,
+/// before retrying it.
+///
+/// Returns [`None`] if [`CAPACITY`] callbacks are already registered.
+///
+/// Callbacks may run on any thread, and concurrently.
+/// Allocation failures within a callback don't call the callbacks again
+/// on the same thread, with the `"std"` feature.
+pub fn register(callback: fn(Level)) -> Option<Registration> {
+    CALLBACKS.iter().enumerate().find_map(|(slot, it)| {
+        it.compare_exchange(
+            ptr::null_mut(),
+            callback as *mut (),
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        )
+        .ok()
+        .map(|_| Registration { slot })
+    })
+}
+
+/// A callback added with [`register`].
+#[must_use = "call `unregister` to remove the callback, or `forget` to keep it"]
+pub struct Registration {
+    slot: usize,
+}
+
+impl Registration {
+    /// Remove the callback.
+    pub fn unregister(self) {
+        CALLBACKS[self.slot].store(ptr::null_mut(), Ordering::Release)
+    }
+    /// Keep the callback registered for the rest of the program.
+    pub fn forget(self) {}
+}
+
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registration")
+            .field("slot", &self.slot)
+            .finish()
+    }
+}
+
+#[cfg(all(feature = "windows", windows))]
+pub use self::windows::{is_low, watch_low_memory};
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static IN_CALLBACK: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+}
+
+/// Call the registered callbacks for a failed allocation of `layout`,
+/// returning whether there were any, so whether it's worth retrying.
+#[cold]
+#[inline(never)]
+pub(crate) fn relieve(layout: Layout) -> bool {
+    #[cfg(feature = "std")]
+    {
+        if IN_CALLBACK.with(|it| it.replace(true)) {
+            return false;
+        }
+        struct Reset;
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                IN_CALLBACK.with(|it| it.set(false))
+            }
+        }
+        let _reset = Reset;
+        call(layout)
+    }
+    #[cfg(not(feature = "std"))]
+    call(layout)
+}
+
+fn call(layout: Layout) -> bool {
+    let level = Level {
+        bytes: layout.size(),
+    };
+    let mut called = false;
+    for it in &CALLBACKS {
+        let ptr = it.load(Ordering::Acquire);
+        if !ptr.is_null() {
+            // SAFETY: only `fn(Level)`s are stored.
+            let callback = unsafe { core::mem::transmute::<*mut (), fn(Level)>(ptr) };
+            callback(level);
+            called = true;
+        }
+    }
+    called
+}
+
+#[cfg(all(feature = "windows", windows))]
+mod windows {
+    use core::{
+        alloc::Layout,
+        ffi::c_void,
+        ptr,
+        sync::atomic::{AtomicPtr, Ordering},
+        time::Duration,
+    };
+    use std::io;
+
+    type Handle = *mut c_void;
+
+    const LOW_MEMORY_RESOURCE_NOTIFICATION: i32 = 0;
+    const INFINITE: u32 = u32::MAX;
+    const WAIT_OBJECT_0: u32 = 0;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateMemoryResourceNotification(kind: i32) -> Handle;
+        fn QueryMemoryResourceNotification(handle: Handle, state: *mut i32) -> i32;
+        fn WaitForSingleObject(handle: Handle, millis: u32) -> u32;
+        fn CloseHandle(handle: Handle) -> i32;
+    }
+
+    /// The low memory notification, created on first use and never closed.
+    static HANDLE: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+
+    fn handle() -> io::Result<Handle> {
+        let existing = HANDLE.load(Ordering::Acquire);
+        if !existing.is_null() {
+            return Ok(existing);
+        }
+        // SAFETY: FFI call with no preconditions.
+        let new = unsafe { CreateMemoryResourceNotification(LOW_MEMORY_RESOURCE_NOTIFICATION) };
+        if new.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        match HANDLE.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => Ok(new),
+            Err(existing) => {
+                // SAFETY: we created this handle, and haven't shared it.
+                unsafe { CloseHandle(new) };
+                Ok(existing)
+            }
+        }
+    }
+
+    /// Whether Windows currently reports that physical memory is low,
+    /// with `QueryMemoryResourceNotification`.
+    ///
+    /// This is advisory: allocations may still succeed while memory is low,
+    /// and fail while it isn't.
+    /// Returns `false` if the notification can't be created or queried.
+    pub fn is_low() -> bool {
+        let handle = match handle() {
+            Ok(it) => it,
+            Err(_) => return false,
+        };
+        let mut state = 0;
+        // SAFETY: the handle is valid, and `state` is valid for writes.
+        match unsafe { QueryMemoryResourceNotification(handle, &mut state) } {
+            0 => false,
+            _ => state != 0,
+        }
+    }
+
+    /// Call the [`register`](super::register)ed callbacks from a background
+    /// thread whenever Windows reports that physical memory is low,
+    /// so that load can be shed before allocations fail.
+    ///
+    /// While memory remains low, the callbacks are called again every
+    /// `interval`.
+    /// The callbacks are passed a [`Level`](super::Level) of zero bytes.
+    ///
+    /// Each call spawns a new thread, so this should typically be called once.
+    pub fn watch_low_memory(interval: Duration) -> io::Result<()> {
+        // Handles aren't `Send`, so the thread gets the cached handle itself.
+        handle()?;
+        std::thread::Builder::new()
+            .name("trybox-low-memory".into())
+            .spawn(move || loop {
+                let handle = match handle() {
+                    Ok(it) => it,
+                    Err(_) => return,
+                };
+                // SAFETY: the handle is valid, and never closed.
+                match unsafe { WaitForSingleObject(handle, INFINITE) } {
+                    WAIT_OBJECT_0 => {
+                        super::call(Layout::new::<()>());
+                        std::thread::sleep(interval)
+                    }
+                    _ => return,
+                }
+            })
+            .map(drop)
+    }
+}
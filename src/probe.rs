@@ -0,0 +1,39 @@
+//! Discover how much memory the allocator will currently grant.
+//!
+//! ```
+//! let buf = trybox::probe::largest_slice(1 << 20, 1024)?;
+//! assert!((1024..=1 << 20).contains(&buf.len()));
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use alloc::boxed::Box;
+
+use crate::{slice, Error};
+
+/// Attempt to allocate the largest zeroed slice of between `min` and `max`
+/// bytes (inclusive), using a binary search.
+///
+/// Since previous successful allocations are held until a larger one succeeds,
+/// this may find less memory than is actually available.
+///
+/// If `min > max`, `max` bytes are attempted.
+/// If `min` bytes cannot be allocated, that [`Error`] is returned,
+/// and is the only failure reported, e.g to the
+/// [OOM hook](crate::set_oom_hook).
+#[cfg_attr(feature = "location", track_caller)]
+pub fn largest_slice(max: usize, min: usize) -> Result<Box<[u8]>, Error> {
+    let min = min.min(max);
+    if let Some(it) = slice::zeroed_silent(max) {
+        return Ok(it);
+    }
+    let mut best = slice::zeroed(min)?;
+    let mut bad = max;
+    while bad - best.len() > 1 {
+        let mid = best.len() + (bad - best.len()) / 2;
+        match slice::zeroed_silent(mid) {
+            Some(it) => best = it,
+            None => bad = mid,
+        }
+    }
+    Ok(best)
+}
@@ -0,0 +1,161 @@
+//! Rate limiting of reported failures, see [`set_oom_rate_limit`].
+
+use core::time::Duration;
+use std::{sync::Once, time::Instant};
+
+use crate::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Error, Info,
+};
+
+/// The number of distinct types which are rate limited separately.
+/// Further types share the last slot.
+const SLOTS: usize = 32;
+
+/// In milliseconds, or zero if there is no limit.
+static INTERVAL: AtomicU64 = AtomicU64::new(0);
+
+struct Slot {
+    /// Zero if unclaimed, see [`key`].
+    key: AtomicUsize,
+    /// When a failure was last reported, from [`now`],
+    /// or zero if none has been.
+    last: AtomicU64,
+    suppressed: AtomicUsize,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const SLOT: Slot = Slot {
+    key: AtomicUsize::new(0),
+    last: AtomicU64::new(0),
+    suppressed: AtomicUsize::new(0),
+};
+
+static TABLE: [Slot; SLOTS] = [SLOT; SLOTS];
+
+/// Report at most one failure per type every `interval`,
+/// to the [OOM hook](crate::set_oom_hook), and with the `"log"` and
+/// `"tracing"` features.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// trybox::set_oom_rate_limit(Duration::from_secs(10));
+/// ```
+///
+/// When a failure is next reported after some were suppressed,
+/// the number suppressed is also logged, e.g
+/// `suppressed 4182 further failures for type X in the last 10 s`.
+/// Metrics and [`stats`](crate::stats) still count every failure.
+///
+/// Types are told apart by name, or by size with the `"strip-type-names"`
+/// feature, and raw allocations by size.
+/// Only the first 32 distinct types are limited separately.
+///
+/// A zero `interval` removes the limit, which is the default.
+pub fn set_oom_rate_limit(interval: Duration) {
+    let millis = interval.as_millis().min(u64::MAX as u128) as u64;
+    INTERVAL.store(millis, Ordering::Relaxed)
+}
+
+/// Install `hook`, like [`set_oom_hook`](crate::set_oom_hook),
+/// and limit reported failures like [`set_oom_rate_limit`].
+///
+/// ```
+/// use std::time::Duration;
+///
+/// trybox::set_oom_hook_with_rate_limit(
+///     |e| eprintln!("{e}"),
+///     Duration::from_secs(10),
+/// );
+/// # trybox::take_oom_hook();
+/// # trybox::set_oom_rate_limit(Duration::ZERO);
+/// ```
+///
+/// The limit also applies to the `"log"` and `"tracing"` features,
+/// and remains after the hook is removed, until it is changed with
+/// [`set_oom_rate_limit`].
+pub fn set_oom_hook_with_rate_limit(hook: fn(&Error), interval: Duration) {
+    set_oom_rate_limit(interval);
+    crate::set_oom_hook(hook);
+}
+
+/// Whether there is a limit in place.
+#[inline(always)]
+pub(crate) fn limited() -> bool {
+    INTERVAL.load(Ordering::Relaxed) != 0
+}
+
+/// Whether a failure should be reported.
+pub(crate) enum Admit {
+    /// Report it, having suppressed this many since the last report,
+    /// over this long.
+    Report(usize, Duration),
+    Suppress,
+}
+
+/// Decide whether to report `e`, counting it if not.
+#[cold]
+#[inline(never)]
+pub(crate) fn admit(e: &Error) -> Admit {
+    let interval = INTERVAL.load(Ordering::Relaxed);
+    let now = now();
+    let slot = slot(key(&e.info()));
+    let last = slot.last.load(Ordering::Relaxed);
+    let elapsed = now.saturating_sub(last);
+    if (last != 0 && elapsed < interval)
+        || slot
+            .last
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+    {
+        slot.suppressed.fetch_add(1, Ordering::Relaxed);
+        return Admit::Suppress;
+    }
+    Admit::Report(
+        slot.suppressed.swap(0, Ordering::Relaxed),
+        Duration::from_millis(elapsed),
+    )
+}
+
+/// The milliseconds since the first failure was admitted, plus one,
+/// so that it's non-zero.
+///
+/// Measured with [`Instant`], so that changes to the system clock don't
+/// stop or flood reports.
+fn now() -> u64 {
+    // `OnceLock` requires Rust 1.70.
+    static INIT: Once = Once::new();
+    static mut ORIGIN: Option<Instant> = None;
+    // SAFETY: `ORIGIN` is only written once, synchronized by `INIT`.
+    INIT.call_once(|| unsafe { ORIGIN = Some(Instant::now()) });
+    // SAFETY: as above, and `ORIGIN` is no longer written.
+    match unsafe { *core::ptr::addr_of!(ORIGIN) } {
+        Some(origin) => origin.elapsed().as_millis() as u64 + 1,
+        None => 1,
+    }
+}
+
+/// A non-zero key for the type of a failure.
+fn key(info: &Info) -> usize {
+    match info.name {
+        Some(name) => name.as_ptr() as usize,
+        None => info.layout.size(),
+    }
+    .max(1)
+}
+
+/// Find or claim the slot for `key`, or the last slot if the table is full.
+fn slot(key: usize) -> &'static Slot {
+    for slot in &TABLE {
+        match slot
+            .key
+            .compare_exchange(0, key, Ordering::Relaxed, Ordering::Relaxed)
+        {
+            Ok(_) => return slot,
+            Err(it) if it == key => return slot,
+            Err(_) => {}
+        }
+    }
+    &TABLE[SLOTS - 1]
+}
@@ -0,0 +1,182 @@
+//! Fallible allocation of raw memory for arbitrary [`Layout`]s.
+//!
+//! ```
+//! # use core::alloc::Layout;
+//! let layout = Layout::from_size_align(100, 8).unwrap();
+//! let ptr = trybox::raw::alloc(layout)?;
+//! // SAFETY: `ptr` was returned from `alloc` with the same layout.
+//! unsafe { trybox::raw::dealloc(ptr.cast(), layout) };
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use alloc::alloc::Layout;
+use core::ptr::{self, NonNull};
+
+use crate::{repr::Repr, Error, ErrorKind};
+
+/// Attempt to allocate a block of memory fitting `layout`.
+///
+/// Zero-sized layouts do not touch the allocator,
+/// and return a dangling, suitably aligned pointer.
+///
+/// The memory is uninitialized.
+/// It should be freed with [`dealloc`], using the same `layout`.
+#[inline]
+#[cfg_attr(feature = "location", track_caller)]
+pub fn alloc(layout: Layout) -> Result<NonNull<[u8]>, Error> {
+    slice(layout, false)
+}
+
+/// Like [`alloc`](fn@alloc), but the memory is zeroed.
+#[inline]
+#[cfg_attr(feature = "location", track_caller)]
+pub fn alloc_zeroed(layout: Layout) -> Result<NonNull<[u8]>, Error> {
+    slice(layout, true)
+}
+
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+fn slice(layout: Layout, zeroed: bool) -> Result<NonNull<[u8]>, Error> {
+    match imp(layout, zeroed) {
+        Some(ptr) => {
+            let slice = ptr::slice_from_raw_parts_mut(ptr.as_ptr(), layout.size());
+            // SAFETY: `ptr` is non-null.
+            Ok(unsafe { NonNull::new_unchecked(slice) })
+        }
+        None => Err(crate::hook::report_layout(layout)),
+    }
+}
+
+/// Attempt to allocate an uninitialized `T`.
+///
+/// Zero-sized types do not touch the allocator,
+/// and return [`NonNull::dangling`].
+///
+/// It should be freed with [`dealloc`], using `Layout::new::<T>()`.
+#[inline]
+#[cfg_attr(feature = "location", track_caller)]
+pub fn alloc_one<T>() -> Result<NonNull<T>, Error> {
+    match imp(Layout::new::<T>(), false) {
+        Some(ptr) => Ok(ptr.cast()),
+        None => Err(crate::hook::report(Repr::of::<T>())),
+    }
+}
+
+/// Attempt to allocate `len` uninitialized `T`s.
+///
+/// It should be freed with [`dealloc`], using `Layout::array::<T>(len)`.
+#[inline]
+#[cfg_attr(feature = "location", track_caller)]
+pub fn alloc_array<T>(len: usize) -> Result<NonNull<T>, Error> {
+    array(len, false)
+}
+
+#[inline(always)]
+#[cfg_attr(feature = "location", track_caller)]
+pub(crate) fn array<T>(len: usize, zeroed: bool) -> Result<NonNull<T>, Error> {
+    let layout = match Layout::array::<T>(len) {
+        Ok(it) => it,
+        Err(_) => return Err(Error::for_type_with_kind::<T>(ErrorKind::LayoutOverflow)),
+    };
+    match imp(layout, zeroed) {
+        Some(ptr) => Ok(ptr.cast()),
+        None => Err(crate::hook::report_layout(layout)),
+    }
+}
+
+/// Like [`array`], but failures aren't reported,
+/// for fallback attempts which are expected to fail.
+///
+/// Failures are still counted, for the `"stats"` feature.
+#[inline(always)]
+pub(crate) fn array_silent<T>(len: usize, zeroed: bool) -> Option<NonNull<T>> {
+    imp(Layout::array::<T>(len).ok()?, zeroed).map(NonNull::cast)
+}
+
+/// Report the failure of [`array_silent`], returning the error from [`array`].
+#[cold]
+#[inline(never)]
+#[cfg_attr(feature = "location", track_caller)]
+pub(crate) fn array_error<T>(len: usize) -> Error {
+    match Layout::array::<T>(len) {
+        Ok(layout) => crate::hook::report_layout(layout),
+        Err(_) => Error::for_type_with_kind::<T>(ErrorKind::LayoutOverflow),
+    }
+}
+
+/// Free memory returned from [`alloc`](fn@alloc), or the other functions in
+/// this module.
+///
+/// # Safety
+/// - `ptr` must have been returned from this module with this `layout`.
+/// - `ptr` must not have already been freed.
+#[inline]
+pub unsafe fn dealloc(ptr: NonNull<u8>, layout: Layout) {
+    if layout.size() != 0 {
+        // SAFETY: caller upholds the contract.
+        unsafe { alloc::alloc::dealloc(ptr.as_ptr(), layout) }
+    }
+}
+
+/// The shared allocation routine for the whole crate.
+///
+/// Only zero-sized layouts are handled inline,
+/// so callers for each type share the same code.
+#[inline(always)]
+pub(crate) fn imp(layout: Layout, zeroed: bool) -> Option<NonNull<u8>> {
+    match layout.size() == 0 {
+        // A pointer without provenance, rather than casting `align` to one.
+        // SAFETY: `align` is non-zero.
+        true => Some(unsafe {
+            NonNull::new_unchecked(ptr::null_mut::<u8>().wrapping_add(layout.align()))
+        }),
+        false => nonzero(layout, zeroed),
+    }
+}
+
+/// Like [`imp`], but `layout` must be non-zero-sized.
+#[inline(never)]
+pub(crate) fn nonzero(layout: Layout, zeroed: bool) -> Option<NonNull<u8>> {
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    if !crate::wasm::fits(layout.size()) {
+        crate::hook::failure(layout);
+        return None;
+    }
+    #[cfg(feature = "std")]
+    if !crate::limit::take(layout.size()) {
+        crate::hook::failure(layout);
+        return None;
+    }
+    // SAFETY: callers check layout to be non-empty.
+    let alloc = || unsafe {
+        NonNull::new(match zeroed {
+            true => alloc::alloc::alloc_zeroed(layout),
+            false => alloc::alloc::alloc(layout),
+        })
+    };
+    #[cfg(all(
+        feature = "latency",
+        any(target_has_atomic = "ptr", feature = "critical-section")
+    ))]
+    let start = crate::stats::now();
+    #[allow(unused_mut)]
+    let mut ptr = alloc();
+    #[cfg(any(target_has_atomic = "ptr", feature = "critical-section"))]
+    if ptr.is_none() && crate::pressure::relieve(layout) {
+        ptr = alloc();
+    }
+    #[cfg(all(
+        feature = "latency",
+        any(target_has_atomic = "ptr", feature = "critical-section")
+    ))]
+    crate::stats::latency(start, ptr.is_some());
+    match ptr {
+        Some(_) => crate::hook::success(layout),
+        None => {
+            #[cfg(feature = "std")]
+            crate::limit::refund(layout.size());
+            crate::hook::failure(layout);
+        }
+    }
+    ptr
+}
@@ -0,0 +1,156 @@
+//! A single-word encoding of a failed allocation.
+
+use core::{
+    alloc::Layout,
+    mem::align_of,
+    ptr::{self, NonNull},
+};
+
+use crate::ErrorKind;
+
+/// Either a `&'static Meta` (low bit clear),
+/// or a packed [`Layout`] for an [`ErrorKind::Exhausted`] (low bit set).
+///
+/// [`Layout`] is two words, but this is just one.
+///
+/// Packed layouts are stored as pointers without provenance, which are never
+/// dereferenced, so that we don't cast integers to pointers.
+#[derive(Clone, Copy)]
+pub(crate) struct Repr(NonNull<Meta>);
+
+// SAFETY: the pointer is either to a `'static` `Meta`, which is `Sync`,
+//         or isn't dereferenced.
+unsafe impl Send for Repr {}
+// SAFETY: as above.
+unsafe impl Sync for Repr {}
+
+/// Information about a failure, known at compile time.
+pub(crate) struct Meta {
+    pub layout: Layout,
+    // This could be replaced by `&'static str` once type_name is a const fn
+    pub name: Option<NameFn>,
+    pub kind: ErrorKind,
+}
+
+/// Computes a type name.
+///
+/// This is `extern "C"` so that calls through it are known not to unwind,
+/// for the `"panic-free"` feature.
+// Only called from Rust.
+#[allow(improper_ctypes_definitions)]
+pub(crate) type NameFn = extern "C" fn() -> &'static str;
+
+// Only called from Rust.
+#[allow(improper_ctypes_definitions)]
+#[cfg(not(feature = "strip-type-names"))]
+extern "C" fn name_of<T>() -> &'static str {
+    core::any::type_name::<T>()
+}
+
+// We use the low bit of `&'static Meta` as a tag.
+const _: () = assert!(align_of::<Meta>() >= 2);
+
+trait Typed: Sized {
+    /// Stripped by the `"strip-type-names"` feature.
+    #[cfg(not(feature = "strip-type-names"))]
+    const NAME: Option<NameFn> = Some(name_of::<Self>);
+    #[cfg(feature = "strip-type-names")]
+    const NAME: Option<NameFn> = None;
+    const EXHAUSTED: &'static Meta = &Meta {
+        layout: Layout::new::<Self>(),
+        name: Self::NAME,
+        kind: ErrorKind::Exhausted,
+    };
+    const LAYOUT_OVERFLOW: &'static Meta = &Meta {
+        layout: Layout::new::<Self>(),
+        name: Self::NAME,
+        kind: ErrorKind::LayoutOverflow,
+    };
+    const ALIGNMENT: &'static Meta = &Meta {
+        layout: Layout::new::<Self>(),
+        name: Self::NAME,
+        kind: ErrorKind::Alignment,
+    };
+}
+impl<T> Typed for T {}
+
+static LAYOUT_OVERFLOW: Meta = Meta {
+    layout: Layout::new::<u8>(),
+    name: None,
+    kind: ErrorKind::LayoutOverflow,
+};
+
+static ALIGNMENT: Meta = Meta {
+    layout: Layout::new::<u8>(),
+    name: None,
+    kind: ErrorKind::Alignment,
+};
+
+/// The name of `T`, unless stripped by the `"strip-type-names"` feature.
+pub(crate) fn type_name<T>() -> Option<&'static str> {
+    <T as Typed>::NAME.map(|it| it())
+}
+
+/// The largest value of `size << 2` or `align << 2` we can store.
+const MAX: usize = usize::MAX >> 2;
+
+impl Repr {
+    #[inline(always)]
+    pub fn of<T>() -> Self {
+        Self::from_meta(T::EXHAUSTED)
+    }
+    pub fn of_kind<T>(kind: ErrorKind) -> Self {
+        Self::from_meta(match kind {
+            ErrorKind::Exhausted => T::EXHAUSTED,
+            ErrorKind::LayoutOverflow => T::LAYOUT_OVERFLOW,
+            ErrorKind::Alignment => T::ALIGNMENT,
+        })
+    }
+    /// For failures which aren't for a particular type or layout.
+    pub fn untyped(kind: ErrorKind) -> Self {
+        Self::from_meta(match kind {
+            ErrorKind::Exhausted => return Self::from_layout(Layout::new::<u8>()),
+            ErrorKind::LayoutOverflow => &LAYOUT_OVERFLOW,
+            ErrorKind::Alignment => &ALIGNMENT,
+        })
+    }
+    #[inline(always)]
+    fn from_meta(meta: &'static Meta) -> Self {
+        Self(NonNull::from(meta))
+    }
+    /// Layouts are packed as `size << 2 | align << 1 | 1`.
+    ///
+    /// Since `align` is a power of two, it's the lowest set bit of `repr >> 1`
+    /// as long as `size` is a multiple of `align`, so `size` is rounded up to
+    /// ensure that.
+    /// Layouts which are too large to pack are saturated.
+    pub fn from_layout(layout: Layout) -> Self {
+        let align = match layout.align() > MAX {
+            true => (MAX >> 1) + 1,
+            false => layout.align(),
+        };
+        let size = layout.size().min(MAX & !(align - 1));
+        let size = (size + (align - 1)) & !(align - 1);
+        let packed = ptr::null_mut::<u8>().wrapping_add(size << 2 | align << 1 | 1);
+        // SAFETY: the low bit is set.
+        Self(unsafe { NonNull::new_unchecked(packed.cast()) })
+    }
+    #[inline(always)]
+    pub fn meta(self) -> Result<&'static Meta, Layout> {
+        let repr = self.0.as_ptr() as usize;
+        match repr & 1 == 0 {
+            // SAFETY: we've checked the tag, and only ever store references
+            //         to `'static` `Meta`s with the tag clear.
+            true => Ok(unsafe { self.0.as_ref() }),
+            false => {
+                let packed = repr >> 1;
+                let align = packed & packed.wrapping_neg();
+                let size = (packed ^ align) >> 1;
+                // SAFETY: `from_layout` ensures that:
+                // - `align` is a power of two.
+                // - `size` (rounded up to `align`) cannot overflow `isize`.
+                Err(unsafe { Layout::from_size_align_unchecked(size, align) })
+            }
+        }
+    }
+}
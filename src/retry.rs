@@ -0,0 +1,143 @@
+//! Retrying allocations, for transient memory pressure.
+//!
+//! ```
+//! use std::time::Duration;
+//! use trybox::retry::{self, Policy};
+//!
+//! let policy = Policy::new(3)
+//!     .delay(Duration::from_millis(1))
+//!     .between(|e, attempt| eprintln!("attempt {attempt} failed: {e}"));
+//! let boxed = retry::new_with(policy, 1)?;
+//! # Ok::<_, trybox::ErrorWith<i32>>(())
+//! ```
+
+use alloc::boxed::Box;
+#[cfg(feature = "async")]
+use core::future::Future;
+use core::time::Duration;
+
+use crate::{Error, ErrorWith};
+
+/// How to retry an allocation, see [`new_with`].
+#[derive(Debug, Clone, Copy)]
+pub struct Policy<F = fn(&Error, u32)> {
+    attempts: u32,
+    delay: Duration,
+    wait: fn(Duration),
+    between: F,
+}
+
+impl Policy {
+    /// Attempt an allocation up to `attempts` times in total,
+    /// without waiting in between.
+    ///
+    /// The allocation is always attempted at least once.
+    pub fn new(attempts: u32) -> Self {
+        Self {
+            attempts,
+            delay: Duration::ZERO,
+            wait: WAIT,
+            between: ignore,
+        }
+    }
+}
+
+impl<F> Policy<F> {
+    /// Wait for `delay` between attempts.
+    ///
+    /// With the `"std"` feature, this sleeps the current thread by default.
+    /// Otherwise, there is no default way to wait, so [`wait`](Self::wait)
+    /// must also be set.
+    pub fn delay(self, delay: Duration) -> Self {
+        Self { delay, ..self }
+    }
+    /// Call `wait` with the [`delay`](Self::delay) between attempts,
+    /// e.g to spin or yield to an executor.
+    pub fn wait(self, wait: fn(Duration)) -> Self {
+        Self { wait, ..self }
+    }
+    /// Call `f` with the error and the (one-based) number of the attempt
+    /// after each failed attempt which will be retried,
+    /// e.g to free some memory.
+    pub fn between<G: FnMut(&Error, u32)>(self, f: G) -> Policy<G> {
+        Policy {
+            attempts: self.attempts,
+            delay: self.delay,
+            wait: self.wait,
+            between: f,
+        }
+    }
+}
+
+impl Default for Policy {
+    /// Three attempts, without waiting in between.
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+#[cfg(feature = "std")]
+const WAIT: fn(Duration) = std::thread::sleep;
+#[cfg(not(feature = "std"))]
+const WAIT: fn(Duration) = |_| {};
+
+fn ignore(_: &Error, _: u32) {}
+
+/// Like [`new`](crate::new), but retry failed allocations according to `policy`.
+///
+/// The payload is returned if every attempt fails.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn new_with<T, F: FnMut(&Error, u32)>(
+    mut policy: Policy<F>,
+    x: T,
+) -> Result<Box<T>, ErrorWith<T>> {
+    let mut attempt = 1;
+    loop {
+        // Rather than `new`, to pass on the error given to the hook.
+        let e = match crate::uninit::<T>() {
+            Ok(heap) => return Ok(crate::init(heap, x)),
+            Err(e) => e,
+        };
+        if attempt >= policy.attempts {
+            return Err(ErrorWith(x));
+        }
+        (policy.between)(&e, attempt);
+        if policy.delay != Duration::ZERO {
+            (policy.wait)(policy.delay)
+        }
+        attempt += 1;
+    }
+}
+
+/// Like [`new_with`], but await the future returned by `between` after each
+/// failed attempt which will be retried, e.g to sleep or yield to the executor.
+///
+/// This is executor-agnostic.
+///
+/// ```
+/// async fn alloc() -> Result<Box<[u8; 1024]>, trybox::ErrorWith<[u8; 1024]>> {
+///     // e.g `tokio::task::yield_now`.
+///     trybox::retry::new_async([0; 1024], 3, || async {}).await
+/// }
+/// ```
+#[cfg(feature = "async")]
+pub async fn new_async<T, F, Fut>(
+    x: T,
+    attempts: u32,
+    mut between: F,
+) -> Result<Box<T>, ErrorWith<T>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut res = crate::new(x);
+    for _ in 1..attempts {
+        let e = match res {
+            Ok(it) => return Ok(it),
+            Err(e) => e,
+        };
+        between().await;
+        res = e.retry();
+    }
+    res
+}
@@ -0,0 +1,228 @@
+//! A ring buffer backed by a fallibly allocated boxed slice.
+//!
+//! ```
+//! use trybox::ring::RingBuffer;
+//!
+//! let mut ring = RingBuffer::with_capacity(2)?;
+//! ring.push_back(1).unwrap();
+//! ring.push_back(2).unwrap();
+//! // Full, so the value is handed back without allocating.
+//! assert_eq!(ring.push_back(3), Err(3));
+//! assert_eq!(ring.pop_front(), Some(1));
+//! ring.push_back(3).unwrap();
+//! // Make room for more, or report that we couldn't.
+//! ring.try_grow(2)?;
+//! ring.push_back(4).unwrap();
+//! assert!(ring.iter().eq(&[2, 3, 4]));
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use alloc::boxed::Box;
+use core::{fmt, mem::MaybeUninit, ops::Range, ptr, slice as raw_slice};
+
+use crate::{slice, Error, ErrorKind, ErrorWith};
+
+/// A first-in, first-out queue with a fixed capacity,
+/// which only changes when explicitly grown.
+pub struct RingBuffer<T> {
+    buf: Box<[MaybeUninit<T>]>,
+    /// The index of the front, which is zero if the buffer has no capacity.
+    head: usize,
+    len: usize,
+}
+
+impl<T> RingBuffer<T> {
+    /// Create a ring buffer with no capacity, which doesn't allocate.
+    pub fn new() -> Self {
+        Self {
+            // A dangling pointer, which doesn't allocate.
+            buf: Box::new([]),
+            head: 0,
+            len: 0,
+        }
+    }
+    /// Attempt to create an empty ring buffer with room for exactly `capacity`
+    /// values.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn with_capacity(capacity: usize) -> Result<Self, Error> {
+        Ok(Self {
+            buf: slice::uninit(capacity)?,
+            head: 0,
+            len: 0,
+        })
+    }
+    /// Attempt to make room for exactly `additional` more values.
+    ///
+    /// This moves the contents to a new allocation.
+    /// On failure, the ring buffer is unchanged.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_grow(&mut self, additional: usize) -> Result<(), Error> {
+        if additional == 0 {
+            return Ok(());
+        }
+        let capacity = match self.capacity().checked_add(additional) {
+            Some(it) => it,
+            None => return Err(Error::for_type_with_kind::<T>(ErrorKind::LayoutOverflow)),
+        };
+        let buf = slice::uninit(capacity)?;
+        self.move_to(buf);
+        Ok(())
+    }
+    /// Move the contents to the start of `buf`, which must have room for them.
+    fn move_to(&mut self, mut buf: Box<[MaybeUninit<T>]>) {
+        let (front, back) = self.ranges();
+        // SAFETY: the values are moved, and the old buffer doesn't drop them.
+        unsafe {
+            let src = self.buf.as_ptr();
+            let dst = buf.as_mut_ptr();
+            ptr::copy_nonoverlapping(src.add(front.start), dst, front.len());
+            ptr::copy_nonoverlapping(src, dst.add(front.len()), back.len());
+        }
+        self.buf = buf;
+        self.head = 0;
+    }
+    /// Append `x`, or return it if the ring buffer is full.
+    ///
+    /// This never allocates.
+    pub fn push_back(&mut self, x: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(x);
+        }
+        let ix = self.wrap(self.len);
+        self.buf[ix] = MaybeUninit::new(x);
+        self.len += 1;
+        Ok(())
+    }
+    /// Append `x`, attempting to grow the ring buffer if it's full,
+    /// typically doubling its capacity.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_push_back(&mut self, x: T) -> Result<(), ErrorWith<T>> {
+        if self.is_full() {
+            let doubled = self.capacity().checked_add(self.capacity().max(4));
+            match doubled.and_then(slice::uninit_silent) {
+                Some(buf) => self.move_to(buf),
+                // Doubling may have been too greedy.
+                None => {
+                    if self.try_grow(1).is_err() {
+                        return Err(ErrorWith(x));
+                    }
+                }
+            }
+        }
+        match self.push_back(x) {
+            Ok(()) => Ok(()),
+            Err(_) => unreachable!("there is room after growing"),
+        }
+    }
+    /// Remove the value at the front, if any.
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        // SAFETY: the front is initialized, and is no longer part of the contents.
+        let x = unsafe { self.buf[self.head].as_ptr().read() };
+        self.head = self.wrap(1);
+        self.len -= 1;
+        Some(x)
+    }
+    /// The value at the front, if any.
+    pub fn front(&self) -> Option<&T> {
+        self.iter().next()
+    }
+    /// The value at the back, if any.
+    pub fn back(&self) -> Option<&T> {
+        self.iter().next_back()
+    }
+    /// The number of values.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Whether there are no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Whether [`push_back`](Self::push_back) would fail.
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+    /// The number of values which can be held without growing.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+    /// Remove every value, keeping the capacity.
+    pub fn clear(&mut self) {
+        let (front, back) = self.as_mut_slices();
+        let (front, back) = (front as *mut [T], back as *mut [T]);
+        self.head = 0;
+        self.len = 0;
+        // SAFETY: the values are no longer part of the contents.
+        unsafe {
+            ptr::drop_in_place(front);
+            ptr::drop_in_place(back);
+        }
+    }
+    /// The contents, from front to back,
+    /// which wrap around the end of the buffer into the second slice.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let (front, back) = self.ranges();
+        let ptr = self.buf.as_ptr().cast::<T>();
+        // SAFETY: the ranges are initialized.
+        unsafe {
+            (
+                raw_slice::from_raw_parts(ptr.add(front.start), front.len()),
+                raw_slice::from_raw_parts(ptr, back.len()),
+            )
+        }
+    }
+    /// The contents, from front to back,
+    /// which wrap around the end of the buffer into the second slice.
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        let (front, back) = self.ranges();
+        let ptr = self.buf.as_mut_ptr().cast::<T>();
+        // SAFETY: the ranges are initialized, and don't overlap.
+        unsafe {
+            (
+                raw_slice::from_raw_parts_mut(ptr.add(front.start), front.len()),
+                raw_slice::from_raw_parts_mut(ptr, back.len()),
+            )
+        }
+    }
+    /// The values from front to back.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + '_ {
+        let (front, back) = self.as_slices();
+        front.iter().chain(back)
+    }
+    /// The initialized parts of the buffer,
+    /// up to the end then from the start.
+    fn ranges(&self) -> (Range<usize>, Range<usize>) {
+        let front = (self.capacity() - self.head).min(self.len);
+        (self.head..self.head + front, 0..self.len - front)
+    }
+    /// The index in the buffer `offset` values after the front.
+    fn wrap(&self, offset: usize) -> usize {
+        let ix = self.head + offset;
+        match ix >= self.capacity() {
+            true => ix - self.capacity(),
+            false => ix,
+        }
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        self.clear()
+    }
+}
+
+impl<T> Default for RingBuffer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RingBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (front, back) = self.as_slices();
+        f.debug_list().entries(front).entries(back).finish()
+    }
+}
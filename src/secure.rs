@@ -0,0 +1,183 @@
+//! Boxes for secrets, which are zeroed on drop,
+//! and locked into RAM with `mlock` when the `"unix"` feature is enabled.
+//!
+//! ```
+//! let key = trybox::secure::new([0x42u8; 32]).unwrap();
+//! assert_eq!(key[0], 0x42);
+//! ```
+//!
+//! Note that `secret` is moved into the box, so copies of it may remain on the
+//! stack.
+
+use core::{
+    alloc::Layout,
+    fmt,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+
+use zeroize::Zeroize;
+
+use crate::{raw, repr::Repr, ErrorWith};
+
+/// Attempt to move `secret` to a locked heap allocation,
+/// returning it on failure.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn new<T: Zeroize>(secret: T) -> Result<SecureBox<T>, Error<T>> {
+    // Failures are reported for `T`, like the returned error,
+    // rather than the rounded layout.
+    let (ptr, layout) = match layout::<T>().and_then(|it| Some((raw::imp(it, false)?, it))) {
+        Some((ptr, layout)) => (ptr.cast::<T>(), layout),
+        None => {
+            crate::hook::report(Repr::of::<T>());
+            return Err(Error::Alloc(ErrorWith(secret)));
+        }
+    };
+    // SAFETY: `ptr` was just allocated for a `T`.
+    unsafe { ptr.as_ptr().write(secret) };
+    #[cfg(all(feature = "unix", unix))]
+    {
+        // SAFETY: the range is exactly our allocation.
+        if layout.size() != 0 && unsafe { libc::mlock(ptr.as_ptr().cast(), layout.size()) } != 0 {
+            let source = std::io::Error::last_os_error();
+            // SAFETY:
+            // - the value is initialized, and we don't use it again.
+            // - we zero the bytes of the old value before freeing the memory.
+            let secret = unsafe {
+                let secret = ptr.as_ptr().read();
+                core::slice::from_raw_parts_mut(
+                    ptr.as_ptr().cast::<core::mem::MaybeUninit<u8>>(),
+                    core::mem::size_of::<T>(),
+                )
+                .zeroize();
+                raw::dealloc(ptr.cast(), layout);
+                secret
+            };
+            return Err(Error::Lock { secret, source });
+        }
+    }
+    Ok(SecureBox { ptr, layout })
+}
+
+/// The layout each secret is allocated with.
+///
+/// When locking, this is rounded out to whole pages,
+/// so that unlocking one secret can't unlock another allocation.
+fn layout<T>() -> Option<Layout> {
+    let layout = Layout::new::<T>();
+    #[cfg(all(feature = "unix", unix))]
+    if layout.size() != 0 {
+        let page = crate::sys::page_size();
+        let size = layout.size().checked_add(page - 1)? & !(page - 1);
+        return Layout::from_size_align(size, page.max(layout.align())).ok();
+    }
+    Some(layout)
+}
+
+/// A heap allocated secret, returned from [`new`].
+pub struct SecureBox<T: Zeroize> {
+    ptr: NonNull<T>,
+    layout: Layout,
+}
+
+// SAFETY: we uniquely own the value, like `Box<T>`.
+unsafe impl<T: Zeroize + Send> Send for SecureBox<T> {}
+// SAFETY: we uniquely own the value, like `Box<T>`.
+unsafe impl<T: Zeroize + Sync> Sync for SecureBox<T> {}
+
+impl<T: Zeroize> Deref for SecureBox<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the value is initialized, and we own it.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: Zeroize> DerefMut for SecureBox<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the value is initialized, and we own it.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: Zeroize> Drop for SecureBox<T> {
+    fn drop(&mut self) {
+        T::zeroize(self);
+        // SAFETY: the value is initialized, and not used again.
+        unsafe { ptr::drop_in_place(self.ptr.as_ptr()) };
+        #[cfg(all(feature = "unix", unix))]
+        if self.layout.size() != 0 {
+            // SAFETY: we locked exactly this range in `new`.
+            unsafe { libc::munlock(self.ptr.as_ptr().cast(), self.layout.size()) };
+        }
+        // SAFETY: the memory was allocated in `new`, with this layout.
+        unsafe { raw::dealloc(self.ptr.cast(), self.layout) };
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for SecureBox<T> {
+    /// The secret is not printed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureBox").finish_non_exhaustive()
+    }
+}
+
+/// Failure to create a [`SecureBox`].
+pub enum Error<T> {
+    /// The allocation failed.
+    Alloc(ErrorWith<T>),
+    /// The allocation succeeded, but could not be locked into RAM.
+    #[cfg(all(feature = "unix", unix))]
+    Lock { secret: T, source: std::io::Error },
+}
+
+impl<T> Error<T> {
+    /// Recover the secret.
+    ///
+    /// Note that this will leave a copy of the secret on the stack.
+    pub fn into_inner(self) -> T {
+        match self {
+            Error::Alloc(ErrorWith(secret)) => secret,
+            #[cfg(all(feature = "unix", unix))]
+            Error::Lock { secret, .. } => secret,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Error<T> {
+    /// The secret is not printed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Alloc(e) => f.debug_tuple("Alloc").field(&e.info()).finish(),
+            #[cfg(all(feature = "unix", unix))]
+            Error::Lock { source, .. } => f
+                .debug_struct("Lock")
+                .field("source", source)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl<T> fmt::Display for Error<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Alloc(e) => e.fmt(f),
+            #[cfg(all(feature = "unix", unix))]
+            Error::Lock { .. } => f.write_str("failed to lock secret memory"),
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<T> core::error::Error for Error<T> {}
+
+#[cfg(feature = "std")]
+impl<T> std::error::Error for Error<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Alloc(_) => None,
+            #[cfg(all(feature = "unix", unix))]
+            Error::Lock { source, .. } => Some(source),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Buffers which may be split into several allocations.
+//!
+//! ```
+//! let mut buf = trybox::segmented::Buffer::zeroed(10_000)?;
+//! buf[9_999] = 1;
+//! assert_eq!(buf.len(), 10_000);
+//! assert_eq!(buf.chunks().map(<[u8]>::len).sum::<usize>(), 10_000);
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use alloc::boxed::Box;
+use core::{
+    fmt,
+    ops::{Index, IndexMut, Range},
+};
+
+use crate::{raw, slice, Error};
+
+/// Chunks smaller than this are not attempted.
+const MIN_CHUNK: usize = 256;
+
+/// A zeroed buffer of bytes, which is contiguous if possible,
+/// but is otherwise split into equally-sized chunks (except the last).
+pub struct Buffer {
+    chunks: Box<[Box<[u8]>]>,
+    chunk_len: usize,
+    len: usize,
+}
+
+impl Buffer {
+    /// Attempt to allocate a zeroed buffer of `len` bytes.
+    ///
+    /// A single contiguous allocation is attempted first.
+    /// If that fails, the buffer is assembled from `len / 2`-sized chunks,
+    /// then `len / 4`, and so on.
+    ///
+    /// If all attempts fail, the [`Error`] from the contiguous attempt is
+    /// returned, and is the only one reported, e.g to the
+    /// [OOM hook](crate::set_oom_hook).
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn zeroed(len: usize) -> Result<Self, Error> {
+        if let Some(it) = Self::with_chunk_len(len, len) {
+            return Ok(it);
+        }
+        let mut chunk_len = len / 2;
+        while chunk_len >= MIN_CHUNK {
+            if let Some(it) = Self::with_chunk_len(len, chunk_len) {
+                return Ok(it);
+            }
+            chunk_len /= 2;
+        }
+        Err(raw::array_error::<u8>(len))
+    }
+    fn with_chunk_len(len: usize, chunk_len: usize) -> Option<Self> {
+        let count = match chunk_len {
+            0 => 0,
+            _ => (len + chunk_len - 1) / chunk_len,
+        };
+        let chunks = slice::init_with(slice::uninit_silent(count)?, |ix| {
+            let start = ix * chunk_len;
+            slice::zeroed_silent(chunk_len.min(len - start)).ok_or(())
+        })
+        .ok()?;
+        Some(Self {
+            chunks,
+            chunk_len,
+            len,
+        })
+    }
+    /// The number of bytes in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Whether the buffer has no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Returns [`Some`] if the buffer is a single allocation.
+    pub fn as_contiguous(&self) -> Option<&[u8]> {
+        match &*self.chunks {
+            [] => Some(&[]),
+            [it] => Some(it),
+            _ => None,
+        }
+    }
+    /// Returns [`Some`] if the buffer is a single allocation.
+    pub fn as_contiguous_mut(&mut self) -> Option<&mut [u8]> {
+        match &mut *self.chunks {
+            [] => Some(&mut []),
+            [it] => Some(it),
+            _ => None,
+        }
+    }
+    /// The allocations that make up the buffer, in order.
+    pub fn chunks(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.chunks.iter().map(|it| &**it)
+    }
+    /// The allocations that make up the buffer, in order.
+    pub fn chunks_mut(&mut self) -> impl Iterator<Item = &mut [u8]> + '_ {
+        self.chunks.iter_mut().map(|it| &mut **it)
+    }
+    /// The byte at `index`, or [`None`] if it's out of bounds.
+    pub fn get(&self, index: usize) -> Option<&u8> {
+        match index < self.len {
+            true => Some(&self.chunks[index / self.chunk_len][index % self.chunk_len]),
+            false => None,
+        }
+    }
+    /// The byte at `index`, mutably, or [`None`] if it's out of bounds.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut u8> {
+        match index < self.len {
+            true => Some(&mut self.chunks[index / self.chunk_len][index % self.chunk_len]),
+            false => None,
+        }
+    }
+    /// Copy bytes starting at `offset` into `dst`,
+    /// returning the number of bytes copied.
+    pub fn read_at(&self, offset: usize, dst: &mut [u8]) -> usize {
+        let mut copied = 0;
+        for (chunk, range) in ranges(self.chunk_len, self.len, offset, dst.len()) {
+            let n = range.len();
+            dst[copied..][..n].copy_from_slice(&self.chunks[chunk][range]);
+            copied += n;
+        }
+        copied
+    }
+    /// Copy bytes from `src` into the buffer, starting at `offset`,
+    /// returning the number of bytes copied.
+    pub fn write_at(&mut self, offset: usize, src: &[u8]) -> usize {
+        let mut copied = 0;
+        for (chunk, range) in ranges(self.chunk_len, self.len, offset, src.len()) {
+            let n = range.len();
+            self.chunks[chunk][range].copy_from_slice(&src[copied..][..n]);
+            copied += n;
+        }
+        copied
+    }
+}
+
+/// Split `count` bytes starting at `offset` into ranges within each chunk.
+fn ranges(
+    chunk_len: usize,
+    len: usize,
+    offset: usize,
+    count: usize,
+) -> impl Iterator<Item = (usize, Range<usize>)> {
+    let end = offset.saturating_add(count).min(len);
+    let mut pos = offset.min(end);
+    core::iter::from_fn(move || match pos < end {
+        true => {
+            let chunk = pos / chunk_len;
+            let start = pos % chunk_len;
+            let n = (chunk_len - start).min(end - pos);
+            pos += n;
+            Some((chunk, start..start + n))
+        }
+        false => None,
+    })
+}
+
+impl Index<usize> for Buffer {
+    type Output = u8;
+    fn index(&self, index: usize) -> &Self::Output {
+        match self.get(index) {
+            Some(it) => it,
+            None => panic!("index {index} out of bounds for length {}", self.len),
+        }
+    }
+}
+
+impl IndexMut<usize> for Buffer {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        let len = self.len;
+        match self.get_mut(index) {
+            Some(it) => it,
+            None => panic!("index {index} out of bounds for length {len}"),
+        }
+    }
+}
+
+impl fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buffer")
+            .field("len", &self.len)
+            .field("chunks", &self.chunks.len())
+            .finish()
+    }
+}
@@ -0,0 +1,47 @@
+//! [`serde::Serialize`] implementations, behind the `"serde"` feature.
+//!
+//! An [`Error`] is serialized as a struct with its [`kind`](Error::kind),
+//! the `size` and `align` of its [`layout`](Error::layout),
+//! and its [`type_name`](Error::type_name).
+
+use serde::ser::{Serialize, SerializeStruct as _, Serializer};
+
+use crate::{Error, ErrorKind, ErrorWith, Info};
+
+impl Serialize for ErrorKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (index, name) = match self {
+            ErrorKind::Exhausted => (0, "Exhausted"),
+            ErrorKind::LayoutOverflow => (1, "LayoutOverflow"),
+            ErrorKind::Alignment => (2, "Alignment"),
+        };
+        serializer.serialize_unit_variant("ErrorKind", index, name)
+    }
+}
+
+impl Serialize for Info {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Error", 4)?;
+        s.serialize_field("kind", &self.kind)?;
+        s.serialize_field("size", &self.layout.size())?;
+        s.serialize_field("align", &self.layout.align())?;
+        s.serialize_field("type_name", &self.name)?;
+        s.end()
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.info().serialize(serializer)
+    }
+}
+
+/// Serialized as a struct with the `error`, and the `payload`.
+impl<T: Serialize> Serialize for ErrorWith<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("ErrorWith", 2)?;
+        s.serialize_field("error", &self.info())?;
+        s.serialize_field("payload", &self.0)?;
+        s.end()
+    }
+}
@@ -0,0 +1,257 @@
+//! A slab of values addressed by generational keys,
+//! whose storage grows fallibly.
+//!
+//! ```
+//! use trybox::slab::Slab;
+//!
+//! let mut connections = Slab::new();
+//! let alice = connections.try_insert("alice")?;
+//! let bob = connections.try_insert("bob")?;
+//! assert_eq!(connections.remove(alice), Some("alice"));
+//! // The slot is reused, but the old key doesn't refer to the new value.
+//! let carol = connections.try_insert("carol")?;
+//! assert_eq!(connections.get(alice), None);
+//! assert_eq!(connections.get(carol), Some(&"carol"));
+//! assert_eq!(connections.get(bob), Some(&"bob"));
+//! # Ok::<_, trybox::ErrorWith<&str>>(())
+//! ```
+
+use alloc::boxed::Box;
+use core::{fmt, mem, mem::MaybeUninit, ptr, slice as raw_slice};
+
+use crate::{slice, Error, ErrorWith};
+
+/// Identifies a value in a [`Slab`].
+///
+/// Keys are not reused when their value is removed,
+/// until the slot's generation wraps around after [`u32::MAX`] removals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Key {
+    index: usize,
+    generation: u32,
+}
+
+impl Key {
+    /// The position of the value in the slab,
+    /// which is shared with keys to earlier values in the same slot.
+    pub fn index(self) -> usize {
+        self.index
+    }
+}
+
+/// A collection of values, addressed by [`Key`]s,
+/// which reuses the slots of removed values.
+///
+/// Storage is a boxed slice which doubles in size,
+/// so [`try_insert`](Self::try_insert) returns the value rather than
+/// aborting if it can't grow.
+pub struct Slab<T> {
+    entries: Box<[MaybeUninit<Entry<T>>]>,
+    /// The number of initialized entries, which are a prefix of `entries`.
+    init: usize,
+    len: usize,
+    /// The most recently vacated entry.
+    free: Option<usize>,
+}
+
+struct Entry<T> {
+    generation: u32,
+    slot: Slot<T>,
+}
+
+enum Slot<T> {
+    Occupied(T),
+    /// The next vacant entry.
+    Vacant(Option<usize>),
+}
+
+impl<T> Slab<T> {
+    /// Create an empty slab, which doesn't allocate until the first insert.
+    pub fn new() -> Self {
+        Self {
+            // A dangling pointer, which doesn't allocate.
+            entries: Box::new([]),
+            init: 0,
+            len: 0,
+            free: None,
+        }
+    }
+    /// Attempt to create an empty slab with room for `capacity` values.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn with_capacity(capacity: usize) -> Result<Self, Error> {
+        Ok(Self {
+            entries: slice::uninit(capacity)?,
+            ..Self::new()
+        })
+    }
+    /// Attempt to insert `value`, returning its key,
+    /// or the value if there was no room and the slab couldn't grow.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_insert(&mut self, value: T) -> Result<Key, ErrorWith<T>> {
+        if let Some(index) = self.free {
+            let entry = &mut self.entries_mut()[index];
+            let generation = entry.generation;
+            self.free = match mem::replace(&mut entry.slot, Slot::Occupied(value)) {
+                Slot::Vacant(next) => next,
+                Slot::Occupied(_) => unreachable!("occupied entry in the free list"),
+            };
+            self.len += 1;
+            return Ok(Key { index, generation });
+        }
+        if self.init == self.entries.len() && self.grow().is_err() {
+            return Err(ErrorWith(value));
+        }
+        let index = self.init;
+        self.entries[index] = MaybeUninit::new(Entry {
+            generation: 0,
+            slot: Slot::Occupied(value),
+        });
+        self.init += 1;
+        self.len += 1;
+        Ok(Key {
+            index,
+            generation: 0,
+        })
+    }
+    /// Double the storage, or make room for at least one more entry.
+    #[cfg_attr(feature = "location", track_caller)]
+    fn grow(&mut self) -> Result<(), Error> {
+        let cap = self.entries.len();
+        let mut entries = match slice::uninit_silent(cap.saturating_mul(2).max(4)) {
+            Some(it) => it,
+            // Doubling may have been too greedy.
+            None => slice::uninit(cap.saturating_add(1))?,
+        };
+        // SAFETY: the initialized entries are moved,
+        //         and the old storage doesn't drop them.
+        unsafe { ptr::copy_nonoverlapping(self.entries.as_ptr(), entries.as_mut_ptr(), self.init) };
+        self.entries = entries;
+        Ok(())
+    }
+    fn entries(&self) -> &[Entry<T>] {
+        // SAFETY: the first `init` entries are initialized.
+        unsafe { raw_slice::from_raw_parts(self.entries.as_ptr().cast(), self.init) }
+    }
+    fn entries_mut(&mut self) -> &mut [Entry<T>] {
+        // SAFETY: the first `init` entries are initialized.
+        unsafe { raw_slice::from_raw_parts_mut(self.entries.as_mut_ptr().cast(), self.init) }
+    }
+    /// The value for `key`, if it's still in the slab.
+    pub fn get(&self, key: Key) -> Option<&T> {
+        match self.entries().get(key.index)? {
+            Entry {
+                generation,
+                slot: Slot::Occupied(it),
+            } if *generation == key.generation => Some(it),
+            _ => None,
+        }
+    }
+    /// The value for `key`, mutably, if it's still in the slab.
+    pub fn get_mut(&mut self, key: Key) -> Option<&mut T> {
+        match self.entries_mut().get_mut(key.index)? {
+            Entry {
+                generation,
+                slot: Slot::Occupied(it),
+            } if *generation == key.generation => Some(it),
+            _ => None,
+        }
+    }
+    /// Whether `key` refers to a value in the slab.
+    pub fn contains(&self, key: Key) -> bool {
+        self.get(key).is_some()
+    }
+    /// Remove the value for `key`, if any.
+    ///
+    /// Its slot is reused by the next insert, under a different key.
+    pub fn remove(&mut self, key: Key) -> Option<T> {
+        self.get(key)?;
+        let free = self.free;
+        let entry = &mut self.entries_mut()[key.index];
+        entry.generation = entry.generation.wrapping_add(1);
+        let value = match mem::replace(&mut entry.slot, Slot::Vacant(free)) {
+            Slot::Occupied(it) => it,
+            Slot::Vacant(_) => unreachable!("checked above"),
+        };
+        self.free = Some(key.index);
+        self.len -= 1;
+        Some(value)
+    }
+    /// Remove every value, keeping the storage.
+    ///
+    /// Keys to the removed values remain invalid.
+    pub fn clear(&mut self) {
+        let mut free = None;
+        for (index, entry) in self.entries_mut().iter_mut().enumerate().rev() {
+            if let Slot::Occupied(_) = entry.slot {
+                entry.generation = entry.generation.wrapping_add(1);
+            }
+            entry.slot = Slot::Vacant(free);
+            free = Some(index);
+        }
+        self.free = free;
+        self.len = 0;
+    }
+    /// The number of values.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Whether there are no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// The number of values which can be held without growing.
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+    /// The values and their keys, in order of their slots.
+    pub fn iter(&self) -> impl Iterator<Item = (Key, &T)> + '_ {
+        self.entries()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| match &entry.slot {
+                Slot::Occupied(it) => Some((
+                    Key {
+                        index,
+                        generation: entry.generation,
+                    },
+                    it,
+                )),
+                Slot::Vacant(_) => None,
+            })
+    }
+    /// The values and their keys, in order of their slots.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Key, &mut T)> + '_ {
+        self.entries_mut()
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, entry)| match &mut entry.slot {
+                Slot::Occupied(it) => Some((
+                    Key {
+                        index,
+                        generation: entry.generation,
+                    },
+                    it,
+                )),
+                Slot::Vacant(_) => None,
+            })
+    }
+}
+
+impl<T> Drop for Slab<T> {
+    fn drop(&mut self) {
+        // SAFETY: the entries are initialized, and not used again.
+        unsafe { ptr::drop_in_place(self.entries_mut()) }
+    }
+}
+
+impl<T> Default for Slab<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Slab<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,321 @@
+//! Fallibly allocated slices.
+//!
+//! ```
+//! let squares = trybox::slice::from_fn(10, |ix| ix * ix)?;
+//! assert_eq!(squares[3], 9);
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use alloc::boxed::Box;
+use core::{mem::MaybeUninit, ptr};
+
+use crate::{raw, Error};
+
+#[cfg(all(feature = "unix", unix))]
+pub use self::page::{page_aligned, PageAligned};
+
+/// Like [`vec!`](alloc::vec!), but fallibly creates a boxed slice,
+/// returning a `Result<Box<[T]>, Error>`.
+///
+/// ```
+/// let zeroes = trybox::try_boxed_slice![0u8; 4096]?;
+/// assert_eq!(zeroes.len(), 4096);
+/// let abc = trybox::try_boxed_slice!['a', 'b', 'c']?;
+/// assert_eq!(*abc, ['a', 'b', 'c']);
+/// # Ok::<_, trybox::Error>(())
+/// ```
+///
+/// `[elem; n]` requires `elem` to be [`Clone`].
+#[macro_export]
+macro_rules! try_boxed_slice {
+    ($elem:expr; $n:expr) => {{
+        let elem = $elem;
+        $crate::slice::from_fn($n, |_| ::core::clone::Clone::clone(&elem))
+    }};
+    ($($x:expr),* $(,)?) => {
+        $crate::TryIntoBox::<[_]>::try_into_box([$($x),*])
+    };
+}
+
+/// Attempt to allocate a slice of `len` uninitialized `T`s.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn uninit<T>(len: usize) -> Result<Box<[MaybeUninit<T>]>, Error> {
+    let ptr = raw::array::<T>(len, false)?.as_ptr();
+    // SAFETY: the memory is uninitialized, which is fine for MaybeUninit.
+    Ok(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ptr.cast(), len)) })
+}
+
+/// Like [`uninit`], but failures aren't reported, see [`raw::array_silent`].
+pub(crate) fn uninit_silent<T>(len: usize) -> Option<Box<[MaybeUninit<T>]>> {
+    let ptr = raw::array_silent::<T>(len, false)?.as_ptr();
+    // SAFETY: the memory is uninitialized, which is fine for MaybeUninit.
+    Some(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ptr.cast(), len)) })
+}
+
+/// Attempt to allocate a slice of `len` zero bytes.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn zeroed(len: usize) -> Result<Box<[u8]>, Error> {
+    let ptr = raw::array::<u8>(len, true)?.as_ptr();
+    // SAFETY: the memory is zeroed, which is a valid `u8`.
+    Ok(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)) })
+}
+
+/// Like [`zeroed`], but failures aren't reported, see [`raw::array_silent`].
+pub(crate) fn zeroed_silent(len: usize) -> Option<Box<[u8]>> {
+    let ptr = raw::array_silent::<u8>(len, true)?.as_ptr();
+    // SAFETY: the memory is zeroed, which is a valid `u8`.
+    Some(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)) })
+}
+
+/// Attempt to allocate a slice of `len` `T`s,
+/// initializing each element with `f(index)`.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn from_fn<T>(len: usize, mut f: impl FnMut(usize) -> T) -> Result<Box<[T]>, Error> {
+    try_from_fn::<_, Error>(len, |ix| Ok(f(ix)))
+}
+
+/// Like [`from_fn`], but `f` may fail,
+/// in which case its error is returned and the slice is dropped.
+#[cfg_attr(feature = "location", track_caller)]
+pub fn try_from_fn<T, E: From<Error>>(
+    len: usize,
+    f: impl FnMut(usize) -> Result<T, E>,
+) -> Result<Box<[T]>, E> {
+    init_with(uninit::<T>(len)?, f)
+}
+
+/// Initialize each element of `slice` with `f(index)`,
+/// dropping it if `f` fails.
+pub(crate) fn init_with<T, E>(
+    mut slice: Box<[MaybeUninit<T>]>,
+    mut f: impl FnMut(usize) -> Result<T, E>,
+) -> Result<Box<[T]>, E> {
+    let len = slice.len();
+    let mut guard = Guard {
+        slice: &mut slice,
+        init: 0,
+    };
+    while guard.init < len {
+        let x = f(guard.init)?;
+        guard.slice[guard.init].write(x);
+        guard.init += 1;
+    }
+    core::mem::forget(guard);
+    // SAFETY: every element has been initialized.
+    Ok(unsafe { Box::from_raw(Box::into_raw(slice) as *mut [T]) })
+}
+
+/// Drops the initialized prefix of a slice, on failure or panic.
+struct Guard<'a, T> {
+    slice: &'a mut [MaybeUninit<T>],
+    init: usize,
+}
+
+impl<T> Drop for Guard<'_, T> {
+    fn drop(&mut self) {
+        let init = ptr::slice_from_raw_parts_mut(self.slice.as_mut_ptr().cast::<T>(), self.init);
+        // SAFETY: the first `init` elements are initialized.
+        unsafe { ptr::drop_in_place(init) }
+    }
+}
+
+#[cfg(all(feature = "unix", unix))]
+mod page {
+    use core::{
+        alloc::Layout,
+        fmt,
+        ops::{Deref, DerefMut},
+        ptr::NonNull,
+        slice,
+    };
+
+    use crate::{
+        raw,
+        sys::{page_size, populate},
+        Error, ErrorKind,
+    };
+
+    /// Attempt to allocate a zeroed buffer of `len` bytes,
+    /// aligned to the system's page size.
+    ///
+    /// ```
+    /// let mut buf = trybox::slice::page_aligned(1 << 21)?;
+    /// # #[cfg(target_os = "linux")]
+    /// let _ = buf.advise_huge_pages();
+    /// buf[0] = 1;
+    /// # Ok::<_, trybox::Error>(())
+    /// ```
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn page_aligned(len: usize) -> Result<PageAligned, Error> {
+        let page = page_size();
+        let layout = match Layout::from_size_align(len, page) {
+            Ok(it) => it,
+            Err(_) => return Err(Error::for_type_with_kind::<u8>(ErrorKind::LayoutOverflow)),
+        };
+        let ptr = raw::alloc_zeroed(layout)?;
+        Ok(PageAligned {
+            ptr: ptr.cast(),
+            layout,
+        })
+    }
+
+    /// A buffer of bytes aligned to the system's page size,
+    /// returned from [`page_aligned`].
+    pub struct PageAligned {
+        ptr: NonNull<u8>,
+        layout: Layout,
+    }
+
+    // SAFETY: we uniquely own the allocation, like `Box<[u8]>`.
+    unsafe impl Send for PageAligned {}
+    // SAFETY: we uniquely own the allocation, like `Box<[u8]>`.
+    unsafe impl Sync for PageAligned {}
+
+    impl PageAligned {
+        /// Hint to the kernel that this buffer should be backed by
+        /// [transparent huge pages](https://docs.kernel.org/admin-guide/mm/transhuge.html),
+        /// with `madvise(MADV_HUGEPAGE)`.
+        ///
+        /// This is only a hint, and only the whole pages within the buffer are
+        /// affected.
+        #[cfg(target_os = "linux")]
+        pub fn advise_huge_pages(&mut self) -> std::io::Result<()> {
+            if self.layout.size() == 0 {
+                return Ok(());
+            }
+            // SAFETY: the range is within our allocation, and this advice
+            //         doesn't change the contents of memory.
+            match unsafe {
+                libc::madvise(
+                    self.ptr.as_ptr().cast(),
+                    self.layout.size(),
+                    libc::MADV_HUGEPAGE,
+                )
+            } {
+                0 => Ok(()),
+                _ => Err(std::io::Error::last_os_error()),
+            }
+        }
+
+        /// Return this buffer's memory to the kernel with
+        /// `madvise(MADV_DONTNEED)`, leaving it zeroed,
+        /// e.g for long-lived buffers which are rarely used.
+        ///
+        /// Pages are faulted back in, zeroed, when the buffer is next touched,
+        /// so it doesn't have to be dropped and fallibly reallocated.
+        /// Only the whole pages within the buffer are returned,
+        /// and the rest is zeroed by hand.
+        ///
+        /// This assumes that the global allocator's memory is private and
+        /// anonymous, as the system allocator's is.
+        ///
+        /// ```
+        /// let mut buf = trybox::slice::page_aligned(1 << 16)?;
+        /// buf[0] = 1;
+        /// buf.advise_dont_need()?;
+        /// assert_eq!(buf[0], 0);
+        /// # Ok::<_, Box<dyn std::error::Error>>(())
+        /// ```
+        #[cfg(target_os = "linux")]
+        pub fn advise_dont_need(&mut self) -> std::io::Result<()> {
+            // SAFETY: the pages are within our allocation, and we're
+            //         allowed to zero them, which is what this advice
+            //         does to private anonymous memory.
+            unsafe { self.advise_whole_pages(libc::MADV_DONTNEED) }
+        }
+
+        /// Like [`advise_dont_need`](Self::advise_dont_need),
+        /// but with `madvise(MADV_FREE)`,
+        /// which only returns the memory when the kernel is under pressure,
+        /// so is cheaper if the buffer may be used again soon.
+        ///
+        /// Until each page is next written, reading it may return either its
+        /// old contents or zeroes, and may change between reads.
+        ///
+        /// # Safety
+        /// - the buffer must be overwritten before it is next read,
+        ///   e.g with [`fill`](slice::fill).
+        #[cfg(target_os = "linux")]
+        pub unsafe fn advise_free(&mut self) -> std::io::Result<()> {
+            // SAFETY: caller upholds the contract.
+            unsafe { self.advise_whole_pages(libc::MADV_FREE) }
+        }
+
+        /// Apply `advice` to the whole pages in this buffer,
+        /// zeroing the rest.
+        ///
+        /// # Safety
+        /// - `advice` must be safe to apply to this buffer.
+        #[cfg(target_os = "linux")]
+        unsafe fn advise_whole_pages(
+            &mut self,
+            advice: std::os::raw::c_int,
+        ) -> std::io::Result<()> {
+            let whole = self.layout.size() & !(page_size() - 1);
+            self[whole..].fill(0);
+            if whole == 0 {
+                return Ok(());
+            }
+            // SAFETY: the range is within our allocation and page-aligned,
+            //         and the caller upholds the contract.
+            match unsafe { libc::madvise(self.ptr.as_ptr().cast(), whole, advice) } {
+                0 => Ok(()),
+                _ => Err(std::io::Error::last_os_error()),
+            }
+        }
+
+        /// Fault in every page of this buffer now,
+        /// so latency-critical code doesn't take page faults later.
+        ///
+        /// Returns an [`Error`] if the pages can't be backed by memory,
+        /// on Linux 5.14 and later.
+        /// Otherwise, each page is touched in turn,
+        /// which may instead invoke the OOM killer.
+        ///
+        /// ```
+        /// let mut buf = trybox::slice::page_aligned(1 << 16)?;
+        /// buf.populate()?;
+        /// # Ok::<_, trybox::Error>(())
+        /// ```
+        #[cfg_attr(feature = "location", track_caller)]
+        pub fn populate(&mut self) -> Result<(), Error> {
+            // SAFETY: we uniquely own the allocation.
+            match unsafe { populate(self.ptr, self.layout.size()) } {
+                true => Ok(()),
+                false => Err(crate::hook::report_layout(self.layout)),
+            }
+        }
+    }
+
+    impl Deref for PageAligned {
+        type Target = [u8];
+        fn deref(&self) -> &Self::Target {
+            // SAFETY: we allocated and zeroed this many bytes.
+            unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size()) }
+        }
+    }
+
+    impl DerefMut for PageAligned {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            // SAFETY: we allocated and zeroed this many bytes.
+            unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.layout.size()) }
+        }
+    }
+
+    impl Drop for PageAligned {
+        fn drop(&mut self) {
+            // SAFETY: we allocated with this layout, and have not freed it.
+            unsafe { raw::dealloc(self.ptr, self.layout) }
+        }
+    }
+
+    impl fmt::Debug for PageAligned {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("PageAligned")
+                .field("ptr", &self.ptr)
+                .field("len", &self.layout.size())
+                .field("align", &self.layout.align())
+                .finish()
+        }
+    }
+}
@@ -0,0 +1,422 @@
+//! Process-wide counters of the allocations made through this crate.
+//!
+//! ```
+//! let before = trybox::stats::Stats::snapshot();
+//! let _ = trybox::or_drop([0u8; 16]);
+//! let after = trybox::stats::Stats::snapshot();
+//! assert!(after.allocs > before.allocs);
+//! assert!(after.requested_bytes >= before.requested_bytes + 16);
+//! ```
+//!
+//! Memory is freed by the global allocator when e.g a [`Box`]
+//! is dropped, without this crate seeing it,
+//! so bytes currently live are only tracked when [`Track`]
+//! is the global allocator.
+//!
+//! With the `"latency"` feature, allocations are also counted by how long they
+//! took, in [`Stats::success_latency`] and [`Stats::failure_latency`].
+//!
+//! Allocations may also be counted under a tag, with [`new_tagged`](crate::new_tagged):
+//!
+//! ```
+//! let _tokens = trybox::new_tagged("parser", [0u32; 16]);
+//! let stats = trybox::stats::tagged("parser").unwrap();
+//! assert!(stats.bytes >= 64);
+//! ```
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr, slice, str,
+};
+
+use crate::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+static FAILURES: AtomicUsize = AtomicUsize::new(0);
+static REQUESTED_BYTES: AtomicU64 = AtomicU64::new(0);
+static PEAK_ALLOCATION: AtomicUsize = AtomicUsize::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// A point-in-time copy of the counters.
+///
+/// The counters are read individually,
+/// so may be inconsistent with each other if other threads are allocating.
+/// They wrap on overflow.
+/// Byte totals are 64-bit, but wrap at [`usize::MAX`] on targets without
+/// 64-bit atomics, unless the `"portable-atomic"` feature is enabled.
+///
+/// Zero-sized allocations are not counted,
+/// but failures not reported to the [OOM hook](crate::set_oom_hook) are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub struct Stats {
+    /// Successful allocations.
+    pub allocs: usize,
+    /// Failed allocations.
+    pub failures: usize,
+    /// The total size of all allocations, successful or not.
+    pub requested_bytes: u64,
+    /// The size of the largest successful allocation,
+    /// since the last call to [`reset_high_water`].
+    pub peak_allocation: usize,
+    /// The bytes currently allocated in the whole program,
+    /// if [`Track`] is the global allocator.
+    pub live_bytes: usize,
+    /// The most [`live_bytes`](Self::live_bytes)
+    /// since the last call to [`reset_high_water`].
+    pub peak_live_bytes: usize,
+    /// Successful allocations, counted by how long they took,
+    /// see [`latency_bucket`].
+    #[cfg(feature = "latency")]
+    pub success_latency: [usize; LATENCY_BUCKETS],
+    /// Failed allocations, counted by how long they took,
+    /// see [`latency_bucket`].
+    #[cfg(feature = "latency")]
+    pub failure_latency: [usize; LATENCY_BUCKETS],
+}
+
+impl Stats {
+    /// Read the current counters.
+    pub fn snapshot() -> Self {
+        Self {
+            allocs: ALLOCS.load(Ordering::Relaxed),
+            failures: FAILURES.load(Ordering::Relaxed),
+            requested_bytes: REQUESTED_BYTES.load(Ordering::Relaxed),
+            peak_allocation: PEAK_ALLOCATION.load(Ordering::Relaxed),
+            live_bytes: LIVE_BYTES.load(Ordering::Relaxed),
+            peak_live_bytes: PEAK_LIVE_BYTES.load(Ordering::Relaxed),
+            #[cfg(feature = "latency")]
+            success_latency: latency::load(&latency::SUCCESS),
+            #[cfg(feature = "latency")]
+            failure_latency: latency::load(&latency::FAILURE),
+        }
+    }
+}
+
+/// Reset [`Stats::peak_allocation`] to zero,
+/// and [`Stats::peak_live_bytes`] to the current [`Stats::live_bytes`],
+/// e.g at the start of a test scenario.
+pub fn reset_high_water() {
+    PEAK_ALLOCATION.store(0, Ordering::Relaxed);
+    PEAK_LIVE_BYTES.store(LIVE_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+#[cfg(feature = "latency")]
+pub use latency::{latency_bucket, set_clock, LATENCY_BUCKETS};
+
+#[cfg(feature = "latency")]
+pub(crate) use latency::{now, record as latency};
+
+#[cfg(feature = "latency")]
+mod latency {
+    use core::{ops::Range, ptr, time::Duration};
+
+    use crate::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+    /// The number of buckets in [`Stats::success_latency`](super::Stats::success_latency)
+    /// and [`Stats::failure_latency`](super::Stats::failure_latency).
+    pub const LATENCY_BUCKETS: usize = 16;
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicUsize = AtomicUsize::new(0);
+    pub(super) static SUCCESS: [AtomicUsize; LATENCY_BUCKETS] = [ZERO; LATENCY_BUCKETS];
+    pub(super) static FAILURE: [AtomicUsize; LATENCY_BUCKETS] = [ZERO; LATENCY_BUCKETS];
+
+    static CLOCK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+    /// The durations counted by bucket `index` of the latency histograms.
+    ///
+    /// The buckets are powers of four nanoseconds:
+    /// bucket `0` is under a nanosecond, bucket `1` is `1..4ns`,
+    /// bucket `2` is `4..16ns` and so on,
+    /// except that the last bucket has no upper bound.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use trybox::stats::latency_bucket;
+    ///
+    /// assert_eq!(latency_bucket(5), Duration::from_nanos(256)..Duration::from_nanos(1024));
+    /// ```
+    ///
+    /// # Panics
+    /// If `index` isn't less than [`LATENCY_BUCKETS`].
+    pub fn latency_bucket(index: usize) -> Range<Duration> {
+        assert!(index < LATENCY_BUCKETS, "no such latency bucket");
+        let start = match index {
+            0 => Duration::ZERO,
+            _ => Duration::from_nanos(1 << (2 * (index - 1))),
+        };
+        let end = match index == LATENCY_BUCKETS - 1 {
+            true => Duration::MAX,
+            false => Duration::from_nanos(1 << (2 * index)),
+        };
+        start..end
+    }
+
+    /// Time allocations with `clock`, which returns the nanoseconds since an
+    /// arbitrary point, replacing any previous clock.
+    ///
+    /// It should be cheap, monotonic, and must not allocate through this crate.
+    ///
+    /// With the `"std"` feature, [`Instant`](std::time::Instant) is used by
+    /// default.
+    /// Otherwise, latencies aren't recorded until a clock is set.
+    pub fn set_clock(clock: fn() -> u64) {
+        CLOCK.store(clock as *mut (), Ordering::Release);
+    }
+
+    #[inline(always)]
+    pub(crate) fn now() -> Option<u64> {
+        let clock = CLOCK.load(Ordering::Acquire);
+        match clock.is_null() {
+            // SAFETY: only `fn() -> u64`s are stored.
+            false => Some(unsafe {
+                core::mem::transmute::<*mut (), fn() -> u64>(clock)
+            }()),
+            #[cfg(feature = "std")]
+            true => Some(instant_clock()),
+            #[cfg(not(feature = "std"))]
+            true => None,
+        }
+    }
+
+    /// The nanoseconds since this was first called,
+    /// which is monotonic, unlike the system clock.
+    #[cfg(feature = "std")]
+    fn instant_clock() -> u64 {
+        use std::{sync::Once, time::Instant};
+        // `OnceLock` requires Rust 1.70.
+        static INIT: Once = Once::new();
+        static mut ORIGIN: Option<Instant> = None;
+        // SAFETY: `ORIGIN` is only written once, synchronized by `INIT`.
+        INIT.call_once(|| unsafe { ORIGIN = Some(Instant::now()) });
+        // SAFETY: as above, and `ORIGIN` is no longer written.
+        match unsafe { *ptr::addr_of!(ORIGIN) } {
+            Some(origin) => origin.elapsed().as_nanos() as u64,
+            None => 0,
+        }
+    }
+
+    /// Count an allocation which began at `start`, from [`now`].
+    #[inline(always)]
+    pub(crate) fn record(start: Option<u64>, success: bool) {
+        if let (Some(start), Some(end)) = (start, now()) {
+            let nanos = end.saturating_sub(start);
+            // Round the bit length up to a power of four.
+            let bucket = ((64 - nanos.leading_zeros() as usize + 1) / 2).min(LATENCY_BUCKETS - 1);
+            let buckets = match success {
+                true => &SUCCESS,
+                false => &FAILURE,
+            };
+            buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(super) fn load(buckets: &[AtomicUsize; LATENCY_BUCKETS]) -> [usize; LATENCY_BUCKETS] {
+        let mut counts = [0; LATENCY_BUCKETS];
+        for (count, bucket) in counts.iter_mut().zip(buckets) {
+            *count = bucket.load(Ordering::Relaxed)
+        }
+        counts
+    }
+}
+
+/// A [`GlobalAlloc`] which counts the bytes live in the whole program,
+/// for [`Stats::live_bytes`] and [`Stats::peak_live_bytes`].
+///
+/// ```
+/// use std::alloc::System;
+///
+/// #[global_allocator]
+/// static GLOBAL: trybox::stats::Track<System> = trybox::stats::Track(System);
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Track<A>(pub A);
+
+impl<A> Track<A> {
+    fn add(size: usize) {
+        let live = LIVE_BYTES
+            .fetch_add(size, Ordering::Relaxed)
+            .wrapping_add(size);
+        PEAK_LIVE_BYTES.fetch_max(live, Ordering::Relaxed);
+    }
+    fn sub(size: usize) {
+        LIVE_BYTES.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+// SAFETY: forwards to the inner allocator.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Track<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: caller upholds the contract.
+        let ptr = unsafe { self.0.alloc(layout) };
+        if !ptr.is_null() {
+            Self::add(layout.size())
+        }
+        ptr
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: caller upholds the contract.
+        let ptr = unsafe { self.0.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            Self::add(layout.size())
+        }
+        ptr
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: caller upholds the contract.
+        unsafe { self.0.dealloc(ptr, layout) };
+        Self::sub(layout.size())
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // SAFETY: caller upholds the contract.
+        let new = unsafe { self.0.realloc(ptr, layout, new_size) };
+        if !new.is_null() {
+            Self::sub(layout.size());
+            Self::add(new_size);
+        }
+        new
+    }
+}
+
+/// The maximum number of distinct tags which are counted,
+/// see [`new_tagged`](crate::new_tagged).
+pub const TAGS: usize = 32;
+
+/// Counters for a tag passed to [`new_tagged`](crate::new_tagged).
+///
+/// Zero-sized allocations are not counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub struct TagStats {
+    /// Successful allocations.
+    pub allocs: usize,
+    /// Failed allocations.
+    pub failures: usize,
+    /// The total size of the successful allocations,
+    /// which is 64-bit like [`Stats::requested_bytes`].
+    pub bytes: u64,
+}
+
+const EMPTY: usize = 0;
+const WRITING: usize = 1;
+const READY: usize = 2;
+
+struct Slot {
+    state: AtomicUsize,
+    ptr: AtomicPtr<u8>,
+    len: AtomicUsize,
+    allocs: AtomicUsize,
+    failures: AtomicUsize,
+    bytes: AtomicU64,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const SLOT: Slot = Slot {
+    state: AtomicUsize::new(EMPTY),
+    ptr: AtomicPtr::new(ptr::null_mut()),
+    len: AtomicUsize::new(0),
+    allocs: AtomicUsize::new(0),
+    failures: AtomicUsize::new(0),
+    bytes: AtomicU64::new(0),
+};
+
+static SLOTS: [Slot; TAGS] = [SLOT; TAGS];
+
+impl Slot {
+    /// The tag, if this slot has been claimed.
+    fn tag(&self) -> Option<&'static str> {
+        match self.state.load(Ordering::Acquire) {
+            READY => {
+                let ptr = self.ptr.load(Ordering::Relaxed);
+                let len = self.len.load(Ordering::Relaxed);
+                // SAFETY: `ptr` and `len` are from a `&'static str`,
+                //         written before the state was `READY`.
+                Some(unsafe { str::from_utf8_unchecked(slice::from_raw_parts(ptr, len)) })
+            }
+            _ => None,
+        }
+    }
+    fn stats(&self) -> TagStats {
+        TagStats {
+            allocs: self.allocs.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Find or claim the slot for `tag`, or [`None`] if the table is full.
+fn slot(tag: &'static str) -> Option<&'static Slot> {
+    for slot in &SLOTS {
+        loop {
+            match slot
+                .state
+                .compare_exchange(EMPTY, WRITING, Ordering::Acquire, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    slot.ptr.store(tag.as_ptr() as *mut u8, Ordering::Relaxed);
+                    slot.len.store(tag.len(), Ordering::Relaxed);
+                    slot.state.store(READY, Ordering::Release);
+                    return Some(slot);
+                }
+                // Another thread is claiming this slot, possibly for this tag.
+                Err(WRITING) => core::hint::spin_loop(),
+                Err(_) => break,
+            }
+        }
+        if slot.tag() == Some(tag) {
+            return Some(slot);
+        }
+    }
+    None
+}
+
+/// The counters for `tag`, if it has been used.
+pub fn tagged(tag: &str) -> Option<TagStats> {
+    SLOTS
+        .iter()
+        .find(|it| it.tag() == Some(tag))
+        .map(Slot::stats)
+}
+
+/// The counters for every tag which has been used, in the order of first use.
+pub fn tags() -> impl Iterator<Item = (&'static str, TagStats)> {
+    SLOTS
+        .iter()
+        .map_while(|it| it.tag().map(|tag| (tag, it.stats())))
+}
+
+#[inline(always)]
+pub(crate) fn tagged_success(tag: &'static str, layout: Layout) {
+    if layout.size() != 0 {
+        if let Some(slot) = slot(tag) {
+            slot.allocs.fetch_add(1, Ordering::Relaxed);
+            slot.bytes
+                .fetch_add(layout.size() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+#[inline(always)]
+pub(crate) fn tagged_failure(tag: &'static str, layout: Layout) {
+    if layout.size() != 0 {
+        if let Some(slot) = slot(tag) {
+            slot.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[inline(always)]
+pub(crate) fn success(layout: Layout) {
+    ALLOCS.fetch_add(1, Ordering::Relaxed);
+    REQUESTED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+    PEAK_ALLOCATION.fetch_max(layout.size(), Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub(crate) fn failure(layout: Layout) {
+    FAILURES.fetch_add(1, Ordering::Relaxed);
+    REQUESTED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+}
@@ -0,0 +1,72 @@
+//! Writing to stderr without allocating, for [`Error::eprint`](crate::Error::eprint).
+
+use core::fmt;
+use std::io::{self, Write as _};
+
+/// Format `args` and a trailing newline to stderr,
+/// buffering on the stack.
+pub(crate) fn print(args: fmt::Arguments<'_>) -> io::Result<()> {
+    let mut w = Writer {
+        buf: [0; 256],
+        len: 0,
+        res: Ok(()),
+    };
+    let res =
+        fmt::Write::write_fmt(&mut w, args).and_then(|()| fmt::Write::write_str(&mut w, "\n"));
+    let flushed = w.flush();
+    match (res, w.res) {
+        (_, Err(e)) => Err(e),
+        (Err(fmt::Error), Ok(())) => Err(io::ErrorKind::Other.into()),
+        (Ok(()), Ok(())) => flushed,
+    }
+}
+
+struct Writer {
+    buf: [u8; 256],
+    len: usize,
+    /// The first error from the underlying stream.
+    res: io::Result<()>,
+}
+
+impl Writer {
+    fn flush(&mut self) -> io::Result<()> {
+        let res = write_all(&self.buf[..self.len]);
+        self.len = 0;
+        res
+    }
+}
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Bytes, since the buffer may end mid-character.
+        let mut s = s.as_bytes();
+        while !s.is_empty() {
+            if self.len == self.buf.len() {
+                if let Err(e) = self.flush() {
+                    self.res = Err(e);
+                    return Err(fmt::Error);
+                }
+            }
+            let n = s.len().min(self.buf.len() - self.len);
+            self.buf[self.len..][..n].copy_from_slice(&s[..n]);
+            self.len += n;
+            s = &s[n..];
+        }
+        Ok(())
+    }
+}
+
+/// Write directly to file descriptor 2, bypassing [`io::stderr`]'s lock.
+#[cfg(unix)]
+fn write_all(bytes: &[u8]) -> io::Result<()> {
+    use std::{fs::File, mem::ManuallyDrop, os::unix::io::FromRawFd as _};
+    // SAFETY: stderr is open for the life of the process,
+    //         and we don't close it.
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(2) });
+    file.write_all(bytes)
+}
+
+#[cfg(not(unix))]
+fn write_all(bytes: &[u8]) -> io::Result<()> {
+    io::stderr().write_all(bytes)
+}
@@ -0,0 +1,137 @@
+//! A minimal, fallibly-growing string.
+//!
+//! ```
+//! use trybox::string::TryString;
+//!
+//! let mut s = TryString::new();
+//! s.try_push_str("hello")?;
+//! s.try_push('!')?;
+//! assert_eq!(&*s.try_into_boxed_str()?, "hello!");
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use alloc::boxed::Box;
+use core::{fmt, ops::Deref};
+
+use crate::{bytes::Bytes, Error};
+
+/// Like a [`String`], but growth is fallible.
+///
+/// Also implements [`fmt::Write`], returning [`fmt::Error`] on allocation
+/// failure, see [`format::Sink`](crate::format::Sink) to preserve the
+/// [`Error`].
+pub struct TryString {
+    bytes: Bytes,
+}
+
+impl TryString {
+    /// Create an empty string, without allocating.
+    pub fn new() -> Self {
+        Self {
+            bytes: Bytes::new(),
+        }
+    }
+    /// Attempt to create an empty string with room for at least `capacity`
+    /// bytes.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, Error> {
+        let mut this = Self::new();
+        this.try_reserve(capacity)?;
+        Ok(this)
+    }
+    /// Attempt to ensure there is room for at least `additional` more bytes.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), Error> {
+        self.bytes.reserve(additional)
+    }
+    /// Attempt to append `ch`.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_push(&mut self, ch: char) -> Result<(), Error> {
+        self.try_push_str(ch.encode_utf8(&mut [0; 4]))
+    }
+    /// Attempt to append `s`.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_push_str(&mut self, s: &str) -> Result<(), Error> {
+        self.bytes.extend_from_slice(s.as_bytes())
+    }
+    /// The contents.
+    pub fn as_str(&self) -> &str {
+        // SAFETY: only whole `str`s are written.
+        unsafe { core::str::from_utf8_unchecked(self.bytes.as_slice()) }
+    }
+    /// The length of the contents, in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+    /// Whether the string is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The number of bytes which can be held without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.bytes.capacity()
+    }
+    /// Remove the contents, keeping the capacity.
+    pub fn clear(&mut self) {
+        self.bytes.truncate(0)
+    }
+    /// Attempt to convert to an exactly-sized `Box<str>`,
+    /// which allocates unless the string is at capacity.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_into_boxed_str(self) -> Result<Box<str>, Error> {
+        let bytes = self.bytes.into_boxed_slice()?;
+        // SAFETY: only whole `str`s are written.
+        Ok(unsafe { alloc::str::from_boxed_utf8_unchecked(bytes) })
+    }
+}
+
+impl Default for TryString {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for TryString {
+    type Target = str;
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl fmt::Write for TryString {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.try_push_str(s).map_err(|_| fmt::Error)
+    }
+}
+
+impl fmt::Display for TryString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        str::fmt(self, f)
+    }
+}
+
+impl fmt::Debug for TryString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        str::fmt(self, f)
+    }
+}
+
+impl PartialEq for TryString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for TryString {}
+
+impl PartialEq<str> for TryString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for TryString {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
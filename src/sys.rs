@@ -0,0 +1,159 @@
+//! Platform helpers.
+
+/// The system's page size, which is always a power of two.
+pub(crate) fn page_size() -> usize {
+    // SAFETY: FFI call with no preconditions.
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 && (size as usize).is_power_of_two() => size as usize,
+        _ => 4096,
+    }
+}
+
+/// Create a private, anonymous, read-write mapping of `len` bytes,
+/// counting towards the thread's [`limit`](crate::limit).
+///
+/// `len` must be non-zero.
+#[cfg(any(feature = "mmap", feature = "numa"))]
+pub(crate) fn map(len: usize) -> Option<core::ptr::NonNull<u8>> {
+    if !crate::limit::take(len) {
+        return None;
+    }
+    // SAFETY: FFI call requesting a new, private mapping.
+    let base = unsafe {
+        libc::mmap(
+            core::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        )
+    };
+    counted(base, len)
+}
+
+/// Report a failure to create a mapping of `len` bytes for a `T`,
+/// e.g to the [OOM hook](crate::set_oom_hook).
+#[cfg(any(feature = "mmap", feature = "numa"))]
+#[cold]
+#[inline(never)]
+#[cfg_attr(feature = "location", track_caller)]
+pub(crate) fn report_map<T>(len: usize) {
+    match core::alloc::Layout::from_size_align(len, page_size()) {
+        Ok(layout) => crate::hook::report_layout(layout),
+        Err(_) => crate::hook::report(crate::repr::Repr::of::<T>()),
+    };
+}
+
+/// The mapping at `base`, refunding it if it failed,
+/// and counting the attempt for the `"stats"` feature.
+#[cfg(any(feature = "mmap", feature = "numa"))]
+fn counted(base: *mut libc::c_void, len: usize) -> Option<core::ptr::NonNull<u8>> {
+    let ptr = match base == libc::MAP_FAILED {
+        true => {
+            crate::limit::refund(len);
+            None
+        }
+        false => core::ptr::NonNull::new(base.cast()),
+    };
+    if let Ok(layout) = core::alloc::Layout::from_size_align(len, 1) {
+        match ptr {
+            Some(_) => crate::hook::success(layout),
+            None => crate::hook::failure(layout),
+        }
+    }
+    ptr
+}
+
+/// Remove a mapping created by [`map`].
+///
+/// # Safety
+/// - `base` and `len` must be from a call to [`map`].
+/// - the mapping must not be used again.
+#[cfg(any(feature = "mmap", feature = "numa"))]
+pub(crate) unsafe fn unmap(base: core::ptr::NonNull<u8>, len: usize) {
+    // SAFETY: caller upholds the contract.
+    unsafe { libc::munmap(base.as_ptr().cast(), len) };
+}
+
+/// Like [`map`], but backed by an anonymous temporary file rather than swap.
+#[cfg(feature = "mmap")]
+pub(crate) fn map_file(len: usize) -> Option<core::ptr::NonNull<u8>> {
+    if !crate::limit::take(len) {
+        return None;
+    }
+    // SAFETY: FFI calls, checking each for failure.
+    //         The mapping keeps the file alive after it is closed.
+    let base = unsafe {
+        let file = libc::tmpfile();
+        match file.is_null() {
+            true => libc::MAP_FAILED,
+            false => {
+                let fd = libc::fileno(file);
+                let base = match libc::ftruncate(fd, len as libc::off_t) {
+                    0 => libc::mmap(
+                        core::ptr::null_mut(),
+                        len,
+                        libc::PROT_READ | libc::PROT_WRITE,
+                        libc::MAP_SHARED,
+                        fd,
+                        0,
+                    ),
+                    _ => libc::MAP_FAILED,
+                };
+                libc::fclose(file);
+                base
+            }
+        }
+    };
+    counted(base, len)
+}
+
+/// Fault in every page of `len` bytes at `base` for writing,
+/// returning whether they could all be backed by memory.
+///
+/// On Linux, this uses `madvise(MADV_POPULATE_WRITE)`,
+/// which reports failure rather than raising `SIGBUS`.
+/// Elsewhere, or on kernels older than 5.14, each page is touched in turn.
+///
+/// # Safety
+/// - `base` must be valid for reads and writes of `len` bytes.
+/// - the memory must not be accessed concurrently.
+pub(crate) unsafe fn populate(base: core::ptr::NonNull<u8>, len: usize) -> bool {
+    if len == 0 {
+        return true;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let page = page_size();
+        // `madvise` requires a page-aligned start.
+        let offset = base.as_ptr() as usize & (page - 1);
+        // SAFETY: the range covers our memory, rounded out to whole pages,
+        //         and this advice doesn't change the contents of memory.
+        match unsafe {
+            libc::madvise(
+                base.as_ptr().sub(offset).cast(),
+                len + offset,
+                libc::MADV_POPULATE_WRITE,
+            )
+        } {
+            0 => return true,
+            // Unsupported by this kernel.
+            _ if std::io::Error::last_os_error().raw_os_error() == Some(libc::EINVAL) => {}
+            _ => return false,
+        }
+    }
+    let page = page_size();
+    let mut ix = 0;
+    while ix < len {
+        // SAFETY: `ix` is within the memory, which the caller lets us access.
+        //         Rewriting the same value faults the page in for writing,
+        //         without changing the contents.
+        unsafe {
+            let it = base.as_ptr().add(ix);
+            it.write_volatile(it.read_volatile());
+        }
+        ix += page;
+    }
+    true
+}
@@ -0,0 +1,739 @@
+//! A [`GlobalAlloc`] which can be made to fail, for testing allocation
+//! failure handling, with the `"testing"` feature.
+//!
+//! ```
+//! use trybox::testing::FailOrFallback;
+//!
+//! #[global_allocator]
+//! static ALLOC: FailOrFallback<std::alloc::System> = FailOrFallback::system();
+//!
+//! # fn main() {
+//! # #[cfg(feature = "backtrace")]
+//! # trybox::Backtrace::init();
+//! {
+//!     let _failing = ALLOC.failing();
+//!     assert!(trybox::or_drop(1u8).is_err());
+//! }
+//! assert!(trybox::or_drop(1u8).is_ok());
+//! # }
+//! ```
+//!
+//! See also [`fail_allocations`], to fail allocations without a reference
+//! to the allocator.
+//!
+//! Note that the standard library typically aborts when its own allocations
+//! fail, e.g when printing or formatting to a [`String`],
+//! so keep the failing sections small.
+
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::AtomicU64;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    fmt,
+    marker::PhantomData,
+    ptr,
+    sync::atomic::AtomicBool,
+};
+
+use crate::atomic::{AtomicUsize, Ordering};
+
+/// [`FailOrFallback::countdown`] when there is no countdown.
+const NO_COUNTDOWN: usize = usize::MAX;
+
+/// An allocator which fails on demand, and otherwise uses a fallback.
+///
+/// With the `"std"` feature, allocations never fail while the thread is
+/// panicking, so that e.g failed assertions are reported.
+pub struct FailOrFallback<T> {
+    /// Allocations larger than this fail.
+    fail_above: AtomicUsize,
+    /// The number of allocations to allow before failing.
+    countdown: AtomicUsize,
+    /// Whether to fail just one allocation once the countdown reaches zero.
+    once: AtomicBool,
+    #[cfg(target_has_atomic = "64")]
+    random: Random,
+    /// The number of calls to `alloc`, successful or not.
+    attempts: AtomicUsize,
+    fallback: T,
+}
+
+#[cfg(feature = "std")]
+impl FailOrFallback<std::alloc::System> {
+    /// Wrap the [`System`](std::alloc::System) allocator.
+    pub const fn system() -> Self {
+        Self::new(std::alloc::System)
+    }
+}
+
+impl<T> FailOrFallback<T> {
+    /// Create a new allocator, which uses the fallback by default.
+    ///
+    /// This behaviour allows e.g the rust runtime to initialize when using
+    /// `#[global_allocator]`.
+    pub const fn new(fallback: T) -> Self {
+        FailOrFallback {
+            fail_above: AtomicUsize::new(usize::MAX),
+            countdown: AtomicUsize::new(NO_COUNTDOWN),
+            once: AtomicBool::new(false),
+            #[cfg(target_has_atomic = "64")]
+            random: Random {
+                threshold: AtomicU64::new(0),
+                state: AtomicU64::new(0),
+                min_size: AtomicUsize::new(0),
+            },
+            attempts: AtomicUsize::new(0),
+            fallback,
+        }
+    }
+    /// Allocations after this call will always fail.
+    pub fn fail(&self) {
+        self.fail_larger_than(0);
+    }
+    /// Allocations of more than `size` bytes after this call will fail.
+    pub fn fail_larger_than(&self, size: usize) {
+        self.fail_above.store(size, Ordering::Release);
+    }
+    /// Allow the next `n` allocations, then fail every allocation after that.
+    pub fn fail_after(&self, n: usize) {
+        self.countdown(n, false)
+    }
+    /// Allow the next `n` allocations, fail the one after that,
+    /// then use the fallback allocator again.
+    ///
+    /// So `fail_nth(0)` fails just the next allocation.
+    pub fn fail_nth(&self, n: usize) {
+        self.countdown(n, true)
+    }
+    fn countdown(&self, n: usize, once: bool) {
+        self.once.store(once, Ordering::Release);
+        // `NO_COUNTDOWN` allocations may as well be infinite.
+        self.countdown.store(n, Ordering::Release);
+    }
+    /// Fail allocations of at least `min_size` bytes with the given
+    /// `probability`, from `0.0` (never) to `1.0` (always).
+    ///
+    /// The failures are pseudo-random, and reproducible for a given `seed`
+    /// if allocations are made in the same order, e.g from a single thread.
+    ///
+    /// ```
+    /// # let alloc = trybox::testing::FailOrFallback::new(std::alloc::System);
+    /// // Fail a tenth of allocations of 4 KiB or more.
+    /// alloc.fail_randomly(0.1, 0xdeadbeef, 4096);
+    /// ```
+    #[cfg(target_has_atomic = "64")]
+    pub fn fail_randomly(&self, probability: f64, seed: u64, min_size: usize) {
+        self.random.min_size.store(min_size, Ordering::Release);
+        self.random.state.store(seed, Ordering::Release);
+        // Saturates at the bounds.
+        let threshold = (probability * u64::MAX as f64) as u64;
+        self.random.threshold.store(threshold, Ordering::Release);
+    }
+    /// Allocations after this call will use the fallback allocator.
+    pub fn fallback(&self) {
+        #[cfg(target_has_atomic = "64")]
+        self.random.threshold.store(0, Ordering::Release);
+        self.countdown.store(NO_COUNTDOWN, Ordering::Release);
+        self.fail_above.store(usize::MAX, Ordering::Release);
+    }
+    /// Whether to randomly fail an allocation of `layout`.
+    fn random(&self, layout: Layout) -> bool {
+        #[cfg(target_has_atomic = "64")]
+        {
+            let threshold = self.random.threshold.load(Ordering::Acquire);
+            threshold != 0
+                && layout.size() >= self.random.min_size.load(Ordering::Acquire)
+                && self.random.next() < threshold
+        }
+        #[cfg(not(target_has_atomic = "64"))]
+        {
+            let _ = layout;
+            false
+        }
+    }
+    /// Whether the countdown has run out, updating it.
+    fn count_down(&self) -> bool {
+        let res = self
+            .countdown
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |it| match it {
+                0 | NO_COUNTDOWN => None,
+                n => Some(n - 1),
+            });
+        match res {
+            Err(0) => match self.once.load(Ordering::Acquire) {
+                // Only one thread gets to fail.
+                true => self
+                    .countdown
+                    .compare_exchange(0, NO_COUNTDOWN, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok(),
+                false => true,
+            },
+            _ => false,
+        }
+    }
+    /// [`fail`](Self::fail) until the returned guard is dropped,
+    /// including on panic, then [`fallback`](Self::fallback).
+    pub fn failing(&self) -> Failing<'_, T> {
+        self.fail();
+        Failing(self)
+    }
+    /// The number of allocations attempted so far.
+    pub fn attempts(&self) -> usize {
+        self.attempts.load(Ordering::Acquire)
+    }
+}
+
+impl<T: Default> Default for FailOrFallback<T> {
+    /// See [`FailOrFallback::new`].
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> fmt::Debug for FailOrFallback<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FailOrFallback")
+            .field("fail_above", &self.fail_above.load(Ordering::Acquire))
+            .field("countdown", &self.countdown.load(Ordering::Acquire))
+            .field("once", &self.once)
+            .field("attempts", &self.attempts())
+            .finish_non_exhaustive()
+    }
+}
+
+// SAFETY: allocations either fail, or are forwarded to the fallback.
+unsafe impl<T: GlobalAlloc> GlobalAlloc for FailOrFallback<T> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.attempts.fetch_add(1, Ordering::AcqRel);
+        #[cfg(feature = "std")]
+        RECORDING.with(|it| {
+            if let Some(recording) = &mut *it.borrow_mut() {
+                recording.push(layout)
+            }
+        });
+        // Panicking allocates, which would otherwise abort.
+        #[cfg(feature = "std")]
+        let panicking = std::thread::panicking();
+        #[cfg(not(feature = "std"))]
+        let panicking = false;
+        if !panicking
+            && (guarded()
+                || self.count_down()
+                || self.random(layout)
+                || layout.size() > self.fail_above.load(Ordering::Acquire))
+        {
+            #[cfg(feature = "std")]
+            RECORDING.with(|it| {
+                if let Some(recording) = &mut *it.borrow_mut() {
+                    recording.fail_last()
+                }
+            });
+            return ptr::null_mut();
+        }
+        // SAFETY: caller upholds the contract.
+        let ptr = unsafe { self.fallback.alloc(layout) };
+        #[cfg(feature = "std")]
+        if !ptr.is_null() {
+            THREAD_LIVE.with(|it| it.set(it.get().wrapping_add(layout.size())));
+            THREAD_LIVE_COUNT.with(|it| it.set(it.get().wrapping_add(1)));
+        }
+        ptr
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        #[cfg(feature = "std")]
+        {
+            THREAD_LIVE.with(|it| it.set(it.get().wrapping_sub(layout.size())));
+            THREAD_LIVE_COUNT.with(|it| it.set(it.get().wrapping_sub(1)));
+        }
+        // SAFETY: failed allocations are never freed,
+        //         so `ptr` is from the fallback.
+        unsafe { self.fallback.dealloc(ptr, layout) }
+    }
+}
+
+#[cfg(target_has_atomic = "64")]
+struct Random {
+    /// Draws below this fail, so zero is disabled.
+    threshold: AtomicU64,
+    state: AtomicU64,
+    min_size: AtomicUsize,
+}
+
+#[cfg(target_has_atomic = "64")]
+impl Random {
+    /// The next number from a [SplitMix64](https://prng.di.unimi.it/splitmix64.c) generator.
+    fn next(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9e3779b97f4a7c15, Ordering::AcqRel)
+            .wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// The number of live [`fail_allocations`] guards.
+static PROCESS_GUARDS: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    /// The number of live [`fail_thread_allocations`] guards on this thread.
+    static THREAD_GUARDS: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+    /// The allocations to allow on this thread before failing one,
+    /// for [`oom_test`].
+    static THREAD_COUNTDOWN: core::cell::Cell<usize> = const { core::cell::Cell::new(NO_COUNTDOWN) };
+    /// The schedule for [`replay`], and the index of the next allocation.
+    static REPLAY: core::cell::Cell<Option<(*const [usize], usize)>> = const { core::cell::Cell::new(None) };
+    /// Whether [`THREAD_COUNTDOWN`] has failed an allocation.
+    static THREAD_COUNTED_DOWN: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+    /// The bytes allocated on this thread, less those freed on this thread.
+    static THREAD_LIVE: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+    /// Like [`THREAD_LIVE`], but the number of allocations.
+    static THREAD_LIVE_COUNT: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+
+/// Whether a guard or [`oom_test`] is failing this allocation.
+fn guarded() -> bool {
+    #[cfg(feature = "std")]
+    if THREAD_GUARDS.with(|it| it.get()) != 0 {
+        return true;
+    }
+    #[cfg(feature = "std")]
+    match THREAD_COUNTDOWN.with(|it| it.get()) {
+        NO_COUNTDOWN => {}
+        0 => {
+            THREAD_COUNTDOWN.with(|it| it.set(NO_COUNTDOWN));
+            THREAD_COUNTED_DOWN.with(|it| it.set(true));
+            return true;
+        }
+        n => THREAD_COUNTDOWN.with(|it| it.set(n - 1)),
+    }
+    #[cfg(feature = "std")]
+    if let Some((schedule, ix)) = REPLAY.with(|it| it.get()) {
+        REPLAY.with(|it| it.set(Some((schedule, ix + 1))));
+        // SAFETY: `replay` keeps the schedule alive while it's set.
+        if unsafe { &*schedule }.contains(&ix) {
+            return true;
+        }
+    }
+    PROCESS_GUARDS.load(Ordering::Acquire) != 0
+}
+
+/// Make allocations from every [`FailOrFallback`] fail
+/// until the returned guard is dropped, including on panic.
+///
+/// ```
+/// # use trybox::testing::FailOrFallback;
+/// # #[global_allocator]
+/// # static ALLOC: FailOrFallback<std::alloc::System> = FailOrFallback::system();
+/// # fn main() {
+/// # #[cfg(feature = "backtrace")]
+/// # trybox::Backtrace::init();
+/// let guard = trybox::testing::fail_allocations();
+/// assert!(trybox::or_drop(1u8).is_err());
+/// drop(guard);
+/// assert!(trybox::or_drop(1u8).is_ok());
+/// # }
+/// ```
+///
+/// This has no effect unless a [`FailOrFallback`] is the global allocator.
+/// Guards may be nested.
+pub fn fail_allocations() -> FailAllocations {
+    PROCESS_GUARDS.fetch_add(1, Ordering::AcqRel);
+    FailAllocations {
+        thread: false,
+        _not_send: PhantomData,
+    }
+}
+
+/// Like [`fail_allocations`], but only for allocations on the current thread,
+/// e.g so other tests may run in parallel.
+#[cfg(feature = "std")]
+pub fn fail_thread_allocations() -> FailAllocations {
+    THREAD_GUARDS.with(|it| it.set(it.get() + 1));
+    FailAllocations {
+        thread: true,
+        _not_send: PhantomData,
+    }
+}
+
+/// Returned from [`fail_allocations`].
+#[must_use = "allocations only fail while the guard is alive"]
+#[derive(Debug)]
+pub struct FailAllocations {
+    thread: bool,
+    /// Thread guards must be dropped on the same thread.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for FailAllocations {
+    fn drop(&mut self) {
+        match self.thread {
+            #[cfg(feature = "std")]
+            true => THREAD_GUARDS.with(|it| it.set(it.get() - 1)),
+            _ => {
+                PROCESS_GUARDS.fetch_sub(1, Ordering::AcqRel);
+            }
+        }
+    }
+}
+
+/// Returned from [`FailOrFallback::failing`].
+#[must_use = "allocations only fail while the guard is alive"]
+pub struct Failing<'a, T>(&'a FailOrFallback<T>);
+
+impl<T> Drop for Failing<'_, T> {
+    fn drop(&mut self) {
+        self.0.fallback()
+    }
+}
+
+impl<T> fmt::Debug for Failing<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Failing").field(self.0).finish()
+    }
+}
+
+/// The number of layouts kept by a [`Recording`].
+#[cfg(feature = "std")]
+pub const RECORDED_LAYOUTS: usize = 32;
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static RECORDING: core::cell::RefCell<Option<Recording>> =
+        const { core::cell::RefCell::new(None) };
+}
+
+/// The allocations attempted on a thread, returned from [`record`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recording {
+    count: usize,
+    bytes: usize,
+    layouts: [Layout; RECORDED_LAYOUTS],
+    failed: usize,
+    failures: [usize; RECORDED_LAYOUTS],
+}
+
+#[cfg(feature = "std")]
+impl Recording {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            bytes: 0,
+            layouts: [Layout::new::<()>(); RECORDED_LAYOUTS],
+            failed: 0,
+            failures: [0; RECORDED_LAYOUTS],
+        }
+    }
+    fn push(&mut self, layout: Layout) {
+        if let Some(it) = self.layouts.get_mut(self.count) {
+            *it = layout
+        }
+        self.count += 1;
+        self.bytes = self.bytes.saturating_add(layout.size());
+    }
+    fn fail_last(&mut self) {
+        if let Some(it) = self.failures.get_mut(self.failed) {
+            *it = self.count - 1
+        }
+        self.failed += 1;
+    }
+    /// The number of allocations attempted, successful or not.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+    /// The total size of the allocations attempted.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+    /// The layouts of the first [`RECORDED_LAYOUTS`] allocations attempted.
+    pub fn layouts(&self) -> &[Layout] {
+        &self.layouts[..self.count.min(RECORDED_LAYOUTS)]
+    }
+    /// The (zero-based) indices of the first [`RECORDED_LAYOUTS`]
+    /// allocations which failed, to be passed to [`replay`].
+    pub fn failures(&self) -> &[usize] {
+        &self.failures[..self.failed.min(RECORDED_LAYOUTS)]
+    }
+}
+
+/// Run `f`, recording the allocations it attempts on this thread
+/// through a [`FailOrFallback`] global allocator.
+///
+/// ```
+/// # use trybox::testing::FailOrFallback;
+/// # #[global_allocator]
+/// # static ALLOC: FailOrFallback<std::alloc::System> = FailOrFallback::system();
+/// # fn main() {
+/// let (_, recording) = trybox::testing::record(|| trybox::or_drop(1u32));
+/// assert_eq!(recording.layouts(), [core::alloc::Layout::new::<u32>()]);
+/// # }
+/// ```
+///
+/// Allocations within nested recordings are only recorded by the innermost.
+#[cfg(feature = "std")]
+pub fn record<R>(f: impl FnOnce() -> R) -> (R, Recording) {
+    /// Restores the outer recording on exit, including on panic.
+    struct Restore(Option<Recording>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            RECORDING.with(|it| *it.borrow_mut() = self.0)
+        }
+    }
+    let _restore = Restore(RECORDING.with(|it| it.replace(Some(Recording::new()))));
+    let res = f();
+    let recording = RECORDING.with(|it| *it.borrow());
+    (res, recording.unwrap_or(Recording::new()))
+}
+
+/// Run `f`, panicking if it attempts more than `at_most` allocations,
+/// see [`record`].
+///
+/// ```should_panic
+/// # use trybox::testing::FailOrFallback;
+/// # #[global_allocator]
+/// # static ALLOC: FailOrFallback<std::alloc::System> = FailOrFallback::system();
+/// # fn main() {
+/// trybox::testing::assert_allocations(1, || (Box::new(1), Box::new(2)));
+/// # }
+/// ```
+#[cfg(feature = "std")]
+#[track_caller]
+pub fn assert_allocations<R>(at_most: usize, f: impl FnOnce() -> R) -> R {
+    let (res, recording) = record(f);
+    assert!(
+        recording.count() <= at_most,
+        "expected at most {at_most} allocations, but {} were attempted: {:?}",
+        recording.count(),
+        recording.layouts()
+    );
+    res
+}
+
+/// Run `f`, panicking if it attempts any allocations, see [`record`].
+///
+/// ```
+/// # use trybox::testing::FailOrFallback;
+/// # #[global_allocator]
+/// # static ALLOC: FailOrFallback<std::alloc::System> = FailOrFallback::system();
+/// # fn main() {
+/// // Zero-sized types don't need allocating.
+/// trybox::testing::assert_no_alloc(|| trybox::or_drop(()));
+/// # }
+/// ```
+#[cfg(feature = "std")]
+#[track_caller]
+pub fn assert_no_alloc<R>(f: impl FnOnce() -> R) -> R {
+    assert_allocations(0, f)
+}
+
+/// Run `f` repeatedly, failing its first allocation on this thread,
+/// then its second, and so on, until it runs without any failing,
+/// as generated by [`#[trybox::oom_test]`](https://docs.rs/trybox-macros).
+///
+/// ```
+/// # use trybox::testing::FailOrFallback;
+/// # #[global_allocator]
+/// # static ALLOC: FailOrFallback<std::alloc::System> = FailOrFallback::system();
+/// # fn main() {
+/// # #[cfg(feature = "backtrace")]
+/// # trybox::Backtrace::init();
+/// trybox::testing::oom_test(|| {
+///     let a = trybox::or_drop(1);
+///     let b = trybox::or_drop(2);
+///     assert!(a.is_err() || b.is_err() || (*a.unwrap(), *b.unwrap()) == (1, 2));
+/// });
+/// # }
+/// ```
+///
+/// This panics if `f` panics, with its message and the allocation which
+/// failed, or if any memory allocated on this thread by a run isn't freed
+/// by the end of it.
+/// Infallible allocations, e.g with [`Box::new`](alloc::boxed::Box::new),
+/// will typically abort the process when they're made to fail.
+///
+/// Like the rest of this module,
+/// this requires a [`FailOrFallback`] to be the global allocator.
+#[cfg(feature = "std")]
+#[track_caller]
+pub fn oom_test(f: impl Fn()) {
+    let mut n = 0;
+    loop {
+        let check = leak_check();
+        THREAD_COUNTDOWN.with(|it| it.set(n));
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(&f));
+        THREAD_COUNTDOWN.with(|it| it.set(NO_COUNTDOWN));
+        let failed = THREAD_COUNTED_DOWN.with(|it| it.replace(false));
+        let which = match failed {
+            true => Which::Nth(n),
+            false => Which::None,
+        };
+        if let Err(payload) = res {
+            let message = match payload.downcast_ref::<&str>() {
+                Some(it) => Some(*it),
+                None => payload
+                    .downcast_ref::<std::string::String>()
+                    .map(|it| it.as_str()),
+            };
+            match message {
+                Some(message) => panic!("oom_test panicked with {which}: {message}"),
+                // Other payloads are passed on unchanged.
+                None => std::panic::resume_unwind(payload),
+            }
+        }
+        let leaked = check.leaked();
+        core::mem::forget(check);
+        assert!(
+            leaked.bytes <= 0,
+            "oom_test leaked {} bytes in {} allocations with {which}",
+            leaked.bytes,
+            leaked.allocations
+        );
+        if !failed {
+            return;
+        }
+        n += 1;
+    }
+}
+
+#[cfg(feature = "std")]
+enum Which {
+    None,
+    Nth(usize),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for Which {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Which::None => f.write_str("no allocations failing"),
+            // One-based, for humans.
+            Which::Nth(n) => f.write_fmt(format_args!("allocation {} failing", n + 1)),
+        }
+    }
+}
+
+/// Run `f`, failing the allocations on this thread with the (zero-based)
+/// indices in `schedule`, e.g from a [`Recording`] of a failing run.
+///
+/// ```
+/// # use trybox::testing::FailOrFallback;
+/// # #[global_allocator]
+/// # static ALLOC: FailOrFallback<std::alloc::System> = FailOrFallback::system();
+/// # fn main() {
+/// # #[cfg(feature = "backtrace")]
+/// # trybox::Backtrace::init();
+/// let (a, b) = trybox::testing::replay(&[1], || (trybox::or_drop(1), trybox::or_drop(2)));
+/// assert!(a.is_ok() && b.is_err());
+/// # }
+/// ```
+///
+/// Replays may not be nested.
+#[cfg(feature = "std")]
+pub fn replay<R>(schedule: &[usize], f: impl FnOnce() -> R) -> R {
+    /// Clears the schedule on exit, including on panic.
+    struct Clear;
+    impl Drop for Clear {
+        fn drop(&mut self) {
+            REPLAY.with(|it| it.set(None))
+        }
+    }
+    let outer = REPLAY.with(|it| it.replace(Some((schedule as *const [usize], 0))));
+    assert!(outer.is_none(), "`replay` may not be nested");
+    let _clear = Clear;
+    f()
+}
+
+/// Check that the allocations on this thread within a scope are freed,
+/// panicking on drop if they aren't.
+///
+/// ```should_panic
+/// # use trybox::testing::FailOrFallback;
+/// # #[global_allocator]
+/// # static ALLOC: FailOrFallback<std::alloc::System> = FailOrFallback::system();
+/// # fn main() {
+/// let _check = trybox::testing::leak_check();
+/// Box::leak(Box::new(1));
+/// # }
+/// ```
+///
+/// Only memory allocated and freed on this thread is tracked,
+/// so memory sent to other threads to be freed counts as leaked.
+#[cfg(feature = "std")]
+pub fn leak_check() -> LeakCheck {
+    LeakCheck {
+        bytes: THREAD_LIVE.with(|it| it.get()),
+        allocations: THREAD_LIVE_COUNT.with(|it| it.get()),
+        _not_send: PhantomData,
+    }
+}
+
+/// Run `f`, panicking if it doesn't free the memory it allocates on this
+/// thread, see [`leak_check`].
+#[cfg(feature = "std")]
+#[track_caller]
+pub fn assert_no_leaks<R>(f: impl FnOnce() -> R) -> R {
+    let check = leak_check();
+    let res = f();
+    let leaked = check.leaked();
+    core::mem::forget(check);
+    assert!(
+        leaked.bytes <= 0,
+        "leaked {} bytes in {} allocations",
+        leaked.bytes,
+        leaked.allocations
+    );
+    res
+}
+
+/// Returned from [`leak_check`].
+#[cfg(feature = "std")]
+#[must_use = "leaks are checked when the guard is dropped"]
+#[derive(Debug)]
+pub struct LeakCheck {
+    bytes: usize,
+    allocations: usize,
+    _not_send: PhantomData<*const ()>,
+}
+
+/// The memory allocated but not freed since a [`leak_check`].
+///
+/// This is negative if more was freed than allocated.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Leaked {
+    /// The number of bytes.
+    pub bytes: isize,
+    /// The number of allocations.
+    pub allocations: isize,
+}
+
+#[cfg(feature = "std")]
+impl LeakCheck {
+    /// The memory leaked so far.
+    pub fn leaked(&self) -> Leaked {
+        Leaked {
+            bytes: THREAD_LIVE.with(|it| it.get()).wrapping_sub(self.bytes) as isize,
+            allocations: THREAD_LIVE_COUNT
+                .with(|it| it.get())
+                .wrapping_sub(self.allocations) as isize,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for LeakCheck {
+    fn drop(&mut self) {
+        let leaked = self.leaked();
+        // Don't panic while panicking, which would abort.
+        if leaked.bytes > 0 && !std::thread::panicking() {
+            panic!(
+                "leaked {} bytes in {} allocations",
+                leaked.bytes, leaked.allocations
+            )
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Allocation-free backtrace capture, for the `"backtrace"` feature.
+//!
+//! Frames are written to a ring of static slots,
+//! so a slot may be overwritten by later failures before it is read.
+
+use core::{
+    ffi::c_void,
+    fmt, ptr,
+    sync::atomic::{AtomicPtr, AtomicU8, AtomicUsize, Ordering},
+};
+
+const SLOTS: usize = 16;
+const FRAMES: usize = 32;
+
+/// A seqlock: `sequence` is odd while the slot is being written.
+struct Slot {
+    sequence: AtomicUsize,
+    ticket: AtomicUsize,
+    len: AtomicUsize,
+    /// Kept as pointers, rather than addresses, for their provenance.
+    frames: [AtomicPtr<c_void>; FRAMES],
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const SLOT: Slot = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const FRAME: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+    Slot {
+        sequence: AtomicUsize::new(0),
+        ticket: AtomicUsize::new(0),
+        len: AtomicUsize::new(0),
+        frames: [FRAME; FRAMES],
+    }
+};
+
+static RING: [Slot; SLOTS] = [SLOT; SLOTS];
+static NEXT: AtomicUsize = AtomicUsize::new(1);
+
+const UNKNOWN: u8 = 0;
+const DISABLED: u8 = 1;
+const ENABLED: u8 = 2;
+static STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+/// As for [`std::backtrace::Backtrace::capture`],
+/// backtraces are enabled if `RUST_BACKTRACE` is set to anything other than
+/// `0`.
+///
+/// Checking the environment may allocate, so this is cached.
+fn enabled() -> bool {
+    match STATE.load(Ordering::Relaxed) {
+        UNKNOWN => {
+            let enabled = match std::env::var_os("RUST_BACKTRACE") {
+                Some(it) => it != "0",
+                None => false,
+            };
+            STATE.store(
+                match enabled {
+                    true => ENABLED,
+                    false => DISABLED,
+                },
+                Ordering::Relaxed,
+            );
+            enabled
+        }
+        state => state == ENABLED,
+    }
+}
+
+/// Capture a backtrace, returning a ticket for [`Backtrace::get`],
+/// or `0` if backtraces are disabled.
+#[inline(never)]
+#[cold]
+pub(crate) fn capture() -> usize {
+    if !enabled() {
+        return 0;
+    }
+    let ticket = NEXT.fetch_add(1, Ordering::Relaxed);
+    let slot = &RING[ticket % SLOTS];
+    let sequence = slot.sequence.load(Ordering::Relaxed);
+    if sequence % 2 == 1
+        || slot
+            .sequence
+            .compare_exchange(sequence, sequence + 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+    {
+        // Another thread is writing to this slot, so give up.
+        return 0;
+    }
+    let mut len = 0;
+    // `trace` doesn't allocate, and it's fine to skip this function's frame.
+    backtrace::trace(|frame| {
+        slot.frames[len].store(frame.ip(), Ordering::Relaxed);
+        len += 1;
+        len < FRAMES
+    });
+    slot.len.store(len, Ordering::Relaxed);
+    slot.ticket.store(ticket, Ordering::Relaxed);
+    slot.sequence.fetch_add(1, Ordering::Release);
+    ticket
+}
+
+/// The stack of the thread when an [`Error`](crate::Error) was created,
+/// returned from [`Error::backtrace`](crate::Error::backtrace).
+///
+/// Up to 32 frames are captured.
+/// Printing the backtrace resolves symbols, which may allocate.
+#[derive(Clone, Copy)]
+pub struct Backtrace {
+    len: usize,
+    frames: [*mut c_void; FRAMES],
+}
+
+// SAFETY: the frames are only used to resolve symbols, not dereferenced.
+unsafe impl Send for Backtrace {}
+// SAFETY: as above.
+unsafe impl Sync for Backtrace {}
+
+impl Backtrace {
+    /// Check `RUST_BACKTRACE` ahead of time,
+    /// which may allocate, rather than when the first error is created.
+    pub fn init() {
+        enabled();
+    }
+    pub(crate) fn get(ticket: usize) -> Option<Self> {
+        if ticket == 0 {
+            return None;
+        }
+        let slot = &RING[ticket % SLOTS];
+        let before = slot.sequence.load(Ordering::Acquire);
+        let mut this = Self {
+            len: slot.len.load(Ordering::Relaxed),
+            frames: [ptr::null_mut(); FRAMES],
+        };
+        for (dst, src) in this.frames.iter_mut().zip(&slot.frames) {
+            *dst = src.load(Ordering::Relaxed);
+        }
+        let current = slot.ticket.load(Ordering::Relaxed);
+        core::sync::atomic::fence(Ordering::Acquire);
+        match before % 2 == 0
+            && slot.sequence.load(Ordering::Relaxed) == before
+            && current == ticket
+        {
+            true => Some(this),
+            // The slot has been (or is being) overwritten.
+            false => None,
+        }
+    }
+    /// The instruction pointers of each frame, most recent first.
+    pub fn frames(&self) -> impl Iterator<Item = *mut c_void> + '_ {
+        self.frames[..self.len].iter().copied()
+    }
+}
+
+impl fmt::Debug for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.frames()).finish()
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (ix, ip) in self.frames().enumerate() {
+            f.write_fmt(format_args!("{ix:4}: "))?;
+            let mut first = true;
+            let mut res = Ok(());
+            backtrace::resolve(ip, |symbol| {
+                if res.is_err() {
+                    return;
+                }
+                if !first {
+                    res = f.write_str("      ");
+                }
+                first = false;
+                res = res.and_then(|()| match symbol.name() {
+                    Some(name) => f.write_fmt(format_args!("{name:#}\n")),
+                    None => f.write_fmt(format_args!("{ip:?}\n")),
+                });
+                if let (Some(file), Some(line)) = (symbol.filename(), symbol.lineno()) {
+                    res = res.and_then(|()| {
+                        f.write_fmt(format_args!("             at {}:{line}\n", file.display()))
+                    });
+                }
+            });
+            res?;
+            if first {
+                f.write_fmt(format_args!("{ip:?}\n"))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+//! [`ufmt::uDisplay`] implementations, behind the `"ufmt"` feature.
+//!
+//! Messages match the [`Display`](core::fmt::Display) implementations,
+//! using [`Units::Binary`] by default.
+//! `ufmt` has no alternate flag, so use [`Error::with_units`] for other units.
+
+use ufmt::{uDisplay, uWrite, uwrite, Formatter};
+
+use crate::{Error, ErrorKind, ErrorWith, Info, Units, WithUnits};
+
+impl uDisplay for Error {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        write_info(self.info(), Units::Binary, f)
+    }
+}
+
+impl<T> uDisplay for ErrorWith<T> {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        write_info(self.info(), Units::Binary, f)
+    }
+}
+
+impl uDisplay for WithUnits {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        write_info(self.info, self.units, f)
+    }
+}
+
+fn write_info<W: uWrite + ?Sized>(
+    info: Info,
+    units: Units,
+    f: &mut Formatter<'_, W>,
+) -> Result<(), W::Error> {
+    let Info { layout, name, kind } = info;
+    if let Some(reason) = kind.reason() {
+        return match (kind, name) {
+            (ErrorKind::LayoutOverflow, Some(name)) => uwrite!(
+                f,
+                "memory allocation of an array of {} failed: {}",
+                name,
+                reason
+            ),
+            (_, Some(name)) => uwrite!(f, "memory allocation for type {} failed: {}", name, reason),
+            (_, None) => uwrite!(f, "memory allocation failed: {}", reason),
+        };
+    }
+    f.write_str("memory allocation of ")?;
+    match units.prefixes() {
+        #[cfg(feature = "humanize")]
+        Some((boundary, prefixes)) => {
+            let (amount, prefix) = crate::Amount::scale(layout.size(), boundary, prefixes);
+            uwrite!(f, "{}", amount.whole)?;
+            if let Some(hundredths) = amount.hundredths {
+                let pad = if hundredths < 10 { "0" } else { "" };
+                uwrite!(f, ".{}{}", pad, hundredths)?;
+            }
+            uwrite!(f, " {}bytes", prefix)?;
+            if let Some(align) = crate::overaligned(layout) {
+                uwrite!(f, " aligned to {}", align)?;
+            }
+        }
+        _ => uwrite!(
+            f,
+            "{} bytes with alignment {}",
+            layout.size(),
+            layout.align()
+        )?,
+    }
+    if let Some(name) = name {
+        uwrite!(f, " (for type {})", name)?;
+    }
+    f.write_str(" failed")
+}
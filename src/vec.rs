@@ -0,0 +1,224 @@
+//! A minimal, fallibly-growing vector.
+//!
+//! ```
+//! use trybox::vec::TryVec;
+//!
+//! let mut v = TryVec::new();
+//! v.try_push(1)?;
+//! v.try_extend_from_slice(&[2, 3])?;
+//! assert_eq!(v, [1, 2, 3]);
+//! assert_eq!(*v.try_into_boxed_slice()?, [1, 2, 3]);
+//! # Ok::<_, trybox::Error>(())
+//! ```
+
+use alloc::boxed::Box;
+use core::{
+    fmt,
+    mem::{self, MaybeUninit},
+    ops::{Deref, DerefMut},
+    ptr, slice as raw_slice,
+};
+
+use crate::{slice, Error, ErrorKind, ErrorWith};
+
+/// Like a [`Vec`], but growth is fallible.
+pub struct TryVec<T> {
+    buf: Box<[MaybeUninit<T>]>,
+    len: usize,
+}
+
+impl<T> TryVec<T> {
+    /// Create an empty vector, without allocating.
+    pub fn new() -> Self {
+        Self {
+            // A dangling pointer, which doesn't allocate.
+            buf: Box::new([]),
+            len: 0,
+        }
+    }
+    /// Attempt to create an empty vector with room for exactly `capacity`
+    /// values.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, Error> {
+        Ok(Self {
+            buf: slice::uninit(capacity)?,
+            len: 0,
+        })
+    }
+    /// Attempt to ensure there is room for at least `additional` more values,
+    /// typically doubling the capacity.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), Error> {
+        let required = self.required(additional)?;
+        if required <= self.capacity() {
+            return Ok(());
+        }
+        let buf = match slice::uninit_silent(required.max(self.capacity().saturating_mul(2)).max(4))
+        {
+            Some(it) => it,
+            // Doubling may have been too greedy.
+            None => slice::uninit(required)?,
+        };
+        self.move_to(buf);
+        Ok(())
+    }
+    /// Attempt to ensure there is room for exactly `additional` more values.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_reserve_exact(&mut self, additional: usize) -> Result<(), Error> {
+        let required = self.required(additional)?;
+        match required <= self.capacity() {
+            true => Ok(()),
+            false => self.grow_to(required),
+        }
+    }
+    fn required(&self, additional: usize) -> Result<usize, Error> {
+        match self.len.checked_add(additional) {
+            Some(it) => Ok(it),
+            None => Err(Error::for_type_with_kind::<T>(ErrorKind::LayoutOverflow)),
+        }
+    }
+    /// Move the contents to a new allocation of `capacity`.
+    #[cfg_attr(feature = "location", track_caller)]
+    fn grow_to(&mut self, capacity: usize) -> Result<(), Error> {
+        let buf = slice::uninit(capacity)?;
+        self.move_to(buf);
+        Ok(())
+    }
+    /// Move the contents to `buf`, which must have room for them.
+    fn move_to(&mut self, mut buf: Box<[MaybeUninit<T>]>) {
+        // SAFETY: the values are moved, and the old buffer doesn't drop them.
+        unsafe { ptr::copy_nonoverlapping(self.buf.as_ptr(), buf.as_mut_ptr(), self.len) };
+        self.buf = buf;
+    }
+    /// Attempt to append `x`, returning it if the vector couldn't grow.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_push(&mut self, x: T) -> Result<(), ErrorWith<T>> {
+        if self.try_reserve(1).is_err() {
+            return Err(ErrorWith(x));
+        }
+        self.buf[self.len] = MaybeUninit::new(x);
+        self.len += 1;
+        Ok(())
+    }
+    /// Remove the last value, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        self.len = self.len.checked_sub(1)?;
+        // SAFETY: the value is initialized, and no longer part of the contents.
+        Some(unsafe { self.buf[self.len].as_ptr().read() })
+    }
+    /// Remove the values after `len`, if any.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            let tail = &mut self.as_mut_slice()[len..] as *mut [T];
+            self.len = len;
+            // SAFETY: the values are no longer part of the contents.
+            unsafe { ptr::drop_in_place(tail) }
+        }
+    }
+    /// Remove the contents, keeping the capacity.
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+    /// The contents.
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the first `len` values are initialized.
+        unsafe { raw_slice::from_raw_parts(self.buf.as_ptr().cast(), self.len) }
+    }
+    /// The contents.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: the first `len` values are initialized.
+        unsafe { raw_slice::from_raw_parts_mut(self.buf.as_mut_ptr().cast(), self.len) }
+    }
+    /// The number of values.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Whether there are no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// The number of values which can be held without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+    /// Attempt to convert to an exactly-sized `Box<[T]>`,
+    /// which allocates unless the vector is at capacity.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_into_boxed_slice(mut self) -> Result<Box<[T]>, Error> {
+        if self.len != self.capacity() {
+            let mut buf = slice::uninit(self.len)?;
+            // SAFETY: the values are moved, and the old buffer doesn't drop them.
+            unsafe { ptr::copy_nonoverlapping(self.buf.as_ptr(), buf.as_mut_ptr(), self.len) };
+            self.buf = buf;
+        }
+        let buf = mem::replace(&mut self.buf, Box::new([]));
+        self.len = 0;
+        // SAFETY: every value is initialized.
+        Ok(unsafe { Box::from_raw(Box::into_raw(buf) as *mut [T]) })
+    }
+}
+
+impl<T: Clone> TryVec<T> {
+    /// Attempt to append clones of `values`.
+    #[cfg_attr(feature = "location", track_caller)]
+    pub fn try_extend_from_slice(&mut self, values: &[T]) -> Result<(), Error> {
+        self.try_reserve(values.len())?;
+        for value in values {
+            // Pushed one at a time, in case `clone` panics.
+            self.buf[self.len] = MaybeUninit::new(value.clone());
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Drop for TryVec<T> {
+    fn drop(&mut self) {
+        self.clear()
+    }
+}
+
+impl<T> Default for TryVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Deref for TryVec<T> {
+    type Target = [T];
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl<T> DerefMut for TryVec<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for TryVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        <[T]>::fmt(self, f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for TryVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq> Eq for TryVec<T> {}
+
+impl<T: PartialEq> PartialEq<[T]> for TryVec<T> {
+    fn eq(&self, other: &[T]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq<[T; N]> for TryVec<T> {
+    fn eq(&self, other: &[T; N]) -> bool {
+        self.as_slice() == other
+    }
+}
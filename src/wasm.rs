@@ -0,0 +1,49 @@
+//! A cap on the size of WebAssembly linear memory, for the `"wasm"` feature.
+//!
+//! On `wasm32`, allocators may trap rather than returning null when
+//! `memory.grow` fails.
+//! With a cap in place, allocations through this crate which could need to
+//! grow memory past it fail with an [`Error`](crate::Error) instead.
+//!
+//! ```no_run
+//! // Leave headroom below the engine's limit of 64 MiB.
+//! trybox::wasm::set_max_memory(60 << 20);
+//! ```
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The size of a WebAssembly page.
+const PAGE: usize = 64 << 10;
+
+static MAX: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Fail allocations through this crate which could grow linear memory
+/// beyond `bytes`.
+///
+/// This is conservative: an allocation is refused if the current memory size
+/// plus its size exceeds the cap,
+/// even if the allocator could have reused freed memory for it.
+pub fn set_max_memory(bytes: usize) {
+    MAX.store(bytes, Ordering::Relaxed)
+}
+
+/// The cap set with [`set_max_memory`], or [`usize::MAX`] if there is none.
+pub fn max_memory() -> usize {
+    MAX.load(Ordering::Relaxed)
+}
+
+/// The current size of linear memory, in bytes.
+pub fn memory_size() -> usize {
+    core::arch::wasm32::memory_size(0).saturating_mul(PAGE)
+}
+
+/// Whether an allocation of `size` bytes stays within the cap.
+#[inline(always)]
+pub(crate) fn fits(size: usize) -> bool {
+    match MAX.load(Ordering::Relaxed) {
+        usize::MAX => true,
+        max => memory_size()
+            .checked_add(size)
+            .map_or(false, |it| it <= max),
+    }
+}
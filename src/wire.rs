@@ -0,0 +1,120 @@
+//! A compact, fixed-size encoding of [`Error`]s, for sending to a host.
+//!
+//! ```
+//! use trybox::wire::Record;
+//!
+//! let bytes = trybox::Error::for_type::<[u32; 4]>().encode();
+//! let record = Record::decode(&bytes).unwrap();
+//! assert_eq!(record.layout, core::alloc::Layout::new::<[u32; 4]>());
+//! # #[cfg(not(feature = "strip-type-names"))]
+//! assert_eq!(record.type_hash, Some(trybox::wire::type_hash("[u32; 4]")));
+//! ```
+//!
+//! # Format
+//! All integers are little-endian.
+//!
+//! | Offset | Length | Meaning                                            |
+//! | ------ | ------ | -------------------------------------------------- |
+//! | 0      | 1      | [`ErrorKind`]: 0, 1, 2 for `Exhausted`, `LayoutOverflow`, `Alignment` |
+//! | 1      | 1      | 1 if there is a type name hash, else 0             |
+//! | 2      | 1      | `log2(align)`                                      |
+//! | 3      | 8      | `size`                                             |
+//! | 11     | 8      | [`type_hash`] of the type name, or 0               |
+
+use core::alloc::Layout;
+
+use crate::{Error, ErrorKind, Info};
+
+/// The length of an encoded [`Record`].
+pub const LEN: usize = 19;
+
+/// A decoded [`Error`].
+///
+/// Type names can't be recovered, only their [`type_hash`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Record {
+    /// See [`Error::kind`].
+    pub kind: ErrorKind,
+    /// See [`Error::layout`].
+    pub layout: Layout,
+    /// The [`type_hash`] of [`Error::type_name`].
+    pub type_hash: Option<u64>,
+}
+
+impl Record {
+    /// Encode this record, see the [module documentation](self).
+    pub fn encode(&self) -> [u8; LEN] {
+        let mut bytes = [0; LEN];
+        bytes[0] = match self.kind {
+            ErrorKind::Exhausted => 0,
+            ErrorKind::LayoutOverflow => 1,
+            ErrorKind::Alignment => 2,
+        };
+        bytes[1] = self.type_hash.is_some() as u8;
+        bytes[2] = self.layout.align().trailing_zeros() as u8;
+        bytes[3..11].copy_from_slice(&(self.layout.size() as u64).to_le_bytes());
+        bytes[11..].copy_from_slice(&self.type_hash.unwrap_or(0).to_le_bytes());
+        bytes
+    }
+    /// Decode a record, returning [`None`] if it is malformed,
+    /// or its layout can't be represented on this platform.
+    pub fn decode(bytes: &[u8; LEN]) -> Option<Self> {
+        let kind = match bytes[0] {
+            0 => ErrorKind::Exhausted,
+            1 => ErrorKind::LayoutOverflow,
+            2 => ErrorKind::Alignment,
+            _ => return None,
+        };
+        let type_hash = match bytes[1] {
+            0 => None,
+            1 => Some(u64::from_le_bytes(bytes[11..].try_into().ok()?)),
+            _ => return None,
+        };
+        let align = 1usize.checked_shl(bytes[2].into())?;
+        let size = u64::from_le_bytes(bytes[3..11].try_into().ok()?);
+        let layout = Layout::from_size_align(size.try_into().ok()?, align).ok()?;
+        Some(Self {
+            kind,
+            layout,
+            type_hash,
+        })
+    }
+}
+
+impl From<Info> for Record {
+    fn from(value: Info) -> Self {
+        Self {
+            kind: value.kind,
+            layout: value.layout,
+            type_hash: value.name.map(type_hash),
+        }
+    }
+}
+
+impl From<Error> for Record {
+    fn from(value: Error) -> Self {
+        value.info().into()
+    }
+}
+
+/// A stable hash of a type name, as used in [`Record`]s and [`Error::code`].
+///
+/// This is 64-bit [FNV-1a](http://www.isthe.com/chongo/tech/comp/fnv/),
+/// over the UTF-8 bytes of `name`,
+/// with offset basis `0xcbf29ce484222325` and prime `0x100000001b3`,
+/// so hosts can build a table of names to look up.
+///
+/// Note that [`core::any::type_name`] isn't guaranteed to be stable across
+/// compiler versions.
+pub const fn type_hash(name: &str) -> u64 {
+    let bytes = name.as_bytes();
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut ix = 0;
+    while ix < bytes.len() {
+        hash ^= bytes[ix] as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+        ix += 1;
+    }
+    hash
+}
@@ -0,0 +1,59 @@
+//! With optimizations, [`no_panic`] fails to link if rendering may panic:
+//!
+//! ```console
+//! $ cargo test --release --features panic-free --test panic-free
+//! ```
+//!
+//! Without, this only checks that the messages match.
+
+use core::{alloc::Layout, fmt};
+
+use trybox::{Error, ErrorKind};
+
+/// A fixed-size buffer, which can't panic.
+struct Buf {
+    bytes: [u8; 256],
+    len: usize,
+}
+
+impl fmt::Write for Buf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let dst = self
+            .bytes
+            .get_mut(self.len..)
+            .and_then(|it| it.get_mut(..s.len()))
+            .ok_or(fmt::Error)?;
+        dst.copy_from_slice(s.as_bytes());
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+/// Not inlined, so that it's checked for every error, not just constants.
+#[cfg_attr(not(debug_assertions), no_panic::no_panic)]
+#[inline(never)]
+fn render(e: &Error, buf: &mut Buf) -> fmt::Result {
+    e.write_to(buf)
+}
+
+#[test]
+fn matches_display() {
+    for e in [
+        Error::for_type::<u64>(),
+        Error::for_type::<[u8; 2500]>(),
+        Error::for_layout(Layout::from_size_align(4096, 64).unwrap()),
+        Error::for_layout(Layout::from_size_align(usize::MAX / 2, 1).unwrap()),
+        Error::for_type_with_kind::<u32>(ErrorKind::LayoutOverflow),
+        Error::for_kind(ErrorKind::Alignment),
+    ] {
+        let mut buf = Buf {
+            bytes: [0; 256],
+            len: 0,
+        };
+        render(&e, &mut buf).unwrap();
+        assert_eq!(
+            core::str::from_utf8(&buf.bytes[..buf.len]).unwrap(),
+            e.to_string()
+        );
+    }
+}
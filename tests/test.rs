@@ -1,49 +1,1294 @@
 use core::{
-    alloc::{GlobalAlloc, Layout},
-    ptr,
-    sync::atomic::AtomicBool,
+    alloc::Layout,
+    fmt::{self, Write as _},
+    sync::atomic::AtomicUsize,
 };
-use std::{io, sync::atomic::Ordering};
+use std::{alloc::System, io, sync::atomic::Ordering};
 
+#[cfg(not(feature = "strip-type-names"))]
 use expect_test::{expect_file, ExpectFile};
 use libtest_mimic::{Arguments, Trial};
+use trybox::testing::FailOrFallback;
 
 #[global_allocator]
-static ALLOC: FailOrFallback = FailOrFallback::system();
+static ALLOC: FailOrFallback<System> = FailOrFallback::system();
 
 fn main() {
+    // Checking `RUST_BACKTRACE` allocates, so do it before failing allocations.
+    #[cfg(feature = "backtrace")]
+    {
+        std::env::set_var("RUST_BACKTRACE", "1");
+        trybox::Backtrace::init();
+    }
     let mut args = Arguments::from_args();
     args.test_threads = Some(1);
-    libtest_mimic::run(
-        &args,
-        vec![
-            error_message(
-                "i32-error-message",
-                expect_file!["i32-error-message.expected"],
-                1i32,
-            ),
-            error_message(
-                "2k-error-message",
-                expect_file!["2k-error-message.expected"],
-                [0u8; 2048],
-            ),
-            error_message(
-                "2.5k-error-message",
-                expect_file!["2.5k-error-message.expected"],
-                [0u8; 2500],
-            ),
-            Trial::test("io-error-kind", || {
-                let e: io::Error = fail_alloc(1).into();
-                assert_eq!(e.kind(), io::ErrorKind::OutOfMemory);
-                let e: io::ErrorKind = fail_alloc(1).into();
-                assert_eq!(e, io::ErrorKind::OutOfMemory);
-                Ok(())
+    #[allow(unused_mut)]
+    let mut trials = vec![
+        Trial::test("io-error-kind", || {
+            let e: io::Error = fail_alloc(1).into();
+            assert_eq!(e.kind(), io::ErrorKind::OutOfMemory);
+            let e: io::ErrorKind = fail_alloc(1).into();
+            assert_eq!(e, io::ErrorKind::OutOfMemory);
+            let err = fail_alloc(1);
+            let before = ALLOC.attempts();
+            let e = err.into_io_error_static();
+            assert_eq!(ALLOC.attempts(), before);
+            assert_eq!(e.kind(), io::ErrorKind::OutOfMemory);
+            let e = fail_new(1u8).into_io_error();
+            let source = e.into_inner().unwrap();
+            let source = source.downcast::<trybox::ErrorWith<u8>>().unwrap();
+            assert_eq!(source.into_inner(), 1);
+            Ok(())
+        }),
+        Trial::test("retry", || {
+            let mut err = fail_new([1u8, 2]);
+            err[1] = 3;
+            assert_eq!(err.len(), 2);
+            assert_eq!(*err.retry().unwrap(), [1, 3]);
+            Ok(())
+        }),
+        Trial::test("or-place-in", || {
+            use trybox::ResultExt as _;
+            let dropped = std::rc::Rc::new(());
+            let mut slot = std::mem::MaybeUninit::uninit();
+            ALLOC.fail();
+            let it = trybox::new(dropped.clone()).or_place_in(&mut slot);
+            ALLOC.fallback();
+            assert!(!it.is_heap());
+            assert_eq!(std::rc::Rc::strong_count(&it), 2);
+            drop(it);
+            assert_eq!(std::rc::Rc::strong_count(&dropped), 1);
+            Ok(())
+        }),
+        Trial::test("or-drop-with-lazy", || {
+            ALLOC.fail();
+            let res = trybox::or_drop_with(|| -> u8 { unreachable!() });
+            ALLOC.fallback();
+            assert_eq!(res.unwrap_err().layout(), Layout::new::<u8>());
+            Ok(())
+        }),
+        Trial::test("vec-into-box", || {
+            use trybox::TryIntoBox as _;
+            let mut v = Vec::with_capacity(8);
+            v.extend([1u8, 2, 3]);
+            ALLOC.fail();
+            let res: Result<Box<[u8]>, _> = v.try_into_box();
+            ALLOC.fallback();
+            assert_eq!(res.unwrap_err().layout(), Layout::new::<[u8; 3]>());
+            let v = vec![String::from("a"); 3];
+            let boxed: Box<[String]> = v.try_into_box().unwrap();
+            assert_eq!(*boxed, ["a", "a", "a"]);
+            Ok(())
+        }),
+        Trial::test("format-sink", || {
+            let mut sink = trybox::format::Sink::new();
+            let long = "b".repeat(100);
+            sink.write_str("aaa").unwrap();
+            ALLOC.fail();
+            let fits = sink.write_str("aaa");
+            let grows = sink.write_str(&long);
+            ALLOC.fallback();
+            assert!(fits.is_ok());
+            assert!(grows.is_err());
+            assert_eq!(sink.as_str(), "aaaaaa");
+            assert_eq!(sink.error().unwrap().layout(), Layout::new::<[u8; 106]>());
+            assert!(sink.finish().is_err());
+            Ok(())
+        }),
+        Trial::test("io-read-oom", || {
+            let data = vec![0u8; 1000];
+            ALLOC.fail_larger_than(512);
+            let res = trybox::io::read_to_boxed_slice(&data[..]);
+            ALLOC.fallback();
+            assert_eq!(res.unwrap_err().kind(), io::ErrorKind::OutOfMemory);
+            assert_eq!(
+                trybox::io::read_to_boxed_slice(&data[..]).unwrap().len(),
+                1000
+            );
+            Ok(())
+        }),
+        Trial::test("try-string", || {
+            let mut s = trybox::string::TryString::try_with_capacity(4).unwrap();
+            let cap = s.capacity();
+            ALLOC.fail();
+            let fits = s.try_push_str("ab");
+            let grow = s.try_reserve(cap + 1);
+            ALLOC.fallback();
+            assert!(fits.is_ok());
+            assert!(grow.is_err());
+            assert_eq!(s, "ab");
+            s.clear();
+            assert!(s.is_empty());
+            Ok(())
+        }),
+        Trial::test("raw-error-message", || {
+            let layout = Layout::from_size_align(4096, 64).unwrap();
+            ALLOC.fail();
+            let res = trybox::raw::alloc(layout);
+            ALLOC.fallback();
+            let err = res.unwrap_err();
+            assert_eq!(err.layout(), layout);
+            assert_eq!(
+                err.to_string(),
+                "memory allocation of 4 kibibytes aligned to 64 failed"
+            );
+            Ok(())
+        }),
+        Trial::test("aligned-error-layout", || {
+            ALLOC.fail();
+            let res = trybox::new_aligned::<4096, _>(1u8);
+            ALLOC.fallback();
+            let err = trybox::Error::from(res.unwrap_err());
+            assert_eq!(err.layout(), Layout::from_size_align(4096, 4096).unwrap());
+            Ok(())
+        }),
+        Trial::test("limit-nested", || {
+            trybox::limit::scoped(64, || {
+                trybox::limit::scoped(128, || {
+                    assert_eq!(trybox::limit::remaining(), Some(64));
+                    assert!(trybox::or_drop([0u8; 48]).is_ok());
+                });
+                assert_eq!(trybox::limit::remaining(), Some(16));
+                assert!(trybox::or_drop([0u8; 32]).is_err());
+            });
+            assert_eq!(trybox::limit::remaining(), None);
+            Ok(())
+        }),
+        Trial::test("segmented-fallback", || {
+            ALLOC.fail_larger_than(4096);
+            let res = trybox::segmented::Buffer::zeroed(10_000);
+            ALLOC.fallback();
+            let mut buf = res.unwrap();
+            assert!(buf.as_contiguous().is_none());
+            assert_eq!(buf.write_at(4000, &[1; 2000]), 2000);
+            let mut dst = [0; 3000];
+            assert_eq!(buf.read_at(3500, &mut dst), 3000);
+            assert_eq!(dst[..500], [0; 500]);
+            assert_eq!(dst[500..2500], [1; 2000]);
+            assert_eq!(dst[2500..], [0; 500]);
+            Ok(())
+        }),
+        Trial::test("probe-largest", || {
+            ALLOC.fail_larger_than(5000);
+            let res = trybox::probe::largest_slice(10_000, 100);
+            ALLOC.fallback();
+            assert_eq!(res.unwrap().len(), 5000);
+            Ok(())
+        }),
+        Trial::test("batch-transactional", || {
+            ALLOC.fail_larger_than(8);
+            let before = ALLOC.attempts();
+            let res = trybox::batch::new_all((1u8, [2u8; 16], 3u8));
+            // The last member isn't attempted.
+            assert_eq!(ALLOC.attempts() - before, 2);
+            ALLOC.fallback();
+            let err = res.unwrap_err();
+            assert_eq!(err.values, (1, [2; 16], 3));
+            assert_eq!(err.error.layout(), Layout::new::<[u8; 16]>());
+            Ok(())
+        }),
+        Trial::test("format-without-alloc", || {
+            let errors = [
+                fail_alloc(1i32),
+                fail_alloc([0u8; 2500]),
+                trybox::Error::for_layout(Layout::from_size_align(4096, 64).unwrap()),
+                trybox::Error::for_kind(trybox::ErrorKind::LayoutOverflow),
+                trybox::Error::for_type_with_kind::<u8>(trybox::ErrorKind::Alignment),
+            ];
+            let mut buf = Buf::default();
+            let before = ALLOC.attempts();
+            for e in &errors {
+                for units in [
+                    trybox::Units::Binary,
+                    trybox::Units::Decimal,
+                    trybox::Units::Exact,
+                ] {
+                    buf.len = 0;
+                    write!(buf, "{}", e.with_units(units)).unwrap();
+                }
+                buf.len = 0;
+                e.write_to(&mut buf).unwrap();
+                buf.len = 0;
+                write!(buf, "{e:#} {e:?}").unwrap();
+            }
+            assert_eq!(ALLOC.attempts(), before);
+            Ok(())
+        }),
+        Trial::test("eprint-without-alloc", || {
+            let err = fail_alloc([0u8; 2500]);
+            let before = ALLOC.attempts();
+            ALLOC.fail();
+            let res = err.eprint();
+            ALLOC.fallback();
+            res.unwrap();
+            assert_eq!(ALLOC.attempts(), before);
+            Ok(())
+        }),
+        Trial::test("eprint-non-ascii", || {
+            // One of these puts a character across the end of the buffer.
+            #[allow(dead_code)]
+            struct ÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑ(
+                u8,
+            );
+            #[allow(dead_code)]
+            struct AÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑ(
+                u8,
+            );
+            trybox::Error::for_type::<ÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑ>().eprint()?;
+            trybox::Error::for_type::<AÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑÑ>().eprint()?;
+            Ok(())
+        }),
+    ];
+    // Type names are part of the expected messages.
+    #[cfg(not(feature = "strip-type-names"))]
+    trials.extend([
+        error_message(
+            "i32-error-message",
+            expect_file!["i32-error-message.expected"],
+            1i32,
+        ),
+        error_message(
+            "2k-error-message",
+            expect_file!["2k-error-message.expected"],
+            [0u8; 2048],
+        ),
+        error_message(
+            "2.5k-error-message",
+            expect_file!["2.5k-error-message.expected"],
+            [0u8; 2500],
+        ),
+    ]);
+    #[cfg(feature = "location")]
+    trials.push(Trial::test("location", || {
+        ALLOC.fail();
+        let res = trybox::or_drop(1u8);
+        let line = line!() - 1;
+        ALLOC.fallback();
+        let location = res.unwrap_err().location();
+        assert_eq!((location.file(), location.line()), (file!(), line));
+        // The hook sees the caller's location too.
+        static HOOK_LINE: AtomicUsize = AtomicUsize::new(0);
+        trybox::set_oom_hook(|e| {
+            assert_eq!(e.location().file(), file!());
+            HOOK_LINE.store(e.location().line() as usize, Ordering::Relaxed);
+        });
+        ALLOC.fail();
+        let _ = trybox::new(1u8);
+        let line = line!() - 1;
+        ALLOC.fallback();
+        assert_eq!(HOOK_LINE.load(Ordering::Relaxed), line as usize);
+        ALLOC.fail();
+        let _ = trybox::ext::TryBoxed::try_boxed(1u8);
+        let line = line!() - 1;
+        ALLOC.fallback();
+        assert_eq!(HOOK_LINE.load(Ordering::Relaxed), line as usize);
+        ALLOC.fail();
+        let _ = trybox::ErrorWith(1u8).retry();
+        let line = line!() - 1;
+        ALLOC.fallback();
+        trybox::take_oom_hook();
+        assert_eq!(HOOK_LINE.load(Ordering::Relaxed), line as usize);
+        Ok(())
+    }));
+    #[cfg(feature = "backtrace")]
+    trials.push(Trial::test("backtrace", || {
+        let err = fail_alloc(1u8);
+        assert!(err.backtrace().unwrap().frames().count() > 0);
+        // Each failure takes one of the 16 slots,
+        // shared by the hook and the caller.
+        for _ in 0..15 {
+            fail_alloc(1u8);
+        }
+        assert!(err.backtrace().is_some());
+        fail_alloc(1u8);
+        assert!(err.backtrace().is_none());
+        Ok(())
+    }));
+    #[cfg(feature = "log")]
+    trials.push(Trial::test("log", || {
+        struct Count;
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        impl log::Log for Count {
+            fn enabled(&self, metadata: &log::Metadata) -> bool {
+                metadata.target() == "trybox"
+            }
+            fn log(&self, record: &log::Record) {
+                if self.enabled(record.metadata()) {
+                    COUNT.fetch_add(1, Ordering::AcqRel);
+                }
+            }
+            fn flush(&self) {}
+        }
+        log::set_logger(&Count).unwrap();
+        log::set_max_level(log::LevelFilter::Error);
+        fail_alloc(1u8);
+        assert_eq!(COUNT.load(Ordering::Acquire), 1);
+        ALLOC.fail();
+        let res = trybox::or_drop_silent(1u8);
+        ALLOC.fallback();
+        assert!(res.is_err());
+        assert_eq!(COUNT.load(Ordering::Acquire), 1);
+        Ok(())
+    }));
+    #[cfg(feature = "tracing")]
+    trials.push(Trial::test("tracing", || {
+        use tracing::{field, span, Event, Metadata, Subscriber};
+        /// Records the field names of an event, without allocating.
+        #[derive(Default)]
+        struct Fields {
+            len: usize,
+            names: [&'static str; 8],
+        }
+        impl field::Visit for Fields {
+            fn record_debug(&mut self, field: &field::Field, _: &dyn fmt::Debug) {
+                self.names[self.len] = field.name();
+                self.len += 1;
+            }
+        }
+        /// Counts events with the expected fields.
+        struct Count;
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        impl Subscriber for Count {
+            fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+                metadata.target() == "trybox"
+            }
+            fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+                span::Id::from_u64(1)
+            }
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+            fn event(&self, event: &Event<'_>) {
+                let mut fields = Fields::default();
+                event.record(&mut fields);
+                // Absent type names aren't recorded.
+                let expected: &[_] = match cfg!(feature = "strip-type-names") {
+                    true => &["message", "size", "align"],
+                    false => &["message", "size", "align", "type"],
+                };
+                if fields.names[..fields.len] == *expected {
+                    COUNT.fetch_add(1, Ordering::AcqRel);
+                }
+            }
+            fn enter(&self, _: &span::Id) {}
+            fn exit(&self, _: &span::Id) {}
+        }
+        tracing::subscriber::with_default(Count, || fail_alloc(1u8));
+        assert_eq!(COUNT.load(Ordering::Acquire), 1);
+        Ok(())
+    }));
+    #[cfg(feature = "metrics")]
+    trials.push(Trial::test("metrics", || {
+        use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, SharedString, Unit};
+        use std::sync::{atomic::AtomicU64, Arc};
+        /// Registration happens while the allocator is failing,
+        /// so the counters are created up front.
+        struct Counters {
+            failures: Arc<AtomicU64>,
+            failed_bytes: Arc<AtomicU64>,
+        }
+        impl metrics::Recorder for Counters {
+            fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+                match key.name() {
+                    "trybox.alloc_failures" => Counter::from_arc(self.failures.clone()),
+                    "trybox.failed_bytes" => Counter::from_arc(self.failed_bytes.clone()),
+                    _ => Counter::noop(),
+                }
+            }
+            fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+                Gauge::noop()
+            }
+            fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+                Histogram::noop()
+            }
+        }
+        let counters = Counters {
+            failures: Arc::default(),
+            failed_bytes: Arc::default(),
+        };
+        metrics::with_local_recorder(&counters, || {
+            fail_alloc(1u8);
+            fail_alloc([0u8; 16]);
+        });
+        assert_eq!(counters.failures.load(Ordering::Acquire), 2);
+        assert_eq!(counters.failed_bytes.load(Ordering::Acquire), 17);
+        Ok(())
+    }));
+    #[cfg(feature = "stats")]
+    trials.push(Trial::test("stats", || {
+        use trybox::stats::Stats;
+        let before = Stats::snapshot();
+        fail_alloc([0u8; 16]);
+        ALLOC.fail();
+        let res = trybox::or_drop_silent(1u8);
+        ALLOC.fallback();
+        assert!(res.is_err());
+        let _ok = trybox::or_drop(2u8).unwrap();
+        let after = Stats::snapshot();
+        assert_eq!(after.failures - before.failures, 2);
+        assert_eq!(after.allocs - before.allocs, 1);
+        assert_eq!(after.requested_bytes - before.requested_bytes, 18);
+        Ok(())
+    }));
+    #[cfg(all(feature = "stats", feature = "mmap", unix))]
+    trials.push(Trial::test("stats-mappings", || {
+        use trybox::stats::Stats;
+        let before = Stats::snapshot();
+        let _guarded = trybox::guarded::new(1u8).unwrap();
+        let after = Stats::snapshot();
+        assert!(after.allocs > before.allocs);
+        assert!(after.requested_bytes > before.requested_bytes);
+        Ok(())
+    }));
+    trials.push(Trial::test("retry-policy", || {
+        use trybox::retry::{self, Policy};
+        // Fail the first two attempts.
+        ALLOC.fail();
+        let mut calls = 0;
+        #[cfg(feature = "location")]
+        let line = line!() + 1;
+        let boxed = retry::new_with(
+            Policy::new(3).between(|_e, attempt| {
+                // The error given to the hook, from the caller.
+                #[cfg(feature = "location")]
+                assert_eq!(
+                    (_e.location().file(), _e.location().line()),
+                    (file!(), line)
+                );
+                calls += 1;
+                if attempt == 2 {
+                    ALLOC.fallback()
+                }
             }),
-        ],
-    )
-    .exit()
+            1u8,
+        )
+        .unwrap();
+        assert_eq!((*boxed, calls), (1, 2));
+        ALLOC.fail();
+        let res = retry::new_with(Policy::new(2), 2u8);
+        ALLOC.fallback();
+        assert_eq!(res.unwrap_err().into_inner(), 2);
+        // `between` is given the reported error, rather than capturing another.
+        #[cfg(feature = "backtrace")]
+        {
+            let err = fail_alloc(1u8);
+            ALLOC.fail();
+            let _ = retry::new_with(Policy::new(3), 3u8);
+            ALLOC.fallback();
+            for _ in 0..12 {
+                fail_alloc(1u8);
+            }
+            // 16 failures, so the first backtrace is still in the ring.
+            assert!(err.backtrace().is_some());
+        }
+        Ok(())
+    }));
+    trials.push(Trial::test("pressure-purge", || {
+        static PURGES: AtomicUsize = AtomicUsize::new(0);
+        let registration = trybox::pressure::register(|level| {
+            assert_eq!(level.bytes, 4);
+            PURGES.fetch_add(1, Ordering::AcqRel);
+            ALLOC.fallback();
+        })
+        .unwrap();
+        ALLOC.fail();
+        let res = trybox::or_drop(1u32);
+        ALLOC.fallback();
+        registration.unregister();
+        assert_eq!(*res.unwrap(), 1);
+        assert_eq!(PURGES.load(Ordering::Acquire), 1);
+        fail_alloc(1u32);
+        assert_eq!(PURGES.load(Ordering::Acquire), 1);
+        Ok(())
+    }));
+    #[cfg(feature = "async")]
+    trials.push(Trial::test("retry-async", || {
+        use std::{
+            future::Future,
+            pin::Pin,
+            task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+        };
+        /// Polls a future to completion, without allocating.
+        fn block_on<F: Future>(fut: F) -> F::Output {
+            const VTABLE: RawWakerVTable = RawWakerVTable::new(
+                |_| RawWaker::new(core::ptr::null(), &VTABLE),
+                |_| {},
+                |_| {},
+                |_| {},
+            );
+            // SAFETY: the vtable functions do nothing.
+            let waker = unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) };
+            let mut fut = fut;
+            // SAFETY: `fut` is shadowed, so can't be moved again.
+            let mut fut = unsafe { Pin::new_unchecked(&mut fut) };
+            loop {
+                if let Poll::Ready(it) = fut.as_mut().poll(&mut Context::from_waker(&waker)) {
+                    return it;
+                }
+            }
+        }
+        /// Returns pending once, like yielding to the executor.
+        struct YieldNow(bool);
+        impl Future for YieldNow {
+            type Output = ();
+            fn poll(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<()> {
+                match std::mem::replace(&mut self.0, true) {
+                    true => Poll::Ready(()),
+                    false => Poll::Pending,
+                }
+            }
+        }
+        ALLOC.fail();
+        let mut calls = 0;
+        let boxed = block_on(trybox::retry::new_async(1u8, 3, || {
+            calls += 1;
+            if calls == 2 {
+                ALLOC.fallback()
+            }
+            YieldNow(false)
+        }))
+        .unwrap();
+        assert_eq!((*boxed, calls), (1, 2));
+        ALLOC.fail();
+        let res = block_on(trybox::retry::new_async(2u8, 2, || YieldNow(false)));
+        ALLOC.fallback();
+        assert_eq!(res.unwrap_err().into_inner(), 2);
+        Ok(())
+    }));
+    #[cfg(all(feature = "meminfo", target_os = "linux"))]
+    trials.push(Trial::test("meminfo", || {
+        let e = fail_alloc(1u8);
+        let info = e.system_info().unwrap();
+        assert!(info.available.unwrap() > 0);
+        assert!(info.resident.unwrap() > 0);
+        // The snapshot was taken while the allocator was failing.
+        assert!(format!("{e:#}").contains(" bytes were available"));
+        assert_eq!(
+            trybox::Error::for_kind(trybox::ErrorKind::Alignment).system_info(),
+            None
+        );
+        Ok(())
+    }));
+    #[cfg(feature = "timestamp")]
+    trials.push(Trial::test("timestamp", || {
+        let before = std::time::SystemTime::now();
+        let e = fail_alloc(1u8);
+        let after = std::time::SystemTime::now();
+        assert!(before <= e.timestamp() && e.timestamp() <= after);
+        Ok(())
+    }));
+    #[cfg(feature = "stats")]
+    trials.push(Trial::test("stats-high-water", || {
+        use std::alloc::{GlobalAlloc as _, System};
+        use trybox::stats::{reset_high_water, Stats, Track};
+        let _large = trybox::or_drop([0u8; 64]).unwrap();
+        reset_high_water();
+        let _small = trybox::or_drop([0u8; 8]).unwrap();
+        assert_eq!(Stats::snapshot().peak_allocation, 8);
+        let track = Track(System);
+        let layout = Layout::new::<[u8; 100]>();
+        let before = Stats::snapshot().live_bytes;
+        // SAFETY: the layout is non-empty, and the pointer is freed once.
+        unsafe {
+            let ptr = track.alloc(layout);
+            assert_eq!(Stats::snapshot().live_bytes, before + 100);
+            track.dealloc(ptr, layout);
+        }
+        let after = Stats::snapshot();
+        assert_eq!(after.live_bytes, before);
+        assert!(after.peak_live_bytes >= before + 100);
+        reset_high_water();
+        assert_eq!(Stats::snapshot().peak_live_bytes, before);
+        Ok(())
+    }));
+    #[cfg(feature = "latency")]
+    trials.push(Trial::test("stats-latency", || {
+        use std::{sync::atomic::AtomicU64, time::Duration};
+        use trybox::stats::{latency_bucket, set_clock, Stats};
+        static NANOS: AtomicU64 = AtomicU64::new(0);
+        set_clock(|| NANOS.fetch_add(100, Ordering::Relaxed));
+        let before = Stats::snapshot();
+        let _ok = trybox::or_drop(1u8).unwrap();
+        ALLOC.fail();
+        let res = trybox::or_drop_silent(2u8);
+        ALLOC.fallback();
+        assert!(res.is_err());
+        let after = Stats::snapshot();
+        assert_eq!(after.success_latency[4] - before.success_latency[4], 1);
+        assert_eq!(after.failure_latency[4] - before.failure_latency[4], 1);
+        assert!(latency_bucket(4).contains(&Duration::from_nanos(100)));
+        Ok(())
+    }));
+    #[cfg(feature = "stats")]
+    trials.push(Trial::test("stats-tagged", || {
+        use trybox::stats::{tagged, tags};
+        let _ok = trybox::new_tagged("test-ok", [0u8; 10]).unwrap();
+        let _ok = trybox::or_drop_tagged("test-ok", [0u8; 6]).unwrap();
+        ALLOC.fail();
+        let res = trybox::or_drop_tagged("test-fail", 1u8);
+        ALLOC.fallback();
+        assert!(res.is_err());
+        let ok = tagged("test-ok").unwrap();
+        assert_eq!((ok.allocs, ok.failures, ok.bytes), (2, 0, 16));
+        assert_eq!(tagged("test-fail").unwrap().failures, 1);
+        assert_eq!(tagged("unused"), None);
+        assert!(tags().any(|(tag, _)| tag == "test-fail"));
+        Ok(())
+    }));
+    trials.push(Trial::test("testing-failing-guard", || {
+        let res = std::panic::catch_unwind(|| {
+            let _failing = ALLOC.failing();
+            assert!(trybox::or_drop(1u8).is_err());
+            std::panic::resume_unwind(Box::new(()));
+        });
+        assert!(res.is_err());
+        assert!(trybox::or_drop(1u8).is_ok());
+        Ok(())
+    }));
+    trials.push(Trial::test("testing-countdown", || {
+        ALLOC.fail_nth(1);
+        let first = trybox::or_drop(1u8);
+        let second = trybox::or_drop(2u8);
+        let third = trybox::or_drop(3u8);
+        ALLOC.fallback();
+        assert_eq!(
+            (first.is_ok(), second.is_ok(), third.is_ok()),
+            (true, false, true)
+        );
+        ALLOC.fail_after(1);
+        let first = trybox::or_drop(1u8);
+        let second = trybox::or_drop(2u8);
+        let third = trybox::or_drop(3u8);
+        ALLOC.fallback();
+        assert_eq!(
+            (first.is_ok(), second.is_ok(), third.is_ok()),
+            (true, false, false)
+        );
+        Ok(())
+    }));
+    trials.push(Trial::test("testing-random", || {
+        fn run(seed: u64) -> [bool; 64] {
+            let mut res = [false; 64];
+            ALLOC.fail_randomly(0.5, seed, 2);
+            for it in &mut res {
+                *it = trybox::or_drop([0u8; 2]).is_ok();
+            }
+            let small = trybox::or_drop(0u8).is_ok();
+            ALLOC.fallback();
+            assert!(small);
+            res
+        }
+        let (a, b) = (run(1), run(1));
+        assert_eq!(a, b);
+        assert_ne!(a, run(2));
+        let failures = a.iter().filter(|it| !**it).count();
+        assert!((16..48).contains(&failures), "{failures}");
+        Ok(())
+    }));
+    trials.push(Trial::test("testing-thread-guard", || {
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+        let other = std::thread::spawn({
+            let barrier = barrier.clone();
+            move || {
+                barrier.wait();
+                let res = trybox::or_drop(1u8).is_ok();
+                barrier.wait();
+                res
+            }
+        });
+        let guard = trybox::testing::fail_thread_allocations();
+        barrier.wait();
+        let res = trybox::or_drop(1u8);
+        barrier.wait();
+        drop(guard);
+        assert!(res.is_err());
+        assert!(other.join().unwrap());
+        let guard = trybox::testing::fail_allocations();
+        let res = trybox::or_drop(1u8);
+        drop(guard);
+        assert!(res.is_err());
+        assert!(trybox::or_drop(1u8).is_ok());
+        Ok(())
+    }));
+    trials.push(Trial::test("testing-record", || {
+        use trybox::testing::{assert_allocations, assert_no_alloc, record};
+        let e = fail_alloc([0u8; 2500]);
+        assert_no_alloc(|| write!(Buf::default(), "{e:#} {e:?}").unwrap());
+        let (_, recording) = record(|| {
+            let _a = trybox::or_drop(1u8);
+            let _b = trybox::or_drop([0u64; 4]);
+        });
+        assert_eq!(recording.count(), 2);
+        assert_eq!(recording.bytes(), 33);
+        assert_eq!(
+            recording.layouts(),
+            [Layout::new::<u8>(), Layout::new::<[u64; 4]>()]
+        );
+        // Quieten the expected panic.
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|_| {}));
+        let res = std::panic::catch_unwind(|| assert_allocations(1, || (Box::new(1), Box::new(2))));
+        std::panic::set_hook(hook);
+        assert!(res.is_err());
+        Ok(())
+    }));
+    trials.push(Trial::test("testing-oom-test", || {
+        use std::cell::Cell;
+        let runs = Cell::new(0);
+        trybox::testing::oom_test(|| {
+            runs.set(runs.get() + 1);
+            let _a = trybox::or_drop(1u8);
+            let _b = trybox::or_drop(2u8);
+        });
+        assert_eq!(runs.get(), 3);
+        // Quieten the expected panic.
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|_| {}));
+        let res = std::panic::catch_unwind(|| {
+            trybox::testing::oom_test(|| {
+                if let Ok(a) = trybox::or_drop(1u8) {
+                    if trybox::or_drop(2u8).is_err() {
+                        std::mem::forget(a)
+                    }
+                }
+            })
+        });
+        std::panic::set_hook(hook);
+        let message = *res.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            message,
+            "oom_test leaked 1 bytes in 1 allocations with allocation 2 failing"
+        );
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|_| {}));
+        let res = std::panic::catch_unwind(|| {
+            trybox::testing::oom_test(|| {
+                trybox::or_drop(1u8).unwrap();
+            })
+        });
+        std::panic::set_hook(hook);
+        let message = *res.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.starts_with("oom_test panicked with allocation 1 failing: "));
+        Ok(())
+    }));
+    trials.push(Trial::test("testing-replay", || {
+        use trybox::testing::{record, replay};
+        let run = || [0u8, 1, 2, 3, 4].map(|it| trybox::or_drop(it).is_ok());
+        ALLOC.fail_randomly(0.5, 1, 0);
+        let (expected, recording) = record(run);
+        ALLOC.fallback();
+        assert_eq!(
+            recording.failures(),
+            expected
+                .iter()
+                .enumerate()
+                .filter(|(_, ok)| !**ok)
+                .map(|(ix, _)| ix)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(replay(recording.failures(), run), expected);
+        assert_eq!(replay(&[0, 3], run), [false, true, true, false, true]);
+        Ok(())
+    }));
+    trials.push(Trial::test("testing-leaks", || {
+        use trybox::testing::{assert_no_leaks, leak_check};
+        assert_no_leaks(|| drop(trybox::or_drop([0u8; 16])));
+        let check = leak_check();
+        let leaked = Box::leak(trybox::or_drop([0u8; 16]).unwrap());
+        let report = check.leaked();
+        assert_eq!((report.bytes, report.allocations), (16, 1));
+        // SAFETY: the box was leaked above, and is no longer used.
+        drop(unsafe { Box::from_raw(leaked) });
+        drop(check);
+        // Quieten the expected panic.
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|_| {}));
+        let res = std::panic::catch_unwind(|| {
+            assert_no_leaks(|| {
+                std::mem::forget(trybox::or_drop(1u8));
+            })
+        });
+        std::panic::set_hook(hook);
+        assert!(res.is_err());
+        Ok(())
+    }));
+    trials.push(Trial::test("clone-box", || {
+        let sized = trybox::or_drop([1u8; 64]).unwrap();
+        assert_eq!(trybox::clone_box(&sized).unwrap(), sized);
+        let slice = trybox::try_boxed_slice![String::from("a"); 2].unwrap();
+        assert_eq!(trybox::clone_box(&slice).unwrap(), slice);
+        ALLOC.fail();
+        let res = trybox::clone_box(&slice);
+        ALLOC.fallback();
+        assert_eq!(res.unwrap_err().layout(), Layout::new::<[String; 2]>());
+        Ok(())
+    }));
+    trials.push(Trial::test("arena", || {
+        let arena = trybox::arena::Arena::with_capacity(2).unwrap();
+        let first = String::from("first");
+        ALLOC.fail();
+        let first = arena.alloc(first);
+        let second = arena.alloc(String::new());
+        let third = arena.alloc(String::new());
+        ALLOC.fallback();
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert_eq!(third.unwrap_err().into_inner(), "");
+        assert_eq!(arena.len(), 2);
+        second.push_str("second");
+        let fourth = arena.alloc(String::from("fourth")).unwrap();
+        assert_eq!([&**first, second, fourth], ["first", "second", "fourth"]);
+        Ok(())
+    }));
+    trials.push(Trial::test("slab", || {
+        let mut slab = trybox::slab::Slab::with_capacity(1).unwrap();
+        let first = slab.try_insert(1).unwrap();
+        ALLOC.fail();
+        let second = slab.try_insert(2);
+        let removed = slab.remove(first);
+        let third = slab.try_insert(3);
+        ALLOC.fallback();
+        assert_eq!(second.unwrap_err().into_inner(), 2);
+        assert_eq!(removed, Some(1));
+        let third = third.unwrap();
+        assert_eq!(third.index(), first.index());
+        assert_eq!((slab.get(first), slab.get(third)), (None, Some(&3)));
+        let fourth = slab.try_insert(4).unwrap();
+        assert_eq!(slab.capacity(), 4);
+        slab.clear();
+        assert!(!slab.contains(third) && !slab.contains(fourth));
+        assert_eq!(slab.try_insert(5).unwrap().index(), 0);
+        Ok(())
+    }));
+    trials.push(Trial::test("ring", || {
+        let mut ring = trybox::ring::RingBuffer::with_capacity(2).unwrap();
+        ring.push_back(1).unwrap();
+        ring.push_back(2).unwrap();
+        assert_eq!(ring.pop_front(), Some(1));
+        ring.push_back(3).unwrap();
+        ALLOC.fail();
+        let grown = ring.try_grow(1);
+        let pushed = ring.try_push_back(4);
+        ALLOC.fallback();
+        assert!(grown.is_err());
+        assert_eq!(pushed.unwrap_err().into_inner(), 4);
+        assert_eq!(ring.as_slices(), (&[2][..], &[3][..]));
+        ring.try_push_back(4).unwrap();
+        assert_eq!(ring.capacity(), 6);
+        assert_eq!(ring.as_slices(), (&[2, 3, 4][..], &[][..]));
+        Ok(())
+    }));
+    trials.push(Trial::test("try-vec", || {
+        let mut v = trybox::vec::TryVec::try_with_capacity(2).unwrap();
+        v.try_push(1u64).unwrap();
+        ALLOC.fail();
+        v.try_push(2).unwrap();
+        let pushed = v.try_push(3);
+        let reserved = v.try_reserve_exact(1);
+        ALLOC.fallback();
+        assert_eq!(pushed.unwrap_err().into_inner(), 3);
+        assert_eq!(reserved.unwrap_err().layout(), Layout::new::<[u64; 3]>());
+        v.try_reserve_exact(1).unwrap();
+        assert_eq!(v.capacity(), 3);
+        v.try_extend_from_slice(&[3, 4]).unwrap();
+        assert_eq!(v.pop(), Some(4));
+        ALLOC.fail();
+        let boxed = v.try_into_boxed_slice();
+        ALLOC.fallback();
+        assert!(boxed.is_err());
+        Ok(())
+    }));
+    trials.push(Trial::test("raw-typed", || {
+        use trybox::ErrorKind;
+        let ptr = trybox::raw::alloc_array::<u32>(4).unwrap();
+        // SAFETY: allocated with the same layout.
+        unsafe { trybox::raw::dealloc(ptr.cast(), Layout::array::<u32>(4).unwrap()) };
+        let e = trybox::raw::alloc_array::<u32>(usize::MAX).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::LayoutOverflow);
+        ALLOC.fail();
+        let res = trybox::raw::alloc_one::<[u64; 3]>();
+        ALLOC.fallback();
+        assert_eq!(res.unwrap_err().layout(), Layout::new::<[u64; 3]>());
+        Ok(())
+    }));
+    trials.push(Trial::test("pin-slice", || {
+        let pinned = trybox::pin_from_copy(&[1u16, 2, 3]).unwrap();
+        assert_eq!(*pinned, [1, 2, 3]);
+        ALLOC.fail();
+        let copied = trybox::pin_from_copy(&[0u64; 8]);
+        let built = trybox::pin_slice(2, |_| 0u8);
+        ALLOC.fallback();
+        assert_eq!(copied.unwrap_err().layout(), Layout::new::<[u64; 8]>());
+        assert!(built.is_err());
+        Ok(())
+    }));
+    trials.push(Trial::test("io-buffer", || {
+        let mut reader: &[u8] = &[7; 100];
+        let mut buf = trybox::io::Buffer::with_capacity(64).unwrap();
+        assert_eq!(buf.read_from(&mut reader).unwrap(), 64);
+        ALLOC.fail();
+        let res = buf.read_from(&mut reader);
+        ALLOC.fallback();
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::OutOfMemory);
+        assert_eq!(buf.read_from(&mut reader).unwrap(), 36);
+        assert_eq!(buf.filled(), &[7; 100][..]);
+        buf.consume(90);
+        buf.unfilled_mut()[0] = 8;
+        buf.advance(1);
+        assert_eq!(buf.filled(), &[7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 8][..]);
+        Ok(())
+    }));
+    trials.push(Trial::test("heap-chain", || {
+        use std::alloc::GlobalAlloc;
+        use trybox::heap::{self, Chain, Heap};
+        /// Counts the bytes outstanding.
+        struct Secondary(AtomicUsize);
+        unsafe impl GlobalAlloc for Secondary {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                self.0.fetch_add(layout.size(), Ordering::Relaxed);
+                System.alloc(layout)
+            }
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+                System.dealloc(ptr, layout)
+            }
+        }
+        static SECONDARY: Secondary = Secondary(AtomicUsize::new(0));
+        static CHAIN: Chain = Chain(&[&SECONDARY]);
+        static REPORTED: AtomicUsize = AtomicUsize::new(0);
+        trybox::set_oom_hook(|_| {
+            REPORTED.fetch_add(1, Ordering::Relaxed);
+        });
+        ALLOC.fail();
+        assert!(heap::new([1u8; 16]).is_err());
+        heap::set_chain(&CHAIN);
+        let res = heap::new([2u8; 16]);
+        ALLOC.fallback();
+        trybox::take_oom_hook();
+        // Only the failure without a fallback is reported.
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 1);
+        let boxed = res.unwrap();
+        assert_eq!(boxed.heap(), Heap::Secondary(0));
+        assert_eq!(*boxed, [2; 16]);
+        assert_eq!(SECONDARY.0.load(Ordering::Relaxed), 16);
+        // Still freed to the secondary heap, after the chain is removed.
+        heap::take_chain();
+        let boxed = boxed.into_box().unwrap_err();
+        drop(boxed);
+        assert_eq!(SECONDARY.0.load(Ordering::Relaxed), 0);
+        let global = heap::new([3u8; 16]).unwrap();
+        assert_eq!(global.heap(), Heap::Global);
+        assert_eq!(*global.into_box().unwrap(), [3; 16]);
+        Ok(())
+    }));
+    trials.push(Trial::test("intern", || {
+        use trybox::intern::Interner;
+        let mut interner = Interner::new();
+        let words = (0..100).map(|it| it.to_string()).collect::<Vec<_>>();
+        let symbols = words
+            .iter()
+            .map(|it| interner.try_intern(it).unwrap())
+            .collect::<Vec<_>>();
+        ALLOC.fail();
+        // Already interned, so no allocation is needed.
+        let again = interner.try_intern("42");
+        let res = interner.try_intern("new");
+        ALLOC.fallback();
+        assert_eq!(again.unwrap(), symbols[42]);
+        assert!(res.is_err());
+        assert_eq!(interner.len(), 100);
+        assert_eq!(interner.get("new"), None);
+        for (word, symbol) in words.iter().zip(&symbols) {
+            assert_eq!(interner.resolve(*symbol), Some(&**word));
+        }
+        assert_eq!(interner.try_intern("new").unwrap().index(), 100);
+        Ok(())
+    }));
+    trials.push(Trial::test("fallback-unreported", || {
+        static REPORTED: AtomicUsize = AtomicUsize::new(0);
+        trybox::set_oom_hook(|_| {
+            REPORTED.fetch_add(1, Ordering::Relaxed);
+        });
+        ALLOC.fail_larger_than(4096);
+        let segmented = trybox::segmented::Buffer::zeroed(10_000);
+        let probed = trybox::probe::largest_slice(10_000, 100);
+        ALLOC.fallback();
+        #[cfg(all(feature = "mmap", unix))]
+        {
+            ALLOC.fail();
+            let mapped = trybox::mapped::new_slice(1 << 20);
+            ALLOC.fallback();
+            assert!(mapped.is_ok());
+        }
+        assert!(segmented.is_ok() && probed.is_ok());
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 0);
+        // When every attempt fails, only one failure is reported.
+        ALLOC.fail();
+        let res = trybox::segmented::Buffer::zeroed(10_000);
+        ALLOC.fallback();
+        trybox::take_oom_hook();
+        assert_eq!(res.unwrap_err().layout(), Layout::new::<[u8; 10_000]>());
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 1);
+        Ok(())
+    }));
+    trials.push(Trial::test("growth-fallback-unreported", || {
+        static REPORTED: AtomicUsize = AtomicUsize::new(0);
+        trybox::set_oom_hook(|_| {
+            REPORTED.fetch_add(1, Ordering::Relaxed);
+        });
+        // Each container is full, and can grow by one, but not double.
+        let mut vec = trybox::vec::TryVec::<u8>::try_with_capacity(100).unwrap();
+        let mut string = trybox::string::TryString::try_with_capacity(100).unwrap();
+        let mut ring = trybox::ring::RingBuffer::<u8>::with_capacity(100).unwrap();
+        for _ in 0..100 {
+            vec.try_push(0).unwrap();
+            ring.push_back(0).unwrap();
+        }
+        while string.len() < string.capacity() {
+            string.try_push('a').unwrap();
+        }
+        ALLOC.fail_larger_than(150);
+        let pushed = (vec.try_push(0), string.try_push('a'), ring.try_push_back(0));
+        ALLOC.fallback();
+        assert!(pushed.0.is_ok() && pushed.1.is_ok() && pushed.2.is_ok());
+        let mut slab = trybox::slab::Slab::<[u8; 64]>::with_capacity(16).unwrap();
+        for _ in 0..16 {
+            slab.try_insert([0; 64]).unwrap();
+        }
+        ALLOC.fail_larger_than(1800);
+        let inserted = slab.try_insert([0; 64]);
+        ALLOC.fallback();
+        assert!(inserted.is_ok());
+        let arena = trybox::arena::Arena::<u64>::with_capacity(16).unwrap();
+        for _ in 0..16 {
+            arena.alloc(0).unwrap();
+        }
+        ALLOC.fail_larger_than(200);
+        let allocated = arena.alloc(0).is_ok();
+        ALLOC.fallback();
+        assert!(allocated);
+        #[cfg(feature = "std")]
+        {
+            let mut buf = trybox::io::Buffer::with_capacity(100).unwrap();
+            ALLOC.fail_larger_than(150);
+            let reserved = buf.try_reserve(101);
+            ALLOC.fallback();
+            assert!(reserved.is_ok());
+        }
+        trybox::take_oom_hook();
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 0);
+        Ok(())
+    }));
+    #[cfg(all(feature = "mmap", unix))]
+    trials.push(Trial::test("guarded-reported", || {
+        static REPORTED: AtomicUsize = AtomicUsize::new(0);
+        trybox::set_oom_hook(|e| {
+            assert!(e.size() > 100);
+            REPORTED.fetch_add(1, Ordering::Relaxed);
+        });
+        let res = trybox::limit::scoped(0, || trybox::guarded::new([0u8; 100]));
+        trybox::take_oom_hook();
+        assert_eq!(res.unwrap_err().into_inner(), [0u8; 100]);
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 1);
+        Ok(())
+    }));
+    #[cfg(all(feature = "numa", target_os = "linux"))]
+    trials.push(Trial::test("numa-reported", || {
+        static REPORTED: AtomicUsize = AtomicUsize::new(0);
+        trybox::set_oom_hook(|_| {
+            REPORTED.fetch_add(1, Ordering::Relaxed);
+        });
+        let res = trybox::limit::scoped(0, || trybox::numa::new_on_node([0u8; 100], 0));
+        trybox::take_oom_hook();
+        assert!(matches!(res, Err(trybox::numa::Error::Alloc(_))));
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 1);
+        Ok(())
+    }));
+    trials.push(Trial::test("message-fn-with-payload", || {
+        use std::fmt;
+        fn short(info: &trybox::Info, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "oom {}", info.layout.size())
+        }
+        trybox::set_message_fn(short);
+        let e = trybox::ErrorWith([1u8, 2]);
+        let rendered = (
+            format!("{}", e.with_payload()),
+            format!("{:>8}", e.with_payload()),
+        );
+        trybox::take_message_fn();
+        assert_eq!(rendered.0, "oom 2: [1, 2]");
+        assert_eq!(rendered.1, "   oom 2: [1, 2]");
+        Ok(())
+    }));
+    trials.push(Trial::test("rate-limit", || {
+        use std::{sync::atomic::AtomicUsize, time::Duration};
+        static REPORTED: AtomicUsize = AtomicUsize::new(0);
+        trybox::set_oom_hook_with_rate_limit(
+            |_| {
+                REPORTED.fetch_add(1, Ordering::Relaxed);
+            },
+            Duration::from_secs(3600),
+        );
+        ALLOC.fail();
+        for _ in 0..3 {
+            let _ = trybox::or_drop([0u8; 7]);
+        }
+        let _ = trybox::or_drop([0u16; 7]);
+        ALLOC.fallback();
+        trybox::set_oom_rate_limit(Duration::ZERO);
+        trybox::take_oom_hook();
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 2);
+        Ok(())
+    }));
+    #[cfg(feature = "nightly")]
+    trials.push(Trial::test("arc-pin", || {
+        let shared = trybox::arc::pin([1u8; 64]).unwrap();
+        let clone = shared.clone();
+        assert_eq!(*clone, [1; 64]);
+        ALLOC.fail();
+        let res = trybox::arc::pin([2u8; 64]);
+        ALLOC.fallback();
+        assert_eq!(res.unwrap_err().0, [2; 64]);
+        Ok(())
+    }));
+    #[cfg(feature = "nightly")]
+    trials.push(Trial::test("arc-make-mut", || {
+        let mut config = trybox::arc::new([1u8; 64]).unwrap();
+        let ptr = std::sync::Arc::as_ptr(&config);
+        trybox::arc::try_make_mut(&mut config).unwrap()[0] = 2;
+        assert_eq!(
+            std::sync::Arc::as_ptr(&config),
+            ptr,
+            "unique, so not cloned"
+        );
+        let snapshot = config.clone();
+        ALLOC.fail();
+        let res = trybox::arc::try_make_mut(&mut config).map(|_| ());
+        ALLOC.fallback();
+        assert!(res.is_err());
+        trybox::arc::try_make_mut(&mut config).unwrap()[0] = 3;
+        assert_eq!((snapshot[0], config[0]), (2, 3));
+        Ok(())
+    }));
+    #[cfg(feature = "capi")]
+    trials.push(Trial::test("capi", || {
+        use std::ptr;
+        use trybox::capi::*;
+        let mut ptr = ptr::null_mut();
+        // SAFETY: `ptr` is valid for writes, and freed with the same layout.
+        unsafe {
+            assert_eq!(trybox_alloc(64, 8, &mut ptr), TRYBOX_OK);
+            assert_eq!(ptr as usize % 8, 0);
+            trybox_free(ptr, 64, 8);
+        }
+        // SAFETY: `ptr` is valid for writes.
+        assert_eq!(unsafe { trybox_alloc(64, 3, &mut ptr) }, TRYBOX_ALIGNMENT);
+        ALLOC.fail();
+        // SAFETY: `ptr` is valid for writes.
+        let status = unsafe { trybox_alloc(4096, 8, &mut ptr) };
+        ALLOC.fallback();
+        assert_eq!(status, TRYBOX_EXHAUSTED);
+        let expected = "memory allocation of 4 kibibytes failed";
+        let mut buf = [0x7f; 16];
+        // SAFETY: `buf` is valid for writes of its length.
+        let len = unsafe { trybox_last_error_message(buf.as_mut_ptr().cast(), buf.len()) };
+        assert_eq!(len, expected.len());
+        assert_eq!(&buf[..15], &expected.as_bytes()[..15]);
+        assert_eq!(buf[15], 0);
+        Ok(())
+    }));
+    #[cfg(all(feature = "unix", target_os = "linux"))]
+    trials.push(Trial::test("page-aligned-dont-need", || {
+        let mut buf = trybox::slice::page_aligned((1 << 16) + 10).unwrap();
+        buf.fill(1);
+        buf.advise_dont_need().unwrap();
+        assert!(buf.iter().all(|it| *it == 0));
+        Ok(())
+    }));
+    #[cfg(feature = "secure")]
+    trials.push(Trial::test("secure-reported", || {
+        static REPORTED: AtomicUsize = AtomicUsize::new(0);
+        trybox::set_oom_hook(|e| {
+            assert_eq!(e.layout(), Layout::new::<[u8; 32]>());
+            REPORTED.fetch_add(1, Ordering::Relaxed);
+        });
+        ALLOC.fail();
+        let res = trybox::secure::new([0x42u8; 32]);
+        ALLOC.fallback();
+        trybox::take_oom_hook();
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 1);
+        let e = res.unwrap_err();
+        assert!(format!("{e:?}").starts_with("Alloc(Info {"));
+        assert!(e.to_string().contains("32 bytes"));
+        Ok(())
+    }));
+    #[cfg(all(feature = "secure", feature = "unix", target_os = "linux"))]
+    trials.push(Trial::test("secure-adjacent-unlock", || {
+        fn locked_kb() -> usize {
+            let status = std::fs::read_to_string("/proc/self/status").unwrap();
+            let line = status.lines().find(|it| it.starts_with("VmLck:")).unwrap();
+            line.split_whitespace().nth(1).unwrap().parse().unwrap()
+        }
+        let before = locked_kb();
+        let a = trybox::secure::new([1u8; 16]).unwrap();
+        let b = trybox::secure::new([2u8; 16]).unwrap();
+        let both = locked_kb() - before;
+        // Each secret has its own page.
+        assert!(both > 0 && both % 2 == 0);
+        drop(a);
+        // `b` is still locked.
+        assert_eq!(locked_kb() - before, both / 2);
+        assert_eq!(*b, [2; 16]);
+        drop(b);
+        assert_eq!(locked_kb(), before);
+        Ok(())
+    }));
+    #[cfg(all(feature = "mmap", unix))]
+    trials.push(Trial::test("mapped-fallback", || {
+        ALLOC.fail();
+        let res = trybox::mapped::new_slice(1 << 20);
+        ALLOC.fallback();
+        let buf = res.unwrap();
+        assert_eq!(buf.backing(), trybox::mapped::Backing::Anonymous);
+        assert_eq!(buf.len(), 1 << 20);
+        Ok(())
+    }));
+    #[cfg(all(feature = "mmap", unix))]
+    trials.push(Trial::test("mapped-populate", || {
+        let mut buf = trybox::mapped::new_slice(1 << 20).unwrap();
+        buf[4096] = 1;
+        buf.populate().unwrap();
+        assert_eq!(buf[4096], 1);
+        assert!(buf.iter().filter(|it| **it != 0).eq([&1]));
+        Ok(())
+    }));
+    // Last, since the pressure callback stays registered,
+    // so failed allocations are retried.
+    trials.push(Trial::test("cache-pressure", || {
+        use std::sync::Arc;
+        let cache = Arc::new(trybox::cache::BoxCache::new(3 * 64));
+        assert!(cache.shed_under_pressure());
+        for key in 0..4 {
+            assert!(cache.insert(key, [key as u8; 64]).unwrap().is_none());
+        }
+        // The least recently used entry was evicted.
+        assert_eq!((cache.len(), cache.bytes()), (3, 3 * 64));
+        assert_eq!(cache.get(&0, |it| it[0]), None);
+        assert_eq!(cache.get(&1, |it| it[0]), Some(1));
+        // Fail just the next allocation, so the retry succeeds.
+        ALLOC.fail_nth(0);
+        let res = trybox::or_drop([0u8; 64]);
+        ALLOC.fallback();
+        assert!(res.is_ok());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&2, |it| it[0]), None, "2 was least recently used");
+        Ok(())
+    }));
+    libtest_mimic::run(&args, trials).exit()
 }
 
+#[cfg(not(feature = "strip-type-names"))]
 fn error_message<T: Send + 'static>(name: &str, file: ExpectFile, x: T) -> Trial {
     Trial::test(name, move || {
         let err = fail_alloc(x).to_string();
@@ -52,57 +1297,47 @@ fn error_message<T: Send + 'static>(name: &str, file: ExpectFile, x: T) -> Trial
     })
 }
 
-fn fail_alloc<T>(x: T) -> trybox::Error {
+fn fail_new<T>(x: T) -> trybox::ErrorWith<T> {
     ALLOC.fail();
-    let Err(err) = trybox::or_drop(x) else {
+    let Err(err) = trybox::new(x) else {
         unreachable!("we've made the allocator start failing")
     };
     ALLOC.fallback();
     err
 }
 
-#[derive(Debug, Default)]
-pub struct FailOrFallback<T = std::alloc::System> {
-    fail: AtomicBool,
-    fallback: T,
+fn fail_alloc<T>(x: T) -> trybox::Error {
+    ALLOC.fail();
+    let Err(err) = trybox::or_drop(x) else {
+        unreachable!("we've made the allocator start failing")
+    };
+    ALLOC.fallback();
+    err
 }
 
-impl FailOrFallback {
-    /// See [new](FailOrFallback::new).
-    pub const fn system() -> Self {
-        Self::new(std::alloc::System)
-    }
+/// A fixed-size [`fmt::Write`]r.
+struct Buf {
+    bytes: [u8; 4096],
+    len: usize,
 }
 
-impl<T> FailOrFallback<T> {
-    /// Create a new allocator, which uses the fallback by default.
-    ///
-    /// This behaviour allows e.g the rust runtime to initialize when using
-    /// `#[global_allocator]`
-    pub const fn new(fallback: T) -> Self {
-        FailOrFallback {
-            fail: AtomicBool::new(false),
-            fallback,
+impl Default for Buf {
+    fn default() -> Self {
+        Self {
+            bytes: [0; 4096],
+            len: 0,
         }
     }
-    /// Allocations after this call will always fail.
-    pub fn fail(&self) {
-        self.fail.store(true, Ordering::Release);
-    }
-    /// Allocations after this call will use the fallback allocator.
-    pub fn fallback(&self) {
-        self.fail.store(false, Ordering::Release);
-    }
 }
 
-unsafe impl GlobalAlloc for FailOrFallback {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        match self.fail.load(Ordering::Acquire) {
-            true => ptr::null_mut(),
-            false => self.fallback.alloc(layout),
-        }
-    }
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        self.fallback.dealloc(ptr, layout);
+impl fmt::Write for Buf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let dst = self
+            .bytes
+            .get_mut(self.len..self.len + s.len())
+            .ok_or(fmt::Error)?;
+        dst.copy_from_slice(s.as_bytes());
+        self.len += s.len();
+        Ok(())
     }
 }